@@ -12,8 +12,9 @@ fi
 # XXX: OSX might have to install the linker as well?? Good luck, sorry.
 rustup target add x86_64-unknown-linux-musl
 
-# Build executable
-cargo build --release --target x86_64-unknown-linux-musl
+# Build executable, stamped with the commit for the health check
+GIT_SHA="$(git rev-parse --short HEAD 2>/dev/null || echo unknown)" \
+    cargo build --release --target x86_64-unknown-linux-musl
 
 # Package executable
 cp ./target/x86_64-unknown-linux-musl/release/slack_bot_rust ./target/bootstrap
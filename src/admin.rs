@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, GetItemInput, PutItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::router::{Args, Command};
+use crate::{bot_user_id, delete_message, now, LambdaResult, MessageEvent, MessageSink, ReactionEvent, ReactionItem};
+
+// Who can run admin commands: everyone in ADMIN_USERS, plus whoever they've made admins with "admin add".
+// ADMIN_USERS is how the first admins get in, and can't be undone from chat.
+
+fn is_bootstrap_admin(user: &str) -> bool {
+    std::env::var("ADMIN_USERS")
+        .map(|admins| admins.split(',').any(|admin| admin.trim() == user))
+        .unwrap_or(false)
+}
+
+fn user_key(user: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("user".to_string(), string_attr(user.to_string()));
+    key
+}
+
+pub async fn is_admin(user: &str) -> LambdaResult<bool> {
+    if is_bootstrap_admin(user) {
+        return Ok(true);
+    }
+    let table_name = std::env::var("ADMIN_TABLE")?;
+    let client = dynamo_client();
+    let input = GetItemInput { key: user_key(user), table_name, ..Default::default() };
+    Ok(client.get_item(input).await?.item.is_some())
+}
+
+async fn add_admin(user: &str, added_by: &str) -> LambdaResult<()> {
+    let table_name = std::env::var("ADMIN_TABLE")?;
+    let mut item = user_key(user);
+    item.insert("added_by".to_string(), string_attr(added_by.to_string()));
+    item.insert("added".to_string(), number_attr(now()));
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+async fn remove_admin(user: &str) -> LambdaResult<()> {
+    let table_name = std::env::var("ADMIN_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput { key: user_key(user), table_name, ..Default::default() };
+    client.delete_item(input).await?;
+    Ok(())
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "admin add|remove @someone", "Admins only: make someone an admin, or stop them being one.",
+            r"(?i)^\s*admin\s+(add|remove)\s+((?-i:<@U\w+>))$",
+            |event, args, services| Box::pin(handle_admin_command(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+static USER_ID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^<@(U\w+)>$").unwrap());
+
+async fn handle_admin_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let user = match USER_ID_RE.captures(args.get(2)) {
+        Some(caps) => caps.get(1).unwrap().as_str().to_string(),
+        None => return Ok(()),
+    };
+    match args.get(1).to_lowercase().as_str() {
+        "add" => handle_add_admin(event, sink, &user).await,
+        _ => handle_remove_admin(event, sink, &user).await,
+    }
+}
+
+async fn handle_add_admin(event: &MessageEvent, sink: &dyn MessageSink, user: &str) -> LambdaResult<()> {
+    add_admin(user, &event.user).await?;
+    log::info!("{} made {} an admin", event.user, user);
+    event.reply_privately(sink, &format!("<@{}> is an admin now.", user)).await
+}
+
+async fn handle_remove_admin(event: &MessageEvent, sink: &dyn MessageSink, user: &str) -> LambdaResult<()> {
+    if is_bootstrap_admin(user) {
+        let message = format!("<@{}> is an admin through ADMIN_USERS, so that's where to remove them.", user);
+        return event.reply_privately(sink, &message).await;
+    }
+    remove_admin(user).await?;
+    log::info!("{} removed {} as an admin", event.user, user);
+    event.reply_privately(sink, &format!("<@{}> isn't an admin anymore.", user)).await
+}
+
+// An admin reacting to one of the bot's messages with DELETE_EMOJI (eg "wastebasket") takes it down,
+// for the insults that went too far or came out wrong.
+pub async fn handle_delete_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    let emoji = match std::env::var("DELETE_EMOJI") {
+        Ok(emoji) if !emoji.is_empty() => emoji,
+        _ => return Ok(()),
+    };
+    if event.reaction.split("::").next() != Some(emoji.as_str()) {
+        return Ok(());
+    }
+    let (channel, ts, author) = match (&event.item, &event.item_user) {
+        (ReactionItem::Message { channel, ts }, Some(author)) => (channel, ts, author),
+        _ => return Ok(()),
+    };
+    if bot_user_id().await?.as_deref() != Some(author.as_str()) || !is_admin(&event.user).await? {
+        return Ok(());
+    }
+    log::info!("{} deleted the message at {} in {}", event.user, ts, channel);
+    delete_message(channel, ts).await
+}
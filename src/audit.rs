@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::future::Future;
+use rand::random;
+use rusoto_dynamodb::{AttributeValue, PutRequest, QueryInput, WriteRequest};
+
+use crate::dynamo::{dynamo_client, number_attr, query_all, string_attr};
+use crate::insult::{self, encode_word, PartOfSpeech};
+use crate::router::{Args, Command};
+use crate::{now, reminders, LambdaResult, MessageEvent, MessageSink};
+
+// Every change to the vocabulary, kept in AUDIT_TABLE: who added or removed each word, from where, and when.
+// Items are keyed by the encoded word, eg "clownn", then by when and a random suffix, so a word's history
+// reads back in order.
+
+// More than this and the history is scrolled past rather than read.
+const HISTORY_SHOWN: usize = 20;
+
+tokio::task_local! {
+    // The channel the changes made while handling a message, click, or slash command came from.
+    static CHANNEL: Option<String>;
+}
+
+// Runs `future` on behalf of `channel`, so the changes it makes are recorded as coming from there.
+pub async fn in_channel<F: Future>(channel: Option<&str>, future: F) -> F::Output {
+    CHANNEL.scope(channel.map(str::to_string), future).await
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "audit <word>", "Admins only: who added and removed a word, where, and when.",
+            r"(?i)^\s*audit\s+(\S+)$",
+            |event, args, services| Box::pin(handle_audit_command(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+fn change_item(action: &str, word: &str, user: &str, at: u64) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("word".to_string(), string_attr(word.to_string()));
+    item.insert("id".to_string(), string_attr(format!("{:010}-{:08x}", at, random::<u32>())));
+    item.insert("action".to_string(), string_attr(action.to_string()));
+    item.insert("user".to_string(), string_attr(user.to_string()));
+    if let Ok(Some(channel)) = CHANNEL.try_with(Clone::clone) {
+        item.insert("channel".to_string(), string_attr(channel));
+    }
+    item.insert("at".to_string(), number_attr(at));
+    item
+}
+
+// Records that `user` did `action`, eg "added", to each of the encoded `words`.
+// Auditing is best effort: the change has already been made by the time it's recorded.
+pub async fn record(action: &str, words: &[String], user: &str) {
+    let table_name = match std::env::var("AUDIT_TABLE") {
+        Ok(table_name) if !table_name.is_empty() => table_name,
+        _ => return,
+    };
+    let at = now();
+    let puts = words.iter()
+        .map(|word| WriteRequest {
+            put_request: Some(PutRequest { item: change_item(action, word, user, at) }),
+            ..Default::default()
+        })
+        .collect();
+    if let Err(e) = insult::batch_write_to(table_name, puts).await {
+        log::error!("Error auditing {} words: {}", action, e);
+    }
+}
+
+async fn history(word: &str) -> LambdaResult<Vec<HashMap<String, AttributeValue>>> {
+    let table_name = std::env::var("AUDIT_TABLE")?;
+    let input = QueryInput {
+        table_name,
+        key_condition_expression: Some("#word = :word".to_string()),
+        expression_attribute_names: Some(std::iter::once(("#word".to_string(), "word".to_string())).collect()),
+        expression_attribute_values: Some(std::iter::once((":word".to_string(), string_attr(word.to_string()))).collect()),
+        ..Default::default()
+    };
+    Ok(query_all(&dynamo_client(), input).await?)
+}
+
+fn describe(item: &HashMap<String, AttributeValue>) -> Option<String> {
+    let s = |name: &str| item.get(name).and_then(|attr| attr.s.clone());
+    let at = item.get("at").and_then(|attr| attr.n.as_ref()?.parse().ok())?;
+    let mut line = format!("• {} by <@{}>", s("action")?, s("user")?);
+    if let Some(channel) = s("channel") {
+        line.push_str(&format!(" in <#{}>", channel));
+    }
+    line.push_str(&format!(" on {}", reminders::format_time(at)));
+    Some(line)
+}
+
+async fn handle_audit_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let word = args.get(1).to_lowercase();
+    let mut lines = Vec::new();
+    for pos in &PartOfSpeech::ALL {
+        let items = history(&encode_word(pos, word.clone())).await?;
+        let skipped = items.len().saturating_sub(HISTORY_SHOWN);
+        if items.is_empty() {
+            continue;
+        }
+        lines.push(format!("*{}* ({}):", word, pos.name()));
+        if skipped > 0 {
+            lines.push(format!("_…{} older changes_", skipped));
+        }
+        lines.extend(items.iter().skip(skipped).filter_map(describe));
+    }
+    if lines.is_empty() {
+        return event.reply_privately(sink, &format!("I've no record of \"{}\".", word)).await;
+    }
+    event.reply_privately(sink, &lines.join("\n")).await
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use rusoto_core::RusotoError;
+use rusoto_s3::{GetObjectError, GetObjectRequest, PutObjectRequest, S3Client, S3};
+use tokio::io::AsyncReadExt;
+
+use crate::dynamo::aws_region;
+use crate::error::BotError;
+use crate::insult::{self, Vocabulary};
+use crate::router::{Args, Command};
+use crate::{upload_file, LambdaResult, MessageEvent, MessageSink};
+
+fn backup_key(time: DateTime<Utc>) -> String {
+    format!("vocabulary/{}.json", time.format("%Y-%m-%dT%H-%M-%SZ"))
+}
+
+async fn put_backup(client: &impl S3, bucket: String, key: String, body: Vec<u8>) -> LambdaResult<()> {
+    let request = PutObjectRequest {
+        bucket,
+        key,
+        body: Some(body.into()),
+        content_type: Some("application/json".to_string()),
+        ..Default::default()
+    };
+    client.put_object(request).await?;
+    Ok(())
+}
+
+// Writes the full vocabulary to a dated object in `BACKUP_BUCKET`.
+pub async fn backup_vocabulary() -> LambdaResult<()> {
+    let bucket = std::env::var("BACKUP_BUCKET")?;
+    let vocabulary = insult::fetch_vocabulary().await?;
+    let body = serde_json::to_vec(&vocabulary)?;
+    let key = backup_key(Utc::now());
+
+    let client = S3Client::new(aws_region());
+    if let Err(e) = put_backup(&client, bucket.clone(), key.clone(), body).await {
+        log::error!("Error backing up vocabulary to s3://{}/{}: {}", bucket, key, e);
+        return Err(e);
+    }
+    log::info!(
+        "Backed up {} nouns and {} adjectives to s3://{}/{}",
+        vocabulary.nouns.len(), vocabulary.adjectives.len(), bucket, key,
+    );
+    Ok(())
+}
+
+// Returns None if there's no backup at `key`.
+async fn get_backup(client: &impl S3, bucket: String, key: String) -> LambdaResult<Option<Vocabulary>> {
+    let request = GetObjectRequest { bucket, key, ..Default::default() };
+    let output = match client.get_object(request).await {
+        Ok(output) => output,
+        Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let body = output.body.ok_or_else(|| BotError::Dynamo("backup object has no body".to_string()))?;
+    let mut bytes = Vec::new();
+    body.into_async_read().read_to_end(&mut bytes).await?;
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "restore <backup> [replace]", "Admins only: load words from a backup.",
+            r"(?i)^\s*restore\s+(\S+)(\s+replace)?(\s+confirm)?$",
+            |event, args, services| Box::pin(handle_restore_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "export words [csv|text]", "Admins only: upload every word I know as a file.",
+            r"(?i)^\s*export\s+words(?:\s+(?:as\s+)?(csv|text))?$",
+            |event, args, _| Box::pin(handle_export_command(event, args)),
+        ).admin_only(),
+    ]
+}
+
+// One "noun,clown" line per word, which is also what importing reads.
+fn to_csv(vocabulary: &Vocabulary) -> String {
+    let lines = vocabulary.nouns.iter().map(|word| ("noun", word))
+        .chain(vocabulary.adjectives.iter().map(|word| ("adjective", word)))
+        .chain(vocabulary.emoji.iter().map(|word| ("emoji", word)))
+        .map(|(pos, word)| format!("{},{}\n", pos, word));
+    lines.collect()
+}
+
+// Each part of speech under its own heading, for reading.
+fn to_text(vocabulary: &Vocabulary) -> String {
+    let sections = [("Nouns", &vocabulary.nouns), ("Adjectives", &vocabulary.adjectives), ("Emoji", &vocabulary.emoji)];
+    let sections: Vec<_> = sections.iter()
+        .filter(|(_, words)| !words.is_empty())
+        .map(|(heading, words)| {
+            let mut words = words.to_vec();
+            words.sort();
+            format!("{}\n{}\n", heading, words.join("\n"))
+        })
+        .collect();
+    sections.join("\n")
+}
+
+async fn handle_export_command(event: &MessageEvent, args: Args) -> LambdaResult<()> {
+    let vocabulary = insult::fetch_vocabulary().await?;
+    let (filename, contents) = if args.get(1).eq_ignore_ascii_case("text") {
+        ("words.txt", to_text(&vocabulary))
+    } else {
+        ("words.csv", to_csv(&vocabulary))
+    };
+    let comment = format!(
+        "{} nouns, {} adjectives, and {} emoji.",
+        vocabulary.nouns.len(), vocabulary.adjectives.len(), vocabulary.emoji.len(),
+    );
+    let thread_ts = event.reply_options().thread_ts;
+    upload_file(&event.channel, thread_ts.as_deref(), filename, contents.into_bytes(), &comment).await
+}
+
+async fn handle_restore_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_restore(event, sink, args.get(1), args.has(2), args.has(3)).await
+}
+
+// Restores the backup at `key`. With `replace` the existing words are deleted first,
+// which is destructive enough that we make the admin say so twice.
+async fn handle_restore(
+    event: &MessageEvent, sink: &dyn MessageSink, key: &str, replace: bool, confirmed: bool,
+) -> LambdaResult<()> {
+    let bucket = std::env::var("BACKUP_BUCKET")?;
+    let client = S3Client::new(aws_region());
+    let vocabulary = match get_backup(&client, bucket, key.to_string()).await? {
+        Some(vocabulary) => vocabulary,
+        None => return event.reply(sink, &format!("There's no backup at `{}`.", key)).await,
+    };
+    let (nouns, adjectives) = (vocabulary.nouns.len(), vocabulary.adjectives.len());
+
+    if replace && !confirmed {
+        let message = format!(
+            "This will delete every word I know and replace them with the {} nouns and {} adjectives in `{}`. \
+            Say `restore {} replace confirm` if you're sure.",
+            nouns, adjectives, key, key,
+        );
+        return event.reply(sink, &message).await;
+    }
+    let count = insult::restore_vocabulary(vocabulary, replace, &event.user).await?;
+    log::info!("Restored {} words from backup {} (replace: {})", count, key, replace);
+    let message = if replace {
+        format!("Restored {} nouns and {} adjectives from `{}`.", nouns, adjectives, key)
+    } else {
+        format!("Restored {} words from `{}`, and left the ones I already had as they were.", count, key)
+    };
+    event.reply(sink, &message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rusoto_core::Region;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher};
+
+    use super::*;
+    use crate::dynamo::mock::{recording, Requests};
+
+    fn s3_client(dispatcher: MockRequestDispatcher) -> S3Client {
+        S3Client::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1)
+    }
+
+    #[tokio::test]
+    async fn get_backup_reads_words_and_details() {
+        let body = r#"{
+            "nouns": ["clown"],
+            "adjectives": ["smelly"],
+            "details": { "clownn": { "added_by": { "S": "U1" }, "tags": { "SS": ["work"] } } }
+        }"#;
+        let client = s3_client(MockRequestDispatcher::with_status(200).with_body(body));
+        let vocabulary = get_backup(&client, "bucket".to_string(), "key".to_string()).await.unwrap().unwrap();
+        assert_eq!(vocabulary.nouns, ["clown"]);
+        assert!(vocabulary.emoji.is_empty());
+        let details = &vocabulary.details["clownn"];
+        assert_eq!(details["added_by"].s.as_deref(), Some("U1"));
+        assert_eq!(details["tags"].ss, Some(vec!["work".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn get_backup_without_the_object_is_none() {
+        let body = "<Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message></Error>";
+        let client = s3_client(MockRequestDispatcher::with_status(404).with_body(body));
+        assert!(get_backup(&client, "bucket".to_string(), "key".to_string()).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn backup_keys_are_dated() {
+        let time = Utc.ymd(2021, 3, 4).and_hms(5, 6, 7);
+        assert_eq!(backup_key(time), "vocabulary/2021-03-04T05-06-07Z.json");
+    }
+
+    #[tokio::test]
+    async fn put_backup_writes_the_key() {
+        let sent = Requests::default();
+        let dispatcher = recording(MockRequestDispatcher::with_status(200), &sent);
+        let key = backup_key(Utc.ymd(2021, 3, 4).and_hms(5, 6, 7));
+        put_backup(&s3_client(dispatcher), "bucket".to_string(), key, b"{}".to_vec()).await.unwrap();
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].method, "PUT");
+        assert_eq!(sent[0].path, "/bucket/vocabulary/2021-03-04T05-06-07Z.json");
+    }
+}
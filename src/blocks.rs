@@ -0,0 +1,122 @@
+use serde::Serialize;
+use serde_json::Value;
+
+// Just enough of Block Kit for our messages.
+// https://api.slack.com/reference/block-kit/blocks
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Header { text: Text },
+    Section {
+        text: Text,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<Text>,
+    },
+    Divider,
+    Context { elements: Vec<Text> },
+    Actions { elements: Vec<Button> },
+    // Only allowed in modals.
+    Input { block_id: String, label: Text, element: Input },
+}
+
+impl Block {
+    pub fn header(text: &str) -> Self {
+        Block::Header { text: Text::plain(text) }
+    }
+
+    pub fn section(markdown: &str) -> Self {
+        Block::Section { text: Text::markdown(markdown), fields: Vec::new() }
+    }
+
+    // Slack lays `fields` out in two columns under the text.
+    pub fn section_with_fields(markdown: &str, fields: &[&str]) -> Self {
+        let fields = fields.iter().map(|field| Text::markdown(field)).collect();
+        Block::Section { text: Text::markdown(markdown), fields }
+    }
+
+    pub fn divider() -> Self {
+        Block::Divider
+    }
+
+    // Small grey print.
+    pub fn context(markdown: &str) -> Self {
+        Block::Context { elements: vec![Text::markdown(markdown)] }
+    }
+
+    pub fn actions(buttons: Vec<Button>) -> Self {
+        Block::Actions { elements: buttons }
+    }
+
+    // What's entered comes back under `block_id` when the modal is submitted.
+    pub fn input(block_id: &str, label: &str, element: Input) -> Self {
+        Block::Input { block_id: block_id.to_string(), label: Text::plain(label), element }
+    }
+}
+
+// https://api.slack.com/reference/block-kit/composition-objects#text
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Text {
+    Mrkdwn { text: String },
+    PlainText { text: String },
+}
+
+impl Text {
+    pub fn markdown(text: &str) -> Self {
+        Text::Mrkdwn { text: text.to_string() }
+    }
+
+    pub fn plain(text: &str) -> Self {
+        Text::PlainText { text: text.to_string() }
+    }
+}
+
+// https://api.slack.com/reference/block-kit/block-elements#button
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "button")]
+pub struct Button {
+    text: Text,
+    action_id: String,
+    value: String,
+}
+
+impl Button {
+    // `value` comes back to us along with `action_id` when the button is clicked.
+    pub fn new(label: &str, action_id: &str, value: &str) -> Self {
+        Button { text: Text::plain(label), action_id: action_id.to_string(), value: value.to_string() }
+    }
+}
+
+// https://api.slack.com/reference/block-kit/block-elements#input
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    PlainTextInput { action_id: String, max_length: usize },
+    StaticSelect { action_id: String, options: Vec<SelectOption> },
+}
+
+impl Input {
+    pub fn text(action_id: &str, max_length: usize) -> Self {
+        Input::PlainTextInput { action_id: action_id.to_string(), max_length }
+    }
+
+    // `options` are (label, value) pairs.
+    pub fn select(action_id: &str, options: &[(&str, &str)]) -> Self {
+        let options = options.iter()
+            .map(|(label, value)| SelectOption { text: Text::plain(label), value: value.to_string() })
+            .collect();
+        Input::StaticSelect { action_id: action_id.to_string(), options }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SelectOption {
+    text: Text,
+    value: String,
+}
+
+// The blocks as the JSON array the Web API takes.
+pub fn to_value(blocks: &[Block]) -> Value {
+    serde_json::to_value(blocks).unwrap_or_default()
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, GetItemInput, UpdateItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::router::{Args, Command};
+use crate::{grammar, now, LambdaResult, MessageEvent, MessageSink};
+
+// Per-channel settings admins change with "config set", eg to keep insults out of #general.
+// Channels nobody has configured get the defaults, so everything is on.
+
+// What a command needs switched on in the channel to run.
+#[derive(Clone, Copy)]
+pub enum Feature {
+    Insults,
+    AddWord,
+}
+
+#[derive(Clone)]
+pub struct ChannelConfig {
+    pub insults: bool,
+    pub add_word: bool,
+    // Seconds between insults in the channel, or 0 for as many as people like.
+    pub cooldown_secs: u64,
+    // What to greet people who join with, `{user}` being them. "insult" insults them instead.
+    pub welcome: Option<String>,
+    // Whether only the person joining sees the welcome.
+    pub welcome_privately: bool,
+    // Whether commands that just succeed get a ✅ on the message rather than a reply.
+    pub reactions: bool,
+    // The language insults are put together in, eg "es", if not LANG's.
+    pub lang: Option<String>,
+    // "emoji" to insult in strings of emoji rather than words.
+    pub theme: Option<String>,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            insults: true, add_word: true, cooldown_secs: 0, welcome: None, welcome_privately: false, reactions: false,
+            lang: None, theme: None,
+        }
+    }
+}
+
+impl ChannelConfig {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Self {
+        let defaults = ChannelConfig::default();
+        let flag = |name: &str, default: bool| item.get(name).and_then(|attr| attr.bool).unwrap_or(default);
+        ChannelConfig {
+            insults: flag("insults", defaults.insults),
+            add_word: flag("add_word", defaults.add_word),
+            cooldown_secs: item.get("cooldown_secs")
+                .and_then(|attr| attr.n.as_ref()?.parse().ok())
+                .unwrap_or(defaults.cooldown_secs),
+            // Switching the welcome off stores it empty.
+            welcome: item.get("welcome").and_then(|attr| attr.s.clone()).filter(|welcome| !welcome.is_empty()),
+            welcome_privately: flag("welcome_privately", defaults.welcome_privately),
+            reactions: flag("reactions", defaults.reactions),
+            // Switching back to the default stores it empty, like the welcome.
+            lang: item.get("lang").and_then(|attr| attr.s.clone()).filter(|lang| !lang.is_empty()),
+            theme: item.get("theme").and_then(|attr| attr.s.clone()).filter(|theme| !theme.is_empty()),
+        }
+    }
+
+    pub fn emoji_theme(&self) -> bool {
+        self.theme.as_deref() == Some("emoji")
+    }
+
+    pub fn allows(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Insults => self.insults,
+            Feature::AddWord => self.add_word,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        format!(
+            "insults: {}\nadd_word: {}\ncooldown: {}s\nwelcome: {}\nwelcome_privately: {}\nreactions: {}\nlang: {}\ntheme: {}",
+            on_off(self.insults), on_off(self.add_word), self.cooldown_secs,
+            self.welcome.as_deref().unwrap_or("off"), on_off(self.welcome_privately), on_off(self.reactions),
+            grammar::for_code(self.lang.as_deref()).code(), self.theme.as_deref().unwrap_or("words"),
+        )
+    }
+}
+
+// Every message checks its channel's config, so each container holds on to what it read for a bit.
+// Changes made from another container can take this long to show up.
+const CACHE_SECS: u64 = 60;
+
+static CACHE: Lazy<Mutex<HashMap<String, (u64, ChannelConfig)>>> = Lazy::new(Default::default);
+
+fn cached(channel: &str) -> Option<ChannelConfig> {
+    let cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    match cache.get(channel) {
+        Some((read, config)) if read + CACHE_SECS > now() => Some(config.clone()),
+        _ => None,
+    }
+}
+
+fn cache(channel: &str, config: &ChannelConfig) {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.insert(channel.to_string(), (now(), config.clone()));
+}
+
+// Gives `channel` a config without reading it from dynamo.
+#[cfg(test)]
+pub fn prime(channel: &str, config: ChannelConfig) {
+    cache(channel, &config);
+}
+
+fn channel_key(channel: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("channel".to_string(), string_attr(channel.to_string()));
+    key
+}
+
+pub async fn get(channel: &str) -> LambdaResult<ChannelConfig> {
+    if let Some(config) = cached(channel) {
+        return Ok(config);
+    }
+    let table_name = std::env::var("CHANNEL_CONFIG_TABLE")?;
+    let client = dynamo_client();
+    let input = GetItemInput { key: channel_key(channel), table_name, ..Default::default() };
+    let config = match client.get_item(input).await?.item {
+        Some(item) => ChannelConfig::from_item(&item),
+        None => ChannelConfig::default(),
+    };
+    cache(channel, &config);
+    Ok(config)
+}
+
+// Stores one setting, returning the channel's whole config afterwards.
+async fn set(channel: &str, name: &str, value: AttributeValue) -> LambdaResult<ChannelConfig> {
+    let table_name = std::env::var("CHANNEL_CONFIG_TABLE")?;
+    let mut names = HashMap::new();
+    names.insert("#setting".to_string(), name.to_string());
+    let mut values = HashMap::new();
+    values.insert(":value".to_string(), value);
+
+    let client = dynamo_client();
+    let input = UpdateItemInput {
+        key: channel_key(channel),
+        table_name,
+        update_expression: Some("SET #setting = :value".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("ALL_NEW".to_string()),
+        ..Default::default()
+    };
+    let output = client.update_item(input).await?;
+    let config = output.attributes.as_ref().map(ChannelConfig::from_item).unwrap_or_default();
+    cache(channel, &config);
+    Ok(config)
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "config get", "See this channel's settings.",
+            r"(?i)^\s*config\s+get$",
+            |event, _, services| Box::pin(handle_config_get(event, services.sink)),
+        ),
+        Command::new(
+            "config set <setting> <value>",
+            "Admins only: turn insults, add_word, or reactions for acknowledgements on or off here, \
+             or set a cooldown in seconds between insults.",
+            r"(?i)^\s*config\s+set\s+(insults|add_word|cooldown|welcome_privately|reactions)\s+(\w+)$",
+            |event, args, services| Box::pin(handle_config_set_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "config set lang <language>|default",
+            "Admins only: put insults together in another language here, eg `es`, for a vocabulary in it.",
+            r"(?i)^\s*config\s+set\s+lang\s+(\w+)$",
+            |event, args, services| Box::pin(handle_config_set_lang(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "config set theme emoji|default",
+            "Admins only: insult people here in strings of emoji instead of words.",
+            r"(?i)^\s*config\s+set\s+theme\s+(\w+)$",
+            |event, args, services| Box::pin(handle_config_set_theme(event, services.sink, args)),
+        ).admin_only(),
+        Command::verbatim(
+            "config set welcome <message>|insult|off",
+            "Admins only: greet people who join this channel. `{user}` in the message is them.",
+            r"(?i)^\s*config\s+set\s+welcome\s+(.+)$",
+            |event, args, services| Box::pin(handle_config_set_welcome(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+async fn handle_config_set_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_config_set(event, sink, &args.get(1).to_lowercase(), &args.get(2).to_lowercase()).await
+}
+
+async fn handle_config_get(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let config = get(&event.channel).await?;
+    event.reply_privately(sink, &config.describe()).await
+}
+
+async fn handle_config_set(event: &MessageEvent, sink: &dyn MessageSink, name: &str, value: &str) -> LambdaResult<()> {
+    let value = match (name, value) {
+        ("insults" | "add_word" | "welcome_privately" | "reactions", "on" | "true") =>
+            AttributeValue { bool: Some(true), ..Default::default() },
+        ("insults" | "add_word" | "welcome_privately" | "reactions", "off" | "false") =>
+            AttributeValue { bool: Some(false), ..Default::default() },
+        ("cooldown", seconds) => match seconds.parse() {
+            Ok(seconds) => number_attr(seconds),
+            Err(_) => return event.reply_privately(sink, "The cooldown is a number of seconds.").await,
+        },
+        (_, _) => return event.reply_privately(sink, "That's either on or off.").await,
+    };
+    let name = if name == "cooldown" { "cooldown_secs" } else { name };
+    let config = set(&event.channel, name, value).await?;
+    log::info!("{} set {} in {}", event.user, name, event.channel);
+    event.reply_privately(sink, &config.describe()).await
+}
+
+async fn handle_config_set_lang(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let lang = match args.get(1).to_lowercase().as_str() {
+        "default" => String::new(),
+        code => match grammar::by_code(code) {
+            Some(grammar) => grammar.code().to_string(),
+            None => {
+                let message = format!("I only know {}.", grammar::codes().join(", "));
+                return event.reply_privately(sink, &message).await;
+            },
+        },
+    };
+    let config = set(&event.channel, "lang", string_attr(lang)).await?;
+    log::info!("{} set lang in {}", event.user, event.channel);
+    event.reply_privately(sink, &config.describe()).await
+}
+
+async fn handle_config_set_theme(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let theme = match args.get(1).to_lowercase().as_str() {
+        "default" | "words" => "",
+        "emoji" => "emoji",
+        _ => return event.reply_privately(sink, "The theme is either emoji or default.").await,
+    };
+    let config = set(&event.channel, "theme", string_attr(theme.to_string())).await?;
+    log::info!("{} set theme in {}", event.user, event.channel);
+    event.reply_privately(sink, &config.describe()).await
+}
+
+async fn handle_config_set_welcome(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let welcome = args.get(1).trim();
+    let welcome = match welcome.to_lowercase().as_str() {
+        "off" => "",
+        "insult" => "insult",
+        _ => welcome,
+    };
+    let config = set(&event.channel, "welcome", string_attr(welcome.to_string())).await?;
+    log::info!("{} set welcome in {}", event.user, event.channel);
+    event.reply_privately(sink, &config.describe()).await
+}
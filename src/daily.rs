@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, PutItemInput, ScanInput, UpdateItemError, UpdateItemInput};
+
+use crate::config::{self, Feature};
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr};
+use crate::router::{Args, Command};
+use crate::{insult, now, send_message, teams, LambdaResult, MessageEvent, MessageSink};
+
+// A daily insult for channels that ask for one, at a time of day kept in DAILY_INSULT_TABLE.
+// The daily_insults schedule checks every few minutes for any that are due.
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "schedule daily insult #channel HH:MM", "Admins only: insult a channel every day at a time, in UTC.",
+            r"(?i)^\s*schedule\s+daily\s+insult\s+(?-i:<#(C\w+)(?:\|[^>]*)?>)\s+(\d{1,2}):(\d{2})$",
+            |event, args, services| Box::pin(handle_schedule_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "unschedule daily insult #channel", "Admins only: stop a channel's daily insult.",
+            r"(?i)^\s*unschedule\s+daily\s+insult\s+(?-i:<#(C\w+)(?:\|[^>]*)?>)$",
+            |event, args, services| Box::pin(handle_unschedule_command(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+fn channel_key(channel: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("channel".to_string(), string_attr(channel.to_string()));
+    key
+}
+
+// Days since the epoch, which is when each UTC day starts over.
+fn today() -> u64 {
+    now() / DAY_SECS
+}
+
+async fn handle_schedule_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let channel = args.get(1);
+    let (hour, minute): (u64, u64) = (args.get(2).parse().unwrap_or(99), args.get(3).parse().unwrap_or(99));
+    if hour > 23 || minute > 59 {
+        return event.reply_privately(sink, "That's not a time. Try eg `09:00`, in UTC.").await;
+    }
+    let table_name = std::env::var("DAILY_INSULT_TABLE")?;
+    let mut item = channel_key(channel);
+    item.insert("minute_of_day".to_string(), number_attr(hour * 60 + minute));
+    // Scheduling for later today shouldn't wait until tomorrow, and scheduling for earlier shouldn't post right away.
+    let posted_day = if now() % DAY_SECS >= (hour * 60 + minute) * 60 { today() } else { today() - 1 };
+    item.insert("posted_day".to_string(), number_attr(posted_day));
+    if let Some(team_id) = teams::current_team_id() {
+        item.insert("team_id".to_string(), string_attr(team_id));
+    }
+    let client = dynamo_client();
+    client.put_item(PutItemInput { item, table_name, ..Default::default() }).await?;
+    let message = format!("I'll insult <#{}> every day at {:02}:{:02} UTC.", channel, hour, minute);
+    event.reply_privately(sink, &message).await
+}
+
+async fn handle_unschedule_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let table_name = std::env::var("DAILY_INSULT_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput {
+        key: channel_key(args.get(1)),
+        table_name,
+        return_values: Some("ALL_OLD".to_string()),
+        ..Default::default()
+    };
+    let message = match client.delete_item(input).await?.attributes {
+        Some(_) => format!("No more daily insults for <#{}>.", args.get(1)),
+        None => format!("<#{}> didn't have a daily insult.", args.get(1)),
+    };
+    event.reply_privately(sink, &message).await
+}
+
+// Posts every daily insult that's due and hasn't gone out today.
+pub async fn post_due() -> LambdaResult<()> {
+    let table_name = std::env::var("DAILY_INSULT_TABLE")?;
+    let client = dynamo_client();
+    let items = scan_all(&client, ScanInput { table_name: table_name.clone(), ..Default::default() }).await?;
+    let (today, minute_now) = (today(), now() % DAY_SECS / 60);
+    let mut posted = 0;
+    for item in items {
+        let s = |name: &str| item.get(name).and_then(|attr| attr.s.clone());
+        let n = |name: &str| item.get(name).and_then(|attr| attr.n.as_ref()?.parse::<u64>().ok());
+        let (channel, minute_of_day) = match (s("channel"), n("minute_of_day")) {
+            (Some(channel), Some(minute_of_day)) => (channel, minute_of_day),
+            _ => continue,
+        };
+        if n("posted_day").unwrap_or(0) >= today || minute_now < minute_of_day {
+            continue;
+        }
+        // Claimed before posting, so overlapping runs don't both post it.
+        if !claim(&client, &table_name, &channel, today).await? {
+            continue;
+        }
+        let result = teams::scope(s("team_id"), post_daily_insult(&channel)).await;
+        if let Err(e) = result {
+            log::error!("Error posting the daily insult to {}: {}", channel, e);
+            continue;
+        }
+        posted += 1;
+    }
+    log::info!("Posted {} daily insults", posted);
+    Ok(())
+}
+
+async fn claim(client: &impl DynamoDb, table_name: &str, channel: &str, today: u64) -> LambdaResult<bool> {
+    let mut names = HashMap::new();
+    names.insert("#posted_day".to_string(), "posted_day".to_string());
+    let mut values = HashMap::new();
+    values.insert(":today".to_string(), number_attr(today));
+    let input = UpdateItemInput {
+        key: channel_key(channel),
+        table_name: table_name.to_string(),
+        update_expression: Some("SET #posted_day = :today".to_string()),
+        condition_expression: Some("#posted_day < :today".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    match client.update_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn post_daily_insult(channel: &str) -> LambdaResult<()> {
+    if !config::get(channel).await?.allows(Feature::Insults) {
+        return Ok(());
+    }
+    let insult = insult::bare_insult(&insult::DynamoWords, channel).await?;
+    send_message(channel, &format!("Your daily insult: {}", insult)).await
+}
@@ -0,0 +1,174 @@
+use once_cell::sync::Lazy;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use regex::Regex;
+
+use crate::router::{Args, Command};
+use crate::{LambdaResult, MessageEvent, MessageSink};
+
+// For settling things: "roll 3d6+2", "roll d20 with advantage", "flip a coin", "pick tacos, pizza".
+
+// Enough for anything a real game needs, and short enough to fit in a message.
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "roll <dice, eg 3d6+2 or d20 advantage>", "Roll some dice and show the working.",
+            r"(?i)^\s*roll\s+(.+)$",
+            |event, args, services| Box::pin(handle_roll_command(event, services.sink, args)),
+        ),
+        Command::new(
+            "flip a coin", "Heads or tails.",
+            r"(?i)\bflip\s+a\s+coin\b",
+            |event, _, services| Box::pin(handle_flip(event, services.sink)),
+        ),
+        Command::new(
+            "pick <this>, <that> or <the other>", "Choose for you.",
+            r"(?i)^\s*pick\s+(.+)$",
+            |event, args, services| Box::pin(handle_pick_command(event, services.sink, args)),
+        ),
+    ]
+}
+
+#[derive(Debug, PartialEq)]
+enum Term {
+    Dice { count: u32, sides: u32 },
+    Constant(i64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Advantage {
+    Advantage,
+    Disadvantage,
+}
+
+// Terms with their signs, eg 2d8 - 1 is [(1, 2d8), (-1, 1)].
+#[derive(Debug, PartialEq)]
+struct Expression {
+    terms: Vec<(i64, Term)>,
+    advantage: Option<Advantage>,
+}
+
+static ADVANTAGE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\s*(?:with\s+)?\b(advantage|adv|disadvantage|dis)$").unwrap());
+static EXPRESSION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^[+-]?(?:\d*d\d+|\d+)(?:[+-](?:\d*d\d+|\d+))*$").unwrap());
+static TERM_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)([+-]?)(?:(\d*)d(\d+)|(\d+))").unwrap());
+
+fn parse_expression(text: &str) -> Result<Expression, String> {
+    let mut text = text.trim().to_string();
+    let mut advantage = None;
+    if let Some(caps) = ADVANTAGE_RE.captures(&text) {
+        advantage = Some(match caps[1].to_lowercase().as_str() {
+            "advantage" | "adv" => Advantage::Advantage,
+            _ => Advantage::Disadvantage,
+        });
+        text.truncate(caps.get(0).unwrap().start());
+    }
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !EXPRESSION_RE.is_match(&text) {
+        return Err("I can't read that. Try eg `roll 3d6+2` or `roll d20 advantage`.".to_string());
+    }
+
+    let mut terms = Vec::new();
+    let mut total_dice = 0;
+    for caps in TERM_RE.captures_iter(&text) {
+        let sign = if &caps[1] == "-" { -1 } else { 1 };
+        let term = match (caps.get(3), caps.get(4)) {
+            (Some(sides), _) => {
+                let count = match caps.get(2).map(|count| count.as_str()) {
+                    None | Some("") => 1,
+                    Some(count) => count.parse().map_err(|_| "That's too many dice.".to_string())?,
+                };
+                let sides = sides.as_str().parse().unwrap_or(0);
+                if count == 0 || sides == 0 {
+                    return Err("Dice need at least one of them, and at least one side.".to_string());
+                }
+                if sides > MAX_SIDES {
+                    return Err(format!("I don't have any dice with more than {} sides.", MAX_SIDES));
+                }
+                total_dice += count;
+                Term::Dice { count, sides }
+            },
+            (None, Some(constant)) => Term::Constant(constant.as_str().parse().map_err(|_| "That's too big.".to_string())?),
+            _ => continue,
+        };
+        if total_dice > MAX_DICE {
+            return Err(format!("I only have {} dice.", MAX_DICE));
+        }
+        terms.push((sign, term));
+    }
+    if advantage.is_some() {
+        let dice_terms = terms.iter().filter(|(_, term)| matches!(term, Term::Dice { .. })).count();
+        if dice_terms != 1 || !terms.iter().any(|(_, term)| *term == Term::Dice { count: 1, sides: 20 }) {
+            return Err("Advantage only works with a single d20, like `roll d20+5 advantage`.".to_string());
+        }
+    }
+    Ok(Expression { terms, advantage })
+}
+
+// Rolls `expression`, returning the total and the working, eg "[4, 2, 6] + 2".
+fn roll(expression: &Expression, rng: &mut impl Rng) -> (i64, String) {
+    let mut total = 0;
+    let mut working = String::new();
+    for (i, (sign, term)) in expression.terms.iter().enumerate() {
+        let (value, shown) = match term {
+            Term::Constant(constant) => (*constant, constant.to_string()),
+            Term::Dice { count, sides } => match expression.advantage {
+                // The only die, rolled twice. The one that doesn't count is struck through.
+                Some(advantage) => {
+                    let (a, b) = (rng.gen_range(1..=*sides as i64), rng.gen_range(1..=*sides as i64));
+                    let (kept, dropped) = match advantage {
+                        Advantage::Advantage => (a.max(b), a.min(b)),
+                        Advantage::Disadvantage => (a.min(b), a.max(b)),
+                    };
+                    (kept, format!("[{}, ~{}~]", kept, dropped))
+                },
+                None => {
+                    let rolls: Vec<i64> = (0..*count).map(|_| rng.gen_range(1..=*sides as i64)).collect();
+                    let shown: Vec<_> = rolls.iter().map(i64::to_string).collect();
+                    (rolls.iter().sum(), format!("[{}]", shown.join(", ")))
+                },
+            },
+        };
+        total += sign * value;
+        match (i, sign) {
+            (0, -1) => working.push_str(&format!("-{}", shown)),
+            (0, _) => working.push_str(&shown),
+            (_, -1) => working.push_str(&format!(" - {}", shown)),
+            _ => working.push_str(&format!(" + {}", shown)),
+        }
+    }
+    (total, working)
+}
+
+async fn handle_roll_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let expression = match parse_expression(args.get(1)) {
+        Ok(expression) => expression,
+        Err(message) => return event.reply_privately(sink, &message).await,
+    };
+    let (total, working) = roll(&expression, &mut rand::thread_rng());
+    event.reply(sink, &format!("🎲 {} = *{}*", working, total)).await
+}
+
+async fn handle_flip(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let side = if rand::thread_rng().gen_bool(0.5) { "Heads" } else { "Tails" };
+    event.reply(sink, &format!("🪙 {}.", side)).await
+}
+
+static CHOICE_SEPARATOR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\s*,\s*(?:or\s+)?|\s+or\s+").unwrap());
+
+async fn handle_pick_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let choices: Vec<_> = CHOICE_SEPARATOR_RE.split(args.get(1))
+        .map(str::trim)
+        .filter(|choice| !choice.is_empty())
+        .collect();
+    if choices.len() < 2 {
+        let message = "Give me something to choose between, like `pick tacos, pizza or sushi`.";
+        return event.reply_privately(sink, message).await;
+    }
+    let choice = choices.choose(&mut rand::thread_rng()).unwrap();
+    event.reply(sink, &format!("I pick {}.", choice)).await
+}
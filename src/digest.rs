@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::iter;
+
+use crate::blocks::Block;
+use crate::insult::{self, WordRecord};
+use crate::insult_log::{self, LogEntry};
+use crate::{now, send_blocks, LambdaResult};
+
+const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+
+// Posts the week's most insulted, most used words, top contributors, and vocabulary growth
+// to `WEEKLY_DIGEST_CHANNEL`.
+pub async fn post_weekly_digest() -> LambdaResult<()> {
+    let channel = match std::env::var("WEEKLY_DIGEST_CHANNEL") {
+        Ok(channel) if !channel.is_empty() => channel,
+        _ => {
+            log::info!("No WEEKLY_DIGEST_CHANNEL configured, skipping the digest");
+            return Ok(());
+        },
+    };
+    let since = now().saturating_sub(WEEK_SECS);
+    let insults = insult_log::fetch_since(since).await?;
+    let words = insult::fetch_word_records().await?;
+    send_blocks(&channel, "This week in insults", &digest_blocks(&insults, &words, since)).await
+}
+
+// The `limit` most frequent names, most frequent first.
+pub fn top<'a>(names: impl Iterator<Item = &'a str>, limit: usize) -> Vec<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    counts.truncate(limit);
+    counts
+}
+
+fn ranking(title: &str, entries: &[(String, usize)]) -> Block {
+    let mut text = format!("*{}*", title);
+    if entries.is_empty() {
+        text.push_str("\n_Nothing yet._");
+    }
+    for (i, (name, count)) in entries.iter().enumerate() {
+        text.push_str(&format!("\n{}. {} ({})", i + 1, name, count));
+    }
+    Block::section(&text)
+}
+
+fn owned(entries: Vec<(&str, usize)>, fmt: impl Fn(&str) -> String) -> Vec<(String, usize)> {
+    entries.into_iter().map(|(name, count)| (fmt(name), count)).collect()
+}
+
+// Number of new words listed by name in the growth summary.
+const NEW_WORDS_SHOWN: usize = 5;
+
+fn growth(new_words: &[&WordRecord], total: usize) -> String {
+    let mut text = format!("Vocabulary grew by {} to {} words.", new_words.len(), total);
+    if !new_words.is_empty() {
+        let names: Vec<_> = new_words.iter()
+            .take(NEW_WORDS_SHOWN)
+            .map(|w| format!("_{}_ ({})", w.word, w.pos.name()))
+            .collect();
+        text.push_str(&format!(" Fresh: {}", names.join(", ")));
+    }
+    text
+}
+
+fn digest_blocks(insults: &[LogEntry], words: &[WordRecord], since: u64) -> Vec<Block> {
+    let new_words: Vec<_> = words.iter().filter(|w| w.added.is_some_and(|added| added >= since)).collect();
+    let targets = top(insults.iter().filter_map(|i| i.target.as_deref()), 3);
+    let used_words = top(
+        insults.iter().flat_map(|i| i.adjectives.iter().map(String::as_str).chain(iter::once(i.noun.as_str()))),
+        5,
+    );
+    let contributors = top(new_words.iter().filter_map(|w| w.added_by.as_deref()), 3);
+
+    vec![
+        Block::header("📰 This week in insults"),
+        Block::section(&format!("*{}* insults delivered.", insults.len())),
+        ranking("Most insulted", &owned(targets, str::to_string)),
+        ranking("Most used words", &owned(used_words, |word| format!("_{}_", word))),
+        ranking("Top contributors", &owned(contributors, |user| format!("<@{}>", user))),
+        Block::divider(),
+        Block::context(&growth(&new_words, words.len())),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::blocks;
+    use crate::insult::PartOfSpeech;
+
+    fn insult(target: &str, adjective: &str, noun: &str) -> LogEntry {
+        LogEntry { target: Some(target.to_string()), adjectives: vec![adjective.to_string()], noun: noun.to_string() }
+    }
+
+    fn word(pos: PartOfSpeech, word: &str, added_by: &str, added: u64) -> WordRecord {
+        WordRecord { pos, word: word.to_string(), added_by: Some(added_by.to_string()), added: Some(added) }
+    }
+
+    #[test]
+    fn digest_has_each_section() {
+        let insults = [
+            insult("<@U1>", "smelly", "clown"),
+            insult("<@U1>", "smelly", "goblin"),
+            insult("<@U2>", "dumb", "clown"),
+        ];
+        let words = [
+            word(PartOfSpeech::Noun, "clown", "U3", 10),
+            word(PartOfSpeech::Noun, "goblin", "U4", 1_000),
+            word(PartOfSpeech::Adjective, "smelly", "U4", 1_001),
+        ];
+        let blocks = blocks::to_value(&digest_blocks(&insults, &words, 500));
+        let texts: Vec<Value> = blocks.as_array().unwrap().iter()
+            .map(|block| block.get("text").or_else(|| block.pointer("/elements/0")).map_or(Value::Null, |text| text["text"].clone()))
+            .collect();
+        assert_eq!(texts, [
+            json!("📰 This week in insults"),
+            json!("*3* insults delivered."),
+            json!("*Most insulted*\n1. <@U1> (2)\n2. <@U2> (1)"),
+            json!("*Most used words*\n1. _clown_ (2)\n2. _smelly_ (2)\n3. _dumb_ (1)\n4. _goblin_ (1)"),
+            json!("*Top contributors*\n1. <@U4> (2)"),
+            Value::Null,
+            json!("Vocabulary grew by 2 to 3 words. Fresh: _goblin_ (noun), _smelly_ (adjective)"),
+        ]);
+        assert_eq!(blocks[5]["type"], "divider");
+    }
+
+    #[test]
+    fn a_quiet_week_says_so() {
+        let blocks = blocks::to_value(&digest_blocks(&[], &[], 500));
+        assert_eq!(blocks[2]["text"]["text"], "*Most insulted*\n_Nothing yet._");
+        assert_eq!(blocks[6]["elements"][0]["text"], "Vocabulary grew by 0 to 0 words.");
+    }
+}
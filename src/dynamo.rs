@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Instant;
+use rusoto_core::{Region, RusotoError};
+use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, QueryInput, ScanInput};
+use tracing::Instrument;
+
+use crate::error::{BotError, BotResult};
+use crate::{health, metrics};
+
+// The region from AWS_REGION, which Lambda sets for us.
+pub fn aws_region() -> Region {
+    std::env::var("AWS_REGION").ok()
+        .and_then(|name| Region::from_str(&name).ok())
+        .unwrap_or(Region::UsEast1)
+}
+
+// Set DYNAMODB_ENDPOINT to point at something else, like a local DynamoDB.
+fn dynamo_region() -> Region {
+    with_endpoint(aws_region(), std::env::var("DYNAMODB_ENDPOINT").ok())
+}
+
+fn with_endpoint(region: Region, endpoint: Option<String>) -> Region {
+    match endpoint {
+        Some(endpoint) if !endpoint.is_empty() => Region::Custom { name: region.name().to_string(), endpoint },
+        _ => region,
+    }
+}
+
+pub fn dynamo_client() -> DynamoDbClient {
+    DynamoDbClient::new(dynamo_region())
+}
+
+pub fn string_attr(s: String) -> AttributeValue {
+    AttributeValue { s: Some(s), ..Default::default() }
+}
+
+// Dynamo rejects empty sets and sets with duplicates.
+pub fn string_set_attr(ss: Vec<String>) -> AttributeValue {
+    AttributeValue { ss: Some(ss), ..Default::default() }
+}
+
+pub fn number_attr(n: u64) -> AttributeValue {
+    AttributeValue { n: Some(n.to_string()), ..Default::default() }
+}
+
+// Scans and queries are how the words get loaded, so their failures are what "status" reports for dynamo.
+fn record_error<E: std::error::Error + 'static>(e: RusotoError<E>) -> BotError {
+    health::record_error("dynamo", &e);
+    e.into()
+}
+
+// Scans every page of `input`, since a single scan stops after 1MB.
+pub async fn scan_all(client: &impl DynamoDb, mut input: ScanInput) -> BotResult<Vec<HashMap<String, AttributeValue>>> {
+    let mut items = Vec::new();
+    loop {
+        let span = tracing::info_span!("dynamo", operation = "Scan", table = input.table_name.as_str());
+        let started = Instant::now();
+        let output = client.scan(input.clone()).instrument(span).await;
+        metrics::time_by("DynamoScanDuration", "Table", &input.table_name, started.elapsed());
+        let output = output.map_err(record_error)?;
+        items.extend(output.items.unwrap_or_default());
+        match output.last_evaluated_key {
+            Some(key) => { input.exclusive_start_key = Some(key); },
+            None => return Ok(items),
+        }
+    }
+}
+
+// Like scan_all, for a query.
+pub async fn query_all(client: &impl DynamoDb, mut input: QueryInput) -> BotResult<Vec<HashMap<String, AttributeValue>>> {
+    let mut items = Vec::new();
+    loop {
+        let span = tracing::info_span!("dynamo", operation = "Query", table = input.table_name.as_str());
+        let output = client.query(input.clone()).instrument(span).await.map_err(record_error)?;
+        items.extend(output.items.unwrap_or_default());
+        match output.last_evaluated_key {
+            Some(key) => { input.exclusive_start_key = Some(key); },
+            None => return Ok(items),
+        }
+    }
+}
+
+// Mock clients for tests, which keep the requests they're sent.
+#[cfg(test)]
+pub mod mock {
+    use std::sync::{Arc, Mutex};
+    use rusoto_core::signature::SignedRequestPayload;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher, MultipleMockRequestDispatcher};
+    use serde_json::Value;
+
+    use super::*;
+
+    // A request a mock got. Indexing it indexes its body, eg `requests[0]["TableName"]`.
+    pub struct Sent {
+        pub method: String,
+        pub path: String,
+        // Null without a JSON body.
+        pub body: Value,
+    }
+
+    impl std::ops::Index<&str> for Sent {
+        type Output = Value;
+
+        fn index(&self, key: &str) -> &Value {
+            &self.body[key]
+        }
+    }
+
+    pub type Requests = Arc<Mutex<Vec<Sent>>>;
+
+    // `dispatcher`, keeping every request it gets in `requests`. For building clients of any AWS service.
+    pub fn recording(dispatcher: MockRequestDispatcher, requests: &Requests) -> MockRequestDispatcher {
+        let recorded = requests.clone();
+        dispatcher.with_request_checker(move |request| {
+            let body = match &request.payload {
+                Some(SignedRequestPayload::Buffer(bytes)) => serde_json::from_slice(bytes).unwrap_or(Value::Null),
+                _ => Value::Null,
+            };
+            recorded.lock().unwrap().push(Sent { method: request.method.clone(), path: request.path.clone(), body });
+        })
+    }
+
+    // Answers every request with `status` and `body`.
+    pub fn recording_client(status: u16, body: &str) -> (DynamoDbClient, Requests) {
+        let requests = Requests::default();
+        let dispatcher = recording(MockRequestDispatcher::with_status(status).with_body(body), &requests);
+        (DynamoDbClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1), requests)
+    }
+
+    // Answers with each of `bodies` in turn.
+    pub fn client_answering(bodies: Vec<Value>) -> (DynamoDbClient, Requests) {
+        let requests = Requests::default();
+        let dispatchers: Vec<_> = bodies.iter()
+            .map(|body| recording(MockRequestDispatcher::with_status(200).with_body(&body.to_string()), &requests))
+            .collect();
+        let dispatcher = MultipleMockRequestDispatcher::new(dispatchers);
+        (DynamoDbClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1), requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use super::mock::client_answering;
+
+    fn words(items: &[HashMap<String, AttributeValue>]) -> Vec<String> {
+        items.iter().filter_map(|item| item.get("word")?.s.clone()).collect()
+    }
+
+    #[test]
+    fn an_endpoint_makes_a_custom_region() {
+        let local = with_endpoint(Region::EuWest1, Some("http://localhost:8000".to_string()));
+        assert_eq!(local, Region::Custom { name: "eu-west-1".to_string(), endpoint: "http://localhost:8000".to_string() });
+        assert_eq!(with_endpoint(Region::EuWest1, Some(String::new())), Region::EuWest1);
+        assert_eq!(with_endpoint(Region::UsEast1, None), Region::UsEast1);
+    }
+
+    #[tokio::test]
+    async fn scan_all_follows_last_evaluated_key() {
+        let (client, requests) = client_answering(vec![
+            json!({ "Items": [{ "word": { "S": "clownn" } }, { "word": { "S": "smellya" } }],
+                    "LastEvaluatedKey": { "word": { "S": "smellya" } } }),
+            json!({ "Items": [{ "word": { "S": "buffoonn" } }] }),
+        ]);
+        let input = ScanInput { table_name: "words".to_string(), ..Default::default() };
+        let items = scan_all(&client, input).await.unwrap();
+        assert_eq!(words(&items), ["clownn", "smellya", "buffoonn"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body.get("ExclusiveStartKey"), None);
+        assert_eq!(requests[1]["ExclusiveStartKey"], json!({ "word": { "S": "smellya" } }));
+    }
+
+    #[tokio::test]
+    async fn pages_without_items_are_empty() {
+        let (client, requests) = client_answering(vec![
+            json!({ "LastEvaluatedKey": { "word": { "S": "clownn" } } }),
+            json!({ "Items": [{ "word": { "S": "smellya" } }] }),
+        ]);
+        let input = ScanInput { table_name: "words".to_string(), ..Default::default() };
+        let items = scan_all(&client, input).await.unwrap();
+        assert_eq!(words(&items), ["smellya"]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn query_all_follows_last_evaluated_key() {
+        let (client, requests) = client_answering(vec![
+            json!({ "Items": [{ "word": { "S": "clownn" } }], "LastEvaluatedKey": { "word": { "S": "clownn" } } }),
+            json!({ "Items": [] }),
+        ]);
+        let input = QueryInput { table_name: "words".to_string(), ..Default::default() };
+        assert_eq!(words(&query_all(&client, input).await.unwrap()), ["clownn"]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
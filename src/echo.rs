@@ -0,0 +1,21 @@
+use crate::router::{Args, Command};
+use crate::{LambdaResult, MessageEvent, MessageSink, SendOptions};
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::verbatim(
+            "echo <text>", "Admins only: repeat text without formatting it.",
+            r"(?i)^\s*echo\s+(.+)$",
+            |event, args, services| Box::pin(handle_echo_command(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+async fn handle_echo_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_echo(event, sink, args.get(1)).await
+}
+
+// Repeats the text back exactly as Slack delivered it, formatting characters and all.
+async fn handle_echo(event: &MessageEvent, sink: &dyn MessageSink, text: &str) -> LambdaResult<()> {
+    sink.post(&event.channel, text, &SendOptions { raw: true, ..event.reply_options() }).await.map(|_| ())
+}
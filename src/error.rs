@@ -0,0 +1,101 @@
+use std::sync::PoisonError;
+use rusoto_core::RusotoError;
+
+use crate::slack::SlackError;
+
+pub type BotResult<T> = Result<T, BotError>;
+
+// What went wrong, for the code that can do something about the difference.
+// Converts into a LambdaError with `?` like any other error.
+#[derive(Debug)]
+pub enum BotError {
+    MissingEnv(String),
+    Dynamo(String),
+    // Slack answered, but with `"ok": false`.
+    Slack(SlackError),
+    Http(String),
+    Serde(serde_json::Error),
+    // A thread panicked holding the named lock.
+    Poisoned(&'static str),
+}
+
+impl std::error::Error for BotError {}
+
+impl std::fmt::Display for BotError {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            BotError::MissingEnv(name) => fmtr.write_fmt(format_args!("missing env var {}", name)),
+            BotError::Dynamo(e) => fmtr.write_fmt(format_args!("dynamo error: {}", e)),
+            BotError::Slack(e) => fmtr.write_fmt(format_args!("slack error: {}", e)),
+            BotError::Http(e) => fmtr.write_fmt(format_args!("http error: {}", e)),
+            BotError::Serde(e) => fmtr.write_fmt(format_args!("serde error: {}", e)),
+            BotError::Poisoned(what) => fmtr.write_fmt(format_args!("somebody poisoned the {}!", what)),
+        }
+    }
+}
+
+impl BotError {
+    // Whether the same request could go through if we tried it again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BotError::Slack(e) => !e.is_permanent(),
+            BotError::MissingEnv(_) | BotError::Serde(_) | BotError::Poisoned(_) => false,
+            BotError::Dynamo(_) | BotError::Http(_) => true,
+        }
+    }
+
+    // The status to answer API Gateway with when this is why a request failed.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            BotError::Serde(_) => 400,
+            BotError::MissingEnv(_) | BotError::Poisoned(_) => 500,
+            BotError::Dynamo(_) | BotError::Slack(_) | BotError::Http(_) => 502,
+        }
+    }
+}
+
+// Like `std::env::var`, but remembers which variable was missing.
+pub fn env_var(name: &str) -> BotResult<String> {
+    std::env::var(name).map_err(|_| BotError::MissingEnv(name.to_string()))
+}
+
+// For taking a lock: `cache.read().map_err(poisoned("insult cache"))?`.
+pub fn poisoned<T>(what: &'static str) -> impl FnOnce(PoisonError<T>) -> BotError {
+    move |_| BotError::Poisoned(what)
+}
+
+impl<E: std::error::Error + 'static> From<RusotoError<E>> for BotError {
+    fn from(e: RusotoError<E>) -> Self {
+        BotError::Dynamo(e.to_string())
+    }
+}
+
+impl From<hyper::Error> for BotError {
+    fn from(e: hyper::Error) -> Self {
+        BotError::Http(e.to_string())
+    }
+}
+
+impl From<hyper::http::Error> for BotError {
+    fn from(e: hyper::http::Error) -> Self {
+        BotError::Http(e.to_string())
+    }
+}
+
+impl From<openssl::error::ErrorStack> for BotError {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        BotError::Http(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for BotError {
+    fn from(e: std::io::Error) -> Self {
+        BotError::Http(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for BotError {
+    fn from(e: serde_json::Error) -> Self {
+        BotError::Serde(e)
+    }
+}
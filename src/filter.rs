@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, PutItemInput, ScanInput};
+
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr};
+use crate::insult::{self, decode_word, encode_word, PartOfSpeech};
+use crate::router::{Args, Command};
+use crate::{audit, env_flag, now, LambdaResult, MessageEvent, MessageSink};
+
+// Words people add are checked against a blocklist of terms kept in BLOCKLIST_TABLE, and the regexes
+// in FILTER_PATTERNS, separated by spaces. Anything that matches is turned away, or with FILTER_QUARANTINE
+// held in PENDING_TABLE until an admin approves it.
+
+pub enum Verdict {
+    Allow,
+    Reject,
+    Quarantine,
+}
+
+fn quarantines() -> bool {
+    env_flag("FILTER_QUARANTINE")
+}
+
+static PATTERNS: Lazy<Vec<Regex>> = Lazy::new(|| {
+    std::env::var("FILTER_PATTERNS").unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|pattern| match Regex::new(&format!("(?i){}", pattern)) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                log::error!("Ignoring the filter pattern {}: {}", pattern, e);
+                None
+            },
+        })
+        .collect()
+});
+
+// Every add checks the blocklist, so each container holds on to what it read for a bit, like channel configs.
+const CACHE_SECS: u64 = 60;
+
+// When the blocklist was read, and the terms on it.
+type CachedBlocklist = Option<(u64, Vec<String>)>;
+
+static BLOCKLIST: Lazy<Mutex<CachedBlocklist>> = Lazy::new(Default::default);
+
+fn blocklist_table() -> Option<String> {
+    std::env::var("BLOCKLIST_TABLE").ok().filter(|table_name| !table_name.is_empty())
+}
+
+async fn blocklist() -> LambdaResult<Vec<String>> {
+    let table_name = match blocklist_table() {
+        Some(table_name) => table_name,
+        None => return Ok(Vec::new()),
+    };
+    if let Some((read, terms)) = &*BLOCKLIST.lock().unwrap_or_else(|e| e.into_inner()) {
+        if read + CACHE_SECS > now() {
+            return Ok(terms.clone());
+        }
+    }
+    let terms: Vec<_> = scan_all(&dynamo_client(), ScanInput { table_name, ..Default::default() }).await?.iter()
+        .filter_map(|item| item.get("term")?.s.clone())
+        .collect();
+    *BLOCKLIST.lock().unwrap_or_else(|e| e.into_inner()) = Some((now(), terms.clone()));
+    Ok(terms)
+}
+
+fn forget_blocklist() {
+    *BLOCKLIST.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// Terms are lowercased when they're added, so the word is too before looking for them in it.
+fn on_blocklist(word: &str, terms: &[String]) -> bool {
+    let word = word.to_lowercase();
+    terms.iter().any(|term| word.contains(term.as_str()))
+}
+
+pub async fn check(word: &str) -> LambdaResult<Verdict> {
+    let blocked = on_blocklist(word, &blocklist().await?)
+        || PATTERNS.iter().any(|pattern| pattern.is_match(word));
+    Ok(match blocked {
+        false => Verdict::Allow,
+        true if quarantines() => Verdict::Quarantine,
+        true => Verdict::Reject,
+    })
+}
+
+// Why `word` isn't going in yet, if it isn't: turned away, or held for an admin on `user`'s behalf.
+pub async fn screen(
+    pos: &PartOfSpeech, word: &str, user: &str, tag: Option<&str>,
+) -> LambdaResult<Option<&'static str>> {
+    match check(word).await? {
+        Verdict::Allow => Ok(None),
+        Verdict::Reject => Ok(Some("That word isn't allowed here.")),
+        Verdict::Quarantine => {
+            hold(pos, word, user, tag).await?;
+            log::info!("Holding the {} {} from {} for approval", pos.name(), word, user);
+            Ok(Some("That one has to be approved by an admin first."))
+        },
+    }
+}
+
+fn word_key(word: String) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("word".to_string(), string_attr(word));
+    key
+}
+
+async fn hold(pos: &PartOfSpeech, word: &str, user: &str, tag: Option<&str>) -> LambdaResult<()> {
+    let table_name = std::env::var("PENDING_TABLE")?;
+    let mut item = word_key(encode_word(pos, word.to_string()));
+    item.insert("added_by".to_string(), string_attr(user.to_string()));
+    if let Some(tag) = tag {
+        item.insert("tag".to_string(), string_attr(tag.to_string()));
+    }
+    item.insert("created".to_string(), number_attr(now()));
+    dynamo_client().put_item(PutItemInput { item, table_name, ..Default::default() }).await?;
+    Ok(())
+}
+
+struct Pending {
+    pos: PartOfSpeech,
+    word: String,
+    added_by: String,
+    tag: Option<String>,
+}
+
+impl Pending {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name)?.s.clone();
+        let (pos, word) = decode_word(s("word")?)?;
+        Some(Pending { pos, word, added_by: s("added_by")?, tag: s("tag") })
+    }
+}
+
+// Removes and returns the held `word`, whatever part of speech it was added as.
+async fn take_pending(word: &str) -> LambdaResult<Option<Pending>> {
+    let table_name = std::env::var("PENDING_TABLE")?;
+    let client = dynamo_client();
+    for pos in &PartOfSpeech::ALL {
+        let input = DeleteItemInput {
+            key: word_key(encode_word(pos, word.to_string())),
+            table_name: table_name.clone(),
+            return_values: Some("ALL_OLD".to_string()),
+            ..Default::default()
+        };
+        if let Some(item) = client.delete_item(input).await?.attributes {
+            return Ok(Pending::from_item(&item));
+        }
+    }
+    Ok(None)
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "pending words", "Admins only: list the words waiting on approval.",
+            r"(?i)^\s*pending\s+words$",
+            |event, _, services| Box::pin(handle_pending(event, services.sink)),
+        ).admin_only(),
+        Command::new(
+            "approve pending <word>", "Admins only: add a word the filter held back.",
+            r"(?i)^\s*approve\s+pending\s+(\S+)$",
+            |event, args, services| Box::pin(handle_approve(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "reject pending <word>", "Admins only: throw out a word the filter held back.",
+            r"(?i)^\s*reject\s+pending\s+(\S+)$",
+            |event, args, services| Box::pin(handle_reject(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "blocklist add|remove <term>", "Admins only: turn away words containing a term, or stop.",
+            r"(?i)^\s*blocklist\s+(add|remove)\s+(\S+)$",
+            |event, args, services| Box::pin(handle_blocklist(event, services.sink, args)),
+        ).admin_only(),
+    ]
+}
+
+async fn handle_pending(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let table_name = std::env::var("PENDING_TABLE")?;
+    let items = scan_all(&dynamo_client(), ScanInput { table_name, ..Default::default() }).await?;
+    let lines: Vec<_> = items.iter()
+        .filter_map(Pending::from_item)
+        .map(|pending| format!("• _{}_ ({}) from <@{}>", pending.word, pending.pos.name(), pending.added_by))
+        .collect();
+    if lines.is_empty() {
+        return event.reply_privately(sink, "Nothing's waiting on approval.").await;
+    }
+    event.reply_privately(sink, &lines.join("\n")).await
+}
+
+async fn handle_approve(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let pending = match take_pending(&args.get(1).to_lowercase()).await? {
+        Some(pending) => pending,
+        None => return event.reply_privately(sink, "That's not waiting on approval.").await,
+    };
+    let Pending { pos, word, added_by, tag } = pending;
+    if !insult::add_word(&pos, word.clone(), &added_by, tag.as_deref()).await? {
+        return event.reply_privately(sink, "I already have that word!").await;
+    }
+    audit::record("approved", &[encode_word(&pos, word.clone())], &event.user).await;
+    log::info!("{} approved the {} {}", event.user, pos.name(), word);
+    event.acknowledge(sink, "Approved.").await
+}
+
+async fn handle_reject(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let pending = match take_pending(&args.get(1).to_lowercase()).await? {
+        Some(pending) => pending,
+        None => return event.reply_privately(sink, "That's not waiting on approval.").await,
+    };
+    log::info!("{} rejected the {} {}", event.user, pending.pos.name(), pending.word);
+    event.acknowledge(sink, "Rejected.").await
+}
+
+async fn handle_blocklist(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let table_name = std::env::var("BLOCKLIST_TABLE")?;
+    let term = args.get(2).to_lowercase();
+    let mut key = HashMap::new();
+    key.insert("term".to_string(), string_attr(term.clone()));
+    let client = dynamo_client();
+    if args.get(1).eq_ignore_ascii_case("add") {
+        key.insert("added_by".to_string(), string_attr(event.user.clone()));
+        client.put_item(PutItemInput { item: key, table_name, ..Default::default() }).await?;
+    } else {
+        client.delete_item(DeleteItemInput { key, table_name, ..Default::default() }).await?;
+    }
+    forget_blocklist();
+    log::info!("{} ran blocklist {} {}", event.user, args.get(1).to_lowercase(), term);
+    event.acknowledge(sink, "Done.").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_terms_match_whatever_the_casing() {
+        let terms = vec!["heck".to_string()];
+        assert!(on_blocklist("HECKing", &terms));
+        assert!(on_blocklist("oheck", &terms));
+        assert!(!on_blocklist("hecate", &terms));
+    }
+}
@@ -0,0 +1,224 @@
+use once_cell::sync::Lazy;
+
+// How insults are put together in the vocabulary's language: word order, articles, and agreement.
+// LANG picks the language, eg "es", and channels can pick their own with "config set lang".
+// Words are stored as they'd appear in a dictionary, eg masculine singular for Spanish adjectives.
+
+pub trait Grammar: Sync {
+    // The language's code, eg "en".
+    fn code(&self) -> &'static str;
+
+    // The article before `word` in a phrase about `noun`, eg "an" before "ugly" or "una" before "rata".
+    fn article(&self, word: &str, noun: &str) -> &'static str;
+
+    // `adjective` as it goes with `noun`.
+    fn agree(&self, adjective: &str, noun: &str) -> String;
+
+    // A list of adjectives, eg "smelly, dumb".
+    fn list(&self, adjectives: &[String]) -> String;
+
+    // The adjectives and noun in the language's order, without an article, eg "smelly clown".
+    fn noun_phrase(&self, adjectives: &[String], noun: &str) -> String;
+
+    // The sentences an insult can take, with the same slots as INSULT_TEMPLATES.
+    fn templates(&self) -> &'static [&'static str];
+
+    // Phrasings for when one of the word pools is empty, like ADJECTIVE_ONLY_TEMPLATE and NOUN_ONLY_TEMPLATE.
+    fn adjective_only_template(&self) -> &'static str;
+    fn noun_only_template(&self) -> &'static str;
+}
+
+pub struct English;
+
+impl Grammar for English {
+    fn code(&self) -> &'static str {
+        "en"
+    }
+
+    // "an" before vowels, "a" otherwise. Good enough for most words, if not "hour" or "unicorn".
+    fn article(&self, word: &str, _: &str) -> &'static str {
+        match word.chars().next() {
+            Some('a' | 'e' | 'i' | 'o' | 'u' |
+                 'A' | 'E' | 'I' | 'O' | 'U') => "an",
+            _ => "a",
+        }
+    }
+
+    fn agree(&self, adjective: &str, _: &str) -> String {
+        adjective.to_string()
+    }
+
+    fn list(&self, adjectives: &[String]) -> String {
+        adjectives.join(", ")
+    }
+
+    fn noun_phrase(&self, adjectives: &[String], noun: &str) -> String {
+        match adjectives {
+            [] => noun.to_string(),
+            _ => format!("{} {}", self.list(adjectives), noun),
+        }
+    }
+
+    fn templates(&self) -> &'static [&'static str] {
+        &[
+            "{target} is {a adjectives} {noun}",
+            "{target}, you {adjectives} {noun}",
+            "{target} is {a adjective}, {noun}-loving {other_noun}",
+            "{target} is more {adjective} than {a noun}",
+        ]
+    }
+
+    fn adjective_only_template(&self) -> &'static str {
+        "so {adjective}"
+    }
+
+    fn noun_only_template(&self) -> &'static str {
+        "an absolute {noun}"
+    }
+}
+
+pub struct Spanish;
+
+impl Spanish {
+    // Going by the ending: "-a", "-ción", "-dad" and the like are feminine, most everything else masculine.
+    // It gets "día" and "problema" wrong, but those aren't much of an insult anyway.
+    fn is_feminine(noun: &str) -> bool {
+        const FEMININE: &[&str] = &["a", "ción", "sión", "dad", "tad", "tud", "umbre", "triz"];
+        let noun = noun.to_lowercase();
+        FEMININE.iter().any(|ending| noun.ends_with(ending)) && !noun.ends_with("ma")
+    }
+}
+
+impl Grammar for Spanish {
+    fn code(&self) -> &'static str {
+        "es"
+    }
+
+    fn article(&self, _: &str, noun: &str) -> &'static str {
+        if Spanish::is_feminine(noun) { "una" } else { "un" }
+    }
+
+    // Masculine adjectives ending in "-o" take "-a", and "-ón" and "-or" add one, eg "mandona".
+    // The rest, like "inútil" or "grande", are the same either way.
+    fn agree(&self, adjective: &str, noun: &str) -> String {
+        if !Spanish::is_feminine(noun) {
+            return adjective.to_string();
+        }
+        if let Some(stem) = adjective.strip_suffix('o') {
+            format!("{}a", stem)
+        } else if let Some(stem) = adjective.strip_suffix("ón") {
+            format!("{}ona", stem)
+        } else if adjective.ends_with("or") {
+            format!("{}a", adjective)
+        } else {
+            adjective.to_string()
+        }
+    }
+
+    // "tonto y feo", "sucio, tonto y feo".
+    fn list(&self, adjectives: &[String]) -> String {
+        match adjectives.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} y {}", rest.join(", "), last),
+            None => String::new(),
+        }
+    }
+
+    // Adjectives go after the noun, eg "payaso tonto".
+    fn noun_phrase(&self, adjectives: &[String], noun: &str) -> String {
+        let adjectives: Vec<_> = adjectives.iter().map(|adjective| self.agree(adjective, noun)).collect();
+        match adjectives.as_slice() {
+            [] => noun.to_string(),
+            _ => format!("{} {}", noun, self.list(&adjectives)),
+        }
+    }
+
+    fn templates(&self) -> &'static [&'static str] {
+        &[
+            "{target} es {a noun} {adjectives}",
+            "{target}, {noun} {adjectives}",
+            "{target} es más {adjective} que {a noun}",
+        ]
+    }
+
+    fn adjective_only_template(&self) -> &'static str {
+        "tan {adjective}"
+    }
+
+    fn noun_only_template(&self) -> &'static str {
+        "{a noun} de campeonato"
+    }
+}
+
+static LANGUAGES: &[&dyn Grammar] = &[&English, &Spanish];
+
+pub fn by_code(code: &str) -> Option<&'static dyn Grammar> {
+    LANGUAGES.iter().copied().find(|grammar| grammar.code().eq_ignore_ascii_case(code))
+}
+
+pub fn codes() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|grammar| grammar.code()).collect()
+}
+
+// LANG's, for channels that haven't picked one. English if it isn't set, or isn't one we know.
+static DEFAULT: Lazy<&'static dyn Grammar> = Lazy::new(|| {
+    let code = std::env::var("LANG").unwrap_or_default();
+    // Locales like "en_US.UTF-8" count too, since LANG may well be the system's.
+    let code = code.split(['_', '.']).next().unwrap_or_default();
+    match by_code(code) {
+        Some(grammar) => grammar,
+        None => {
+            if !code.is_empty() && code != "C" {
+                log::warn!("No grammar for LANG {}, using English", code);
+            }
+            &English
+        },
+    }
+});
+
+pub fn default_grammar() -> &'static dyn Grammar {
+    *DEFAULT
+}
+
+// `code` if it's one we know, or the default.
+pub fn for_code(code: Option<&str>) -> &'static dyn Grammar {
+    code.and_then(by_code).unwrap_or_else(default_grammar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn english_articles_go_by_the_first_letter() {
+        assert_eq!(English.article("ugly", "clown"), "an");
+        assert_eq!(English.article("Oaf", "oaf"), "an");
+        assert_eq!(English.article("smelly", "ogre"), "a");
+        assert_eq!(English.noun_phrase(&words(&["smelly", "dumb"]), "clown"), "smelly, dumb clown");
+    }
+
+    #[test]
+    fn spanish_adjectives_follow_and_agree_with_the_noun() {
+        assert_eq!(Spanish.article("tonto", "payaso"), "un");
+        assert_eq!(Spanish.article("tonto", "rata"), "una");
+        assert_eq!(Spanish.article("tonto", "problema"), "un");
+        assert_eq!(Spanish.agree("tonto", "rata"), "tonta");
+        assert_eq!(Spanish.agree("mandón", "rata"), "mandona");
+        assert_eq!(Spanish.agree("inútil", "rata"), "inútil");
+        assert_eq!(Spanish.agree("tonto", "payaso"), "tonto");
+        assert_eq!(Spanish.noun_phrase(&words(&["sucio", "tonto", "feo"]), "rata"), "rata sucia, tonta y fea");
+        assert_eq!(Spanish.noun_phrase(&[], "payaso"), "payaso");
+    }
+
+    #[test]
+    fn languages_are_found_by_code() {
+        assert_eq!(by_code("ES").map(|grammar| grammar.code()), Some("es"));
+        assert!(by_code("fr").is_none());
+        assert_eq!(for_code(Some("es")).code(), "es");
+        assert_eq!(for_code(Some("fr")).code(), default_grammar().code());
+    }
+}
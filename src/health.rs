@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use crate::router::Command;
+use crate::{insult, now, reminders, LambdaResult, MessageEvent, MessageSink};
+
+// What operators check after a deploy, without insulting anyone. `GET /healthz` is public, so it only says
+// we're up and which version; "status" from an admin adds the cached vocabulary and the last errors
+// talking to Slack and dynamo. Errors are only this container's, and only since it started.
+
+// Set GIT_SHA when building, eg `GIT_SHA=$(git rev-parse --short HEAD) cargo build`, as deploy.sh does.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_SHA: Option<&str> = option_env!("GIT_SHA");
+
+// When each service last failed and why, keyed by eg "slack".
+static LAST_ERRORS: Lazy<Mutex<BTreeMap<&'static str, (u64, String)>>> = Lazy::new(Default::default);
+
+pub fn record_error(service: &'static str, error: &dyn std::fmt::Display) {
+    let mut last_errors = LAST_ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    last_errors.insert(service, (now(), error.to_string()));
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "status", "Admins only: the build I'm running, the words I have cached, and what last went wrong.",
+            r"(?i)^\s*status$",
+            |event, _, services| Box::pin(handle_status(event, services.sink)),
+        ).admin_only(),
+    ]
+}
+
+// The answer to `GET /healthz`. Answering at all is the check: it doesn't touch dynamo or Slack.
+pub fn check() -> Value {
+    json!({ "ok": true, "version": VERSION })
+}
+
+async fn handle_status(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let mut lines = vec![format!("Version {} ({})", VERSION, GIT_SHA.unwrap_or("unknown build"))];
+    match insult::cache_status().await {
+        Ok(status) => {
+            let mut line = format!("{} nouns, {} adjectives, {} emoji", status.nouns, status.adjectives, status.emoji);
+            match (status.bundled, status.age_secs) {
+                (true, _) => line.push_str(", the bundled ones: I couldn't read dynamo"),
+                (false, Some(age)) => line.push_str(&format!(", scanned {}s ago", age)),
+                (false, None) => (),
+            }
+            lines.push(line);
+        },
+        Err(e) => lines.push(format!("I couldn't read the words: {}", e)),
+    }
+    let last_errors = LAST_ERRORS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if last_errors.is_empty() {
+        lines.push("No errors since this container started.".to_string());
+    }
+    for (service, (at, message)) in last_errors {
+        lines.push(format!("Last {} error, {}: {}", service, reminders::format_time(at), message));
+    }
+    event.reply_privately(sink, &lines.join("\n")).await
+}
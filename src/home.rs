@@ -0,0 +1,58 @@
+use crate::blocks::{self, Block, Button};
+use crate::insult::{self, PartOfSpeech};
+use crate::{insult_log, interactive, now, slack, LambdaResult};
+
+// The app's Home tab, redrawn for whoever opens it: how big the vocabulary is, what's new,
+// and how they've been doing.
+// https://api.slack.com/surfaces/tabs
+
+const RECENT_WORDS_SHOWN: usize = 5;
+const MONTH_SECS: u64 = 30 * 24 * 60 * 60;
+
+pub async fn publish(user: &str) -> LambdaResult<()> {
+    let blocks = home_blocks(user).await?;
+    Ok(slack::client().await?.publish_home(user, blocks::to_value(&blocks)).await?)
+}
+
+async fn home_blocks(user: &str) -> LambdaResult<Vec<Block>> {
+    let words = insult::fetch_word_records().await?;
+    let count = |pos: PartOfSpeech| words.iter().filter(|record| record.pos == pos).count().to_string();
+    let mut blocks = vec![
+        Block::header("Insult vocabulary"),
+        Block::section_with_fields(&format!("*{} words* in all", words.len()), &[
+            &format!("*Adjectives*\n{}", count(PartOfSpeech::Adjective)),
+            &format!("*Nouns*\n{}", count(PartOfSpeech::Noun)),
+            &format!("*Emoji*\n{}", count(PartOfSpeech::Emoji)),
+        ]),
+    ];
+
+    let mut recent: Vec<_> = words.iter().filter(|record| record.added.is_some()).collect();
+    recent.sort_by_key(|record| std::cmp::Reverse(record.added));
+    let mut text = "*Recently added*".to_string();
+    if recent.is_empty() {
+        text.push_str("\n_Nothing yet._");
+    }
+    for record in recent.iter().take(RECENT_WORDS_SHOWN) {
+        text.push_str(&format!("\n_{}_ ({})", record.word, record.pos.name()));
+        if let Some(added_by) = &record.added_by {
+            text.push_str(&format!(" by <@{}>", added_by));
+        }
+    }
+    blocks.push(Block::section(&text));
+    if interactive::enabled() {
+        blocks.push(Block::actions(vec![Button::new("Add word", interactive::OPEN_ADD_WORD, "home")]));
+    }
+
+    blocks.push(Block::divider());
+    let added = words.iter().filter(|record| record.added_by.as_deref() == Some(user)).count();
+    let mut text = format!("*Your stats*\nWords you've added: {}", added);
+    if insult_log::enabled() {
+        let tag = format!("<@{}>", user);
+        let insulted = insult_log::fetch_since(now().saturating_sub(MONTH_SECS)).await?.iter()
+            .filter(|entry| entry.target.as_deref() == Some(tag.as_str()))
+            .count();
+        text.push_str(&format!("\nTimes you've been insulted in the last 30 days: {}", insulted));
+    }
+    blocks.push(Block::section(&text));
+    Ok(blocks)
+}
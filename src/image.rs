@@ -0,0 +1,169 @@
+use crate::config::Feature;
+use crate::insult::{handle_say_insult, make_insult, to_user_tag};
+use crate::router::{Command, Services};
+use crate::{env_flag, upload_file, users, LambdaResult, MessageEvent};
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "insult me as an image", "Insult yourself, in picture form, if image insults are on.",
+            r"(?i)\binsult\s+me\s+as\s+an\s+image\b",
+            |event, _, services| Box::pin(handle_image_insult(event, services)),
+        ).requires(Feature::Insults),
+    ]
+}
+
+// Rendering is deliberately minimal: uppercase text in a built-in 5x7 pixel font
+// on a solid background, so we don't need to ship a font file.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+// Glyphs are drawn in cells with a column of spacing between letters and two rows between lines.
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 2;
+const SCALE: usize = 4;
+const PADDING: usize = 24;
+const MAX_LINE_CHARS: usize = 20;
+
+const BACKGROUND: [u8; 3] = [0x2c, 0x1e, 0x3b];
+const FOREGROUND: [u8; 3] = [0xff, 0xd4, 0x3b];
+
+// Each row is five bits, leftmost pixel first.
+fn glyph(chr: char) -> [u8; GLYPH_HEIGHT] {
+    match chr.to_ascii_uppercase() {
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        ' ' => [0; GLYPH_HEIGHT],
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ',' => [0, 0, 0, 0, 0b01100, 0b00100, 0b01000],
+        '!' => [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0, 0b00100],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        '\'' => [0b01100, 0b00100, 0b01000, 0, 0, 0, 0],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0, 0b00100],  // '?'
+    }
+}
+
+// Greedily wraps on spaces. Words longer than a line get a line to themselves and overflow it.
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= MAX_LINE_CHARS => {
+                line.push(' ');
+                line.push_str(word);
+            },
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+// Renders `text` as a PNG.
+pub fn render_png(text: &str) -> LambdaResult<Vec<u8>> {
+    let lines = wrap(text);
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let width = PADDING * 2 + columns * CELL_WIDTH * SCALE;
+    let height = PADDING * 2 + lines.len() * CELL_HEIGHT * SCALE;
+
+    let mut pixels: Vec<u8> = BACKGROUND.iter().copied().cycle().take(width * height * 3).collect();
+    for (row, line) in lines.iter().enumerate() {
+        for (column, chr) in line.chars().enumerate() {
+            let left = PADDING + column * CELL_WIDTH * SCALE;
+            let top = PADDING + row * CELL_HEIGHT * SCALE;
+            for (y, bits) in glyph(chr).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                        continue;
+                    }
+                    for dy in 0..SCALE {
+                        let start = ((top + y * SCALE + dy) * width + left + x * SCALE) * 3;
+                        for pixel in pixels[start..start + SCALE * 3].chunks_mut(3) {
+                            pixel.copy_from_slice(&FOREGROUND);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(bytes)
+}
+
+async fn handle_image_insult(event: &MessageEvent, services: Services<'_>) -> LambdaResult<()> {
+    if !env_flag("IMAGE_INSULTS") {
+        return handle_say_insult(event, services, to_user_tag(&event.user)).await;
+    }
+    let user_tag = to_user_tag(&event.user);
+    let insult = match make_insult(services.words, &event.channel, Some(&user_tag), 1, None).await? {
+        Some(insult) => insult,
+        None => return event.reply(services.sink, "Shut up.").await,
+    };
+    // Mentions don't render in pictures, so it's their name instead.
+    let png = render_png(&insult.aimed_at(&users::display_name(&event.user).await))?;
+    let thread_ts = event.thread_options().thread_ts;
+    upload_file(&event.channel, thread_ts.as_deref(), "insult.png", png, &insult.aimed_at(&user_tag)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_on_spaces() {
+        assert_eq!(wrap("you are a smelly, dumb clown"), ["you are a smelly,", "dumb clown"]);
+        assert_eq!(wrap("  "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn renders_a_png_of_the_text() {
+        let bytes = render_png("you are a smelly, dumb clown").unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((frame.color_type, frame.bit_depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+        assert_eq!(frame.width as usize, PADDING * 2 + 17 * CELL_WIDTH * SCALE);
+        assert_eq!(frame.height as usize, PADDING * 2 + 2 * CELL_HEIGHT * SCALE);
+        let pixels: Vec<_> = pixels[..frame.buffer_size()].chunks(3).collect();
+        assert_eq!(pixels[0], BACKGROUND);
+        assert!(pixels.contains(&FOREGROUND.as_slice()));
+    }
+}
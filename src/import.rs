@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use rusoto_dynamodb::{PutRequest, WriteRequest};
+
+use crate::error::poisoned;
+use crate::insult::{
+    batch_write, encode_word, insert_word_to_cache, insult_factory, item_word, word_item, DynamoWords, PartOfSpeech,
+    WordStore,
+};
+use crate::words::{normalize_word, validate_word};
+use crate::{admin, audit, bot_user_id, filter, metrics, send_message, slack, FileSharedEvent, LambdaResult};
+
+// Words in bulk, from a file sent to the bot in a direct message. Each line is "noun,clown", or just "clown"
+// in a file named for what's in it, like nouns.txt.
+// https://api.slack.com/events/file_shared
+
+// Far more words than anyone has, and small enough to hold in memory.
+const MAX_FILE_BYTES: u64 = 1 << 20;
+
+fn pos_named(name: &str) -> Option<PartOfSpeech> {
+    match name.trim().to_lowercase().trim_end_matches('s') {
+        "noun" => Some(PartOfSpeech::Noun),
+        "adjective" => Some(PartOfSpeech::Adjective),
+        "emoji" => Some(PartOfSpeech::Emoji),
+        _ => None,
+    }
+}
+
+// The words in `text`, and how many lines didn't say which part of speech they were.
+fn parse_words(text: &str, default_pos: Option<PartOfSpeech>) -> (Vec<(PartOfSpeech, String)>, usize) {
+    let mut words = Vec::new();
+    let mut unreadable = 0;
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let parsed = match line.split_once([',', '\t']) {
+            Some((pos, word)) => pos_named(pos).map(|pos| (pos, word)),
+            None => default_pos.clone().map(|pos| (pos, line)),
+        };
+        match parsed {
+            Some((pos, word)) => words.push((pos, word.trim().trim_matches(&['"', ':'][..]).to_string())),
+            None => unreadable += 1,
+        }
+    }
+    (words, unreadable)
+}
+
+// How an import went, for telling whoever uploaded the words.
+struct ImportSummary {
+    imported: usize,
+    duplicates: usize,
+    invalid: usize,
+    // Turned away by the filter, or held for an admin to approve.
+    screened: usize,
+}
+
+// Adds every valid word we don't already have in one batch, on `user`'s behalf.
+// Imports skip voting, since only admins can do them, but not the filter.
+async fn import_words(words: Vec<(PartOfSpeech, String)>, user: &str) -> LambdaResult<ImportSummary> {
+    import_words_to(&DynamoWords, words, user).await
+}
+
+async fn import_words_to(
+    store: &dyn WordStore, words: Vec<(PartOfSpeech, String)>, user: &str,
+) -> LambdaResult<ImportSummary> {
+    let cache = insult_factory().await?;
+    // A batch put has no condition, so check against what's in dynamo now rather than this container's cache,
+    // which could be missing words someone else added since.
+    let stored: HashSet<_> = store.items().await?.iter().filter_map(item_word).collect();
+    let mut summary = ImportSummary { imported: 0, duplicates: 0, invalid: 0, screened: 0 };
+    let mut added = Vec::new();
+    for (pos, word) in words {
+        let word = normalize_word(&word);
+        if validate_word(&word).is_err() {
+            summary.invalid += 1;
+        } else if stored.contains(&encode_word(&pos, word.clone())) {
+            insert_word_to_cache(cache, &pos, word, None)?;
+            summary.duplicates += 1;
+        } else if filter::screen(&pos, &word, user, None).await?.is_some() {
+            summary.screened += 1;
+        } else if !insert_word_to_cache(cache, &pos, word.clone(), None)? {
+            summary.duplicates += 1;
+        } else {
+            added.push((pos, word));
+        }
+    }
+
+    let puts = added.iter()
+        .map(|(pos, word)| WriteRequest {
+            put_request: Some(PutRequest { item: word_item(encode_word(pos, word.clone()), user, None) }),
+            ..Default::default()
+        })
+        .collect();
+    if let Err(e) = batch_write(puts).await {
+        // Don't offer up words that never made it to dynamo.
+        let mut insults = cache.write()
+            .map_err(poisoned("insult cache"))?;
+        for (pos, word) in &added {
+            insults.remove_word(pos, word);
+        }
+        return Err(e);
+    }
+    metrics::count("WordsAdded", added.len() as u64);
+    let encoded: Vec<_> = added.iter().map(|(pos, word)| encode_word(pos, word.clone())).collect();
+    audit::record("imported", &encoded, user).await;
+    summary.imported = added.len();
+    Ok(summary)
+}
+
+pub async fn handle_file_shared(event: &FileSharedEvent) -> LambdaResult<()> {
+    // Files shared anywhere else are just people sharing files, and ours are never word lists.
+    if !event.channel_id.starts_with('D') || bot_user_id().await?.as_deref() == Some(event.user_id.as_str()) {
+        return Ok(());
+    }
+    if !admin::is_admin(&event.user_id).await? {
+        return send_message(&event.channel_id, "Only admins can import words.").await;
+    }
+    let client = slack::client().await?;
+    let file = client.files_info(&event.file_id).await?;
+    let url = match &file.url_private {
+        Some(url) if matches!(file.filetype.as_str(), "text" | "csv") => url,
+        _ => return send_message(&event.channel_id, "I can only import words from a text or CSV file.").await,
+    };
+    if file.size > MAX_FILE_BYTES {
+        return send_message(&event.channel_id, "That file's too big to be a list of words.").await;
+    }
+    let bytes = client.download(url).await?;
+    let text = String::from_utf8_lossy(&bytes);
+    let default_pos = file.name.split(['.', '_', '-']).next().and_then(pos_named);
+    let (words, unreadable) = parse_words(&text, default_pos);
+    let summary = import_words(words, &event.user_id).await?;
+    log::info!("{} imported {} words from {}", event.user_id, summary.imported, file.name);
+
+    let mut message = format!("Imported {}, skipped {} duplicates.", summary.imported, summary.duplicates);
+    if summary.invalid > 0 {
+        message.push_str(&format!(" {} weren't words.", summary.invalid));
+    }
+    if summary.screened > 0 {
+        message.push_str(&format!(" {} were filtered out or held for approval.", summary.screened));
+    }
+    if unreadable > 0 {
+        message.push_str(&format!(" {} lines didn't say noun, adjective, or emoji.", unreadable));
+    }
+    send_message(&event.channel_id, &message).await
+}
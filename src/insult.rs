@@ -1,171 +1,2363 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use once_cell::sync::Lazy;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use regex::Regex;
-use rusoto_core::Region;
-use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, PutItemInput, ScanInput, ScanOutput};
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, BatchWriteItemInput, DeleteItemError, DeleteItemInput, DeleteRequest, DynamoDb, GetItemInput,
+    PutItemError, PutItemInput, PutRequest, QueryInput, ScanInput, UpdateItemError, UpdateItemInput, WriteRequest,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use tokio::sync::OnceCell;
 
-use crate::{send_message, LambdaResult, MessageEvent};
+use crate::{audit, config, grammar, popularity, war};
+use crate::blocks::{self, Block, Button};
+use crate::config::{ChannelConfig, Feature};
+use crate::dynamo::{dynamo_client, number_attr, query_all, scan_all, string_attr, string_set_attr};
+use crate::grammar::Grammar;
+use crate::popularity::Score;
+use crate::router::{self, Args, Command, Services};
+use crate::error::{env_var, poisoned, BotError, BotResult};
+use crate::{
+    bot_user_id, env_flag, env_parse, insult_log, insult_stats, interactive, last_added, markov, metrics, now,
+    opt_out, send_ephemeral, users, LambdaResult, MessageEvent, MessageSink, PinAddedEvent, ReactionEvent, ReactionItem,
+    SendOptions, SlashCommand,
+};
 
-async fn insult_factory() -> LambdaResult<&'static RwLock<InsultFactory>> {
-    static INSTANCE: OnceCell<RwLock<InsultFactory>> = OnceCell::const_new();
-    INSTANCE.get_or_try_init(fetch_insults_rw).await
+const THINKING: &str = "🤔 thinking of something mean…";
+
+static INSULT_FACTORY: OnceCell<RwLock<InsultFactory>> = OnceCell::const_new();
+
+// When the words were last scanned from dynamo, in epoch seconds.
+static FACTORY_LOADED_AT: AtomicU64 = AtomicU64::new(0);
+
+// How long a warm container goes before scanning for words added elsewhere.
+// Words added through this container are in its cache already.
+fn vocabulary_ttl() -> u64 {
+    env_parse("VOCABULARY_TTL_SECS", 5 * 60)
+}
+
+// Falls back to the bundled words if dynamo can't be read. That isn't cached,
+// so the next call tries dynamo again.
+pub async fn insult_factory() -> BotResult<&'static RwLock<InsultFactory>> {
+    let loaded = INSULT_FACTORY.initialized();
+    let insults = match INSULT_FACTORY.get_or_try_init(fetch_insults_rw).await {
+        Ok(insults) => insults,
+        Err(e) => {
+            log::warn!("Couldn't load words from dynamo, using the bundled ones: {}", e);
+            return Ok(&DEFAULT_FACTORY);
+        },
+    };
+    let (loaded_at, now) = (FACTORY_LOADED_AT.load(Ordering::Relaxed), now());
+    if !loaded {
+        FACTORY_LOADED_AT.store(now, Ordering::Relaxed);
+    } else if now.saturating_sub(loaded_at) >= vocabulary_ttl()
+        // Only one caller refreshes, and everyone else carries on with the words we have.
+        && FACTORY_LOADED_AT.compare_exchange(loaded_at, now, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+    {
+        match fetch_insults(&DynamoWords).await {
+            Ok(fresh) => {
+                *insults.write().map_err(poisoned("insult cache"))? = fresh;
+            },
+            Err(e) => log::warn!("Couldn't refresh words from dynamo, keeping the ones we have: {}", e),
+        }
+    }
+    Ok(insults)
+}
+
+static DEFAULT_FACTORY: Lazy<RwLock<InsultFactory>> = Lazy::new(|| RwLock::new(default_insults()));
+
+fn default_insults() -> InsultFactory {
+    let mut nouns = Vec::new();
+    let mut adjectives = Vec::new();
+    let lines = include_str!("default_words.txt").lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for line in lines {
+        match line.split_once(' ') {
+            Some(("noun", word)) => nouns.push(word.to_string()),
+            Some(("adjective", word)) => adjectives.push(word.to_string()),
+            _ => log::warn!("Ignoring malformed bundled word: {}", line),
+        }
+    }
+    InsultFactory::new(nouns, adjectives)
+}
+
+// Whether this container has already scanned the words from dynamo.
+fn factory_loaded() -> bool {
+    INSULT_FACTORY.initialized()
+}
+
+// How many words this container has cached, for the health check.
+pub struct CacheStatus {
+    pub nouns: usize,
+    pub adjectives: usize,
+    pub emoji: usize,
+    // Seconds since the words were scanned from dynamo.
+    pub age_secs: Option<u64>,
+    // Whether dynamo couldn't be read, so these are the bundled words.
+    pub bundled: bool,
+}
+
+// Loads the words first if they aren't yet, so a broken table shows up.
+pub async fn cache_status() -> LambdaResult<CacheStatus> {
+    let cache = insult_factory().await?;
+    let bundled = std::ptr::eq(cache, &*DEFAULT_FACTORY);
+    let age_secs = match FACTORY_LOADED_AT.load(Ordering::Relaxed) {
+        0 => None,
+        loaded_at => Some(now().saturating_sub(loaded_at)),
+    };
+    let insults = cache.read().map_err(poisoned("insult cache"))?;
+    Ok(CacheStatus {
+        nouns: insults.noun_count(),
+        adjectives: insults.adjective_count(),
+        emoji: insults.emoji.len(),
+        age_secs: if bundled { None } else { age_secs },
+        bundled,
+    })
+}
+
+// Set by admins with "use table <name>" to point this container at another table, eg for staging.
+// It lasts until the container goes cold.
+static TABLE_OVERRIDE: Lazy<RwLock<Option<String>>> = Lazy::new(Default::default);
+
+pub fn insult_table() -> BotResult<String> {
+    // A poisoned lock still holds a perfectly good table name.
+    let table_override = TABLE_OVERRIDE.read().unwrap_or_else(|e| e.into_inner());
+    match &*table_override {
+        Some(table_name) => Ok(table_name.clone()),
+        None if pos_keyed() => env_var("WORD_TABLE"),
+        None => env_var("INSULT_TABLE"),
+    }
+}
+
+// INSULT_TABLE keys each word by its encoded form, eg "clownn". With POS_KEYED_WORDS we use WORD_TABLE instead,
+// which has `pos` as its partition key and the bare word as its sort key.
+// "migrate words" copies INSULT_TABLE over before the switch.
+fn pos_keyed() -> bool {
+    env_flag("POS_KEYED_WORDS")
+}
+
+fn set_table_override(table_name: Option<String>) -> LambdaResult<()> {
+    *TABLE_OVERRIDE.write()
+        .map_err(poisoned("table override"))? = table_name;
+    Ok(())
+}
+
+// Words used in each channel's last few insults, oldest first.
+// Per container, so a cold start forgets them, which is fine.
+static RECENT_WORDS: Lazy<Mutex<HashMap<String, VecDeque<String>>>> = Lazy::new(Default::default);
+
+// How many of a channel's most recent insults to avoid reusing words from.
+fn cooldown_insults() -> usize {
+    env_parse("WORD_COOLDOWN", 5)
+}
+
+fn remember_words(recent: &mut VecDeque<String>, insult: &Insult) {
+    recent.extend(insult.words().into_iter().map(str::to_string));
+    while recent.len() > cooldown_insults() * 2 {
+        recent.pop_front();
+    }
+}
+
+// The word from `pool` that was used most recently, if any were.
+fn last_used<'a>(pool: &[&'a str], recent: &VecDeque<String>) -> Option<&'a str> {
+    recent.iter().rev().find_map(|r| pool.iter().copied().find(|word| word == r))
+}
+
+// How likely each word is to be picked, relative to the others. See `popularity`.
+type Weight<'a> = &'a dyn Fn(&str) -> f64;
+
+// One of `words`, in proportion to their weights.
+fn choose_weighted<'a>(words: &[&'a str], weight: Weight) -> Option<&'a str> {
+    match words.choose_weighted(&mut thread_rng(), |word| weight(word)) {
+        Ok(word) => Some(word),
+        Err(_) => words.choose(&mut thread_rng()),
+    }.copied()
+}
+
+// `words` in a random order, heavier ones tending first. Each gets a key of u^(1/weight) and the
+// biggest keys win, which picks them one after another in proportion to their weights.
+fn shuffle_weighted(words: &mut Vec<&str>, weight: Weight) {
+    let mut rng = thread_rng();
+    let mut keyed: Vec<_> = words.drain(..)
+        .map(|word| (rng.gen::<f64>().powf(1.0 / weight(word).max(f64::MIN_POSITIVE)), word))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    words.extend(keyed.into_iter().map(|(_, word)| word));
+}
+
+// Picks a word that hasn't been used recently, unless they all have.
+// Even then it won't repeat the last one, unless it's the only word we know.
+fn choose_fresh<'a>(pool: &[&'a str], recent: &VecDeque<String>, weight: Weight) -> Option<&'a str> {
+    let fresh: Vec<_> = pool.iter().copied().filter(|word| !recent.iter().any(|r| r == word)).collect();
+    if let Some(word) = choose_weighted(&fresh, weight) {
+        return Some(word);
+    }
+    let last = last_used(pool, recent);
+    let stale: Vec<_> = pool.iter().copied().filter(|&word| Some(word) != last).collect();
+    choose_weighted(&stale, weight).or_else(|| choose_weighted(pool, weight))
+}
+
+// Picks `count` different words, preferring ones that haven't been used recently,
+// and the last one used least of all.
+fn choose_fresh_many<'a>(pool: &[&'a str], recent: &VecDeque<String>, count: usize, weight: Weight) -> Vec<&'a str> {
+    let (mut fresh, mut stale): (Vec<_>, Vec<_>) = pool.iter().copied()
+        .partition(|word| !recent.iter().any(|r| r == word));
+    shuffle_weighted(&mut fresh, weight);
+    shuffle_weighted(&mut stale, weight);
+    if let Some(last) = last_used(pool, recent) {
+        stale.retain(|&word| word != last);
+        stale.push(last);
+    }
+    fresh.into_iter().chain(stale).take(count).collect()
+}
+
+// How many emoji make up an emoji insult.
+fn emoji_insult_length() -> usize {
+    env_parse("EMOJI_INSULT_LENGTH", 3).max(1)
+}
+
+// Phrasings for when one of the word pools is empty, eg "so dumb" or "an absolute clown".
+// Unset, they're the grammar's.
+fn adjective_only_template(grammar: &dyn Grammar) -> String {
+    std::env::var("ADJECTIVE_ONLY_TEMPLATE").unwrap_or_else(|_| grammar.adjective_only_template().to_string())
+}
+
+fn noun_only_template(grammar: &dyn Grammar) -> String {
+    std::env::var("NOUN_ONLY_TEMPLATE").unwrap_or_else(|_| grammar.noun_only_template().to_string())
+}
+
+// Sentences an insult aimed at someone can take, one picked at random each time.
+// `{target}` is who's being insulted, `{adjective}` the first adjective, `{adjectives}` all of them,
+// and `{noun}` and `{other_noun}` two different nouns. `{a adjective}` and the like come with an article to suit.
+// Each grammar has its own, eg "{target} is {a adjectives} {noun}". Set INSULT_TEMPLATES to a |-separated
+// list to use others.
+fn insult_templates(grammar: &dyn Grammar) -> Vec<String> {
+    match std::env::var("INSULT_TEMPLATES") {
+        Ok(templates) if !templates.trim().is_empty() => templates.split('|').map(|t| t.trim().to_string()).collect(),
+        _ => grammar.templates().iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+// A template for `adjective_count` adjectives and up to `noun_count` nouns.
+// Stacked adjectives only fit templates that list them all.
+fn choose_template(grammar: &dyn Grammar, adjective_count: usize, noun_count: usize) -> String {
+    let fits = |template: &String| {
+        (adjective_count < 2 || template.contains("adjectives}"))
+            && (noun_count > 1 || !template.contains("other_noun}"))
+    };
+    let templates: Vec<_> = insult_templates(grammar).into_iter().filter(fits).collect();
+    templates.choose(&mut thread_rng()).cloned().unwrap_or_else(|| grammar.templates()[0].to_string())
 }
 
-struct InsultFactory {
+// Fills each `{slot}` and `{a slot}` in `template`, the articles going with `noun`.
+fn fill_template(grammar: &dyn Grammar, template: &str, noun: &str, slots: &[(&str, &str)]) -> String {
+    let mut filled = template.to_string();
+    for (slot, value) in slots {
+        filled = filled
+            .replace(&format!("{{a {}}}", slot), &format!("{} {}", grammar.article(value, noun), value))
+            .replace(&format!("{{{}}}", slot), value);
+    }
+    filled
+}
+
+// Words can be rated from 1 (mild) to MAX_SPICE (harsh) with a numeric `spice` attribute in dynamo.
+const MAX_SPICE: u8 = 3;
+const UNRATED_SPICE: u8 = 2;
+
+// Times each target was insulted recently, oldest first. Per container, like RECENT_WORDS.
+static RECENT_TARGETS: Lazy<Mutex<HashMap<String, VecDeque<u64>>>> = Lazy::new(Default::default);
+
+// With INTENSITY_DECAY, every DECAY_STEP insults a target takes within DECAY_WINDOW_SECS
+// lowers the spice of the words they get, down to the mildest.
+fn decay_window() -> u64 {
+    env_parse("DECAY_WINDOW_SECS", 10 * 60)
+}
+
+fn decay_step() -> usize {
+    env_parse("DECAY_STEP", 2).max(1)
+}
+
+// Records an insult at `target`, returning the spice the insult should be capped at.
+fn spice_cap(target: &str) -> LambdaResult<u8> {
+    if !env_flag("INTENSITY_DECAY") {
+        return Ok(MAX_SPICE);
+    }
+    let mut recent_targets = RECENT_TARGETS.lock()
+        .map_err(poisoned("recent targets"))?;
+    Ok(decayed_spice(recent_targets.entry(target.to_string()).or_default(), now()))
+}
+
+// Adds an insult at `now` to a target's recent `times`, returning the spice cap for it.
+fn decayed_spice(times: &mut VecDeque<u64>, now: u64) -> u8 {
+    while times.front().is_some_and(|&time| time + decay_window() < now) {
+        times.pop_front();
+    }
+    let softening = (times.len() / decay_step()).min(MAX_SPICE as usize - 1) as u8;
+    times.push_back(now);
+    MAX_SPICE - softening
+}
+
+pub enum Insult {
+    // Usually one adjective, but "insult me hard" stacks a few.
+    // The second noun is only there if the template calls for it.
+    Phrase {
+        adjectives: Vec<String>,
+        noun: String,
+        other_noun: Option<String>,
+        template: String,
+        grammar: &'static dyn Grammar,
+    },
+    // Fallbacks for when we know words of one part of speech but not the other.
+    AdjectiveOnly(String, &'static dyn Grammar),
+    NounOnly(String, &'static dyn Grammar),
+    // Emoji shortcodes, without the colons.
+    Emoji(Vec<String>),
+    // Whatever the Markov chain strung together, eg ["slimy", "sack", "of", "wet", "socks"].
+    Creative(Vec<String>, &'static dyn Grammar),
+}
+
+impl Insult {
+    pub fn words(&self) -> Vec<&str> {
+        match self {
+            Insult::Phrase { adjectives, noun, other_noun, .. } => adjectives.iter()
+                .chain(std::iter::once(noun))
+                .chain(other_noun)
+                .map(String::as_str)
+                .collect(),
+            Insult::AdjectiveOnly(word, _) | Insult::NounOnly(word, _) => vec![word],
+            Insult::Emoji(emoji) | Insult::Creative(emoji, _) => emoji.iter().map(String::as_str).collect(),
+        }
+    }
+
+    pub fn template(&self) -> &str {
+        match self {
+            Insult::Phrase { template, .. } => template,
+            _ => "{target} is {insult}",
+        }
+    }
+
+    // The whole sentence, eg "<@U123> is a dumb clown".
+    pub fn aimed_at(&self, target: &str) -> String {
+        match self {
+            Insult::Phrase { adjectives, noun, other_noun, template, grammar } => {
+                let adjectives: Vec<_> = adjectives.iter().map(|adjective| grammar.agree(adjective, noun)).collect();
+                fill_template(*grammar, template, noun, &[
+                    ("target", target),
+                    ("adjectives", &grammar.list(&adjectives)),
+                    ("adjective", &adjectives[0]),
+                    ("noun", noun),
+                    ("other_noun", other_noun.as_deref().unwrap_or(noun)),
+                ])
+            },
+            _ => format!("{} is {}", target, self),
+        }
+    }
+}
+
+impl std::fmt::Display for Insult {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let (adjectives, noun, grammar) = match self {
+            Insult::Phrase { adjectives, noun, grammar, .. } => (adjectives, noun, grammar),
+            Insult::AdjectiveOnly(adjective, grammar) => {
+                let template = adjective_only_template(*grammar);
+                return fmtr.write_str(&fill_template(*grammar, &template, "", &[("adjective", adjective)]));
+            },
+            Insult::NounOnly(noun, grammar) => {
+                return fmtr.write_str(&fill_template(*grammar, &noun_only_template(*grammar), noun, &[("noun", noun)]));
+            },
+            Insult::Emoji(emoji) => {
+                let shortcodes: Vec<_> = emoji.iter().map(|e| format!(":{}:", e)).collect();
+                return fmtr.write_str(&shortcodes.join(" "));
+            },
+            // There's no telling which word is the noun, so the first will have to do.
+            Insult::Creative(words, grammar) => {
+                return fmtr.write_fmt(format_args!("{} {}", grammar.article(&words[0], &words[0]), words.join(" ")));
+            },
+        };
+        let phrase = grammar.noun_phrase(adjectives, noun);
+        let article = grammar.article(phrase.split(' ').next().unwrap_or_default(), noun);
+        fmtr.write_fmt(format_args!("{} {}", article, phrase))
+    }
+}
+
+// Picks an insult's words from what the factory knows, avoiding what the channel heard lately where it can.
+// The insult's put together in `grammar`, the channel's language.
+trait Generator: Sync {
+    fn generate(
+        &self, factory: &InsultFactory, grammar: &'static dyn Grammar, recent: &VecDeque<String>, max_spice: u8,
+    ) -> Option<Insult>;
+}
+
+// The usual adjectives and a noun, put in a template.
+struct Classic<'a> {
+    adjectives: usize,
+    tag: Option<&'a str>,
+}
+
+impl Generator for Classic<'_> {
+    fn generate(
+        &self, factory: &InsultFactory, grammar: &'static dyn Grammar, recent: &VecDeque<String>, max_spice: u8,
+    ) -> Option<Insult> {
+        factory.get_insult_with(grammar, recent, max_spice, self.adjectives, self.tag)
+    }
+}
+
+// A Markov chain's take on the vocabulary and the seed corpus, for "insult me creatively".
+struct Creative {
+    corpus: &'static [Vec<String>],
+}
+
+impl Generator for Creative {
+    fn generate(
+        &self, factory: &InsultFactory, grammar: &'static dyn Grammar, recent: &VecDeque<String>, max_spice: u8,
+    ) -> Option<Insult> {
+        let adjectives: Vec<_> = factory.adjectives.iter().map(String::as_str).collect();
+        let adjectives = factory.within_spice(&PartOfSpeech::Adjective, &adjectives, max_spice);
+        let nouns: Vec<_> = factory.nouns.iter().map(String::as_str).collect();
+        let nouns = factory.within_spice(&PartOfSpeech::Noun, &nouns, max_spice);
+        let mut rng = thread_rng();
+        let mut chain = markov::Chain::default();
+        // The words on their own only ever come in pairs, so each is paired up at random.
+        let pair = |adjective: &str, noun: &str| markov::words(&grammar.noun_phrase(&[adjective.to_string()], noun));
+        for noun in &nouns {
+            if let Some(adjective) = adjectives.choose(&mut rng) {
+                chain.train(pair(adjective, noun));
+            }
+        }
+        for adjective in &adjectives {
+            if let Some(noun) = nouns.choose(&mut rng) {
+                chain.train(pair(adjective, noun));
+            }
+        }
+        for sequence in self.corpus {
+            chain.train(sequence.clone());
+        }
+        match chain.generate(&mut rng) {
+            Some(words) => Some(Insult::Creative(words, grammar)),
+            // Too little to go on for anything new.
+            None => Classic { adjectives: 1, tag: None }.generate(factory, grammar, recent, max_spice),
+        }
+    }
+}
+
+pub struct InsultFactory {
     nouns: Vec<String>,
     adjectives: Vec<String>,
+    emoji: Vec<String>,
+    // Keyed by the word's dynamo key. Words without a rating aren't in here.
+    spice: HashMap<String, u8>,
+    // Also keyed by dynamo key. Untagged words aren't in here, and go with any tag.
+    tags: HashMap<String, HashSet<String>>,
+    // Also keyed by dynamo key. Words nobody's boosted aren't in here.
+    scores: HashMap<String, Score>,
 }
 
 impl InsultFactory {
-    fn get_insult(&self) -> Option<String> {
-        let adjective = self.adjectives.choose(&mut thread_rng())?;
-        let noun = self.nouns.choose(&mut thread_rng())?;
-        let article = if let Some(chr) = adjective.chars().next() {
-            match chr {
-                'a' | 'e' | 'i' | 'o' | 'u' |
-                'A' | 'E' | 'I' | 'O' | 'U' => "an",
-                _ => "a",
-            }
-        } else { "a" };
+    // A factory of unrated words, with no emoji.
+    fn new(nouns: Vec<String>, adjectives: Vec<String>) -> Self {
+        InsultFactory {
+            nouns, adjectives, emoji: Vec::new(), spice: HashMap::new(), tags: HashMap::new(), scores: HashMap::new(),
+        }
+    }
 
-        Some(format!("{} {} {}", article, adjective, noun))
+    fn weight(&self, pos: &PartOfSpeech, word: &str) -> f64 {
+        popularity::weight(self.scores.get(&encode_word(pos, word.to_string())))
     }
 
-    fn insert_word(&mut self, pos: &PartOfSpeech, word: String) -> bool {
-        let list = match pos {
+    // Usually `adjective_count` is 1, but more get stacked like "a smelly, dumb clown".
+    // They're all different, and we use as many as we know up to the count.
+    // With a `tag`, only words with that tag or no tags at all are used.
+    fn get_insult_with(
+        &self, grammar: &'static dyn Grammar, recent: &VecDeque<String>, max_spice: u8, adjective_count: usize,
+        tag: Option<&str>,
+    ) -> Option<Insult> {
+        let adjectives = self.with_tag(&PartOfSpeech::Adjective, &self.adjectives, tag);
+        let adjectives = self.within_spice(&PartOfSpeech::Adjective, &adjectives, max_spice);
+        let nouns = self.with_tag(&PartOfSpeech::Noun, &self.nouns, tag);
+        let nouns = self.within_spice(&PartOfSpeech::Noun, &nouns, max_spice);
+        let adjective_weight = |word: &str| self.weight(&PartOfSpeech::Adjective, word);
+        let noun_weight = |word: &str| self.weight(&PartOfSpeech::Noun, word);
+        let mut adjectives: Vec<_> = choose_fresh_many(&adjectives, recent, adjective_count.max(1), &adjective_weight)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let noun = choose_fresh(&nouns, recent, &noun_weight).map(str::to_string);
+        match (adjectives.is_empty(), noun) {
+            (false, Some(noun)) => {
+                let template = choose_template(grammar, adjectives.len(), nouns.len());
+                let other_noun = if template.contains("other_noun}") {
+                    let others: Vec<_> = nouns.iter().copied().filter(|&other| other != noun).collect();
+                    choose_fresh(&others, recent, &noun_weight).map(str::to_string)
+                } else {
+                    None
+                };
+                Some(Insult::Phrase { adjectives, noun, other_noun, template, grammar })
+            },
+            (false, None) => Some(Insult::AdjectiveOnly(adjectives.swap_remove(0), grammar)),
+            (true, Some(noun)) => Some(Insult::NounOnly(noun, grammar)),
+            (true, None) => None,
+        }
+    }
+
+    // Emoji aren't spice rated, so there's no cap to apply.
+    fn get_emoji_insult(&self, recent: &VecDeque<String>) -> Option<Insult> {
+        let pool: Vec<_> = self.emoji.iter().map(String::as_str).collect();
+        let weight = |word: &str| self.weight(&PartOfSpeech::Emoji, word);
+        let emoji = choose_fresh_many(&pool, recent, emoji_insult_length(), &weight);
+        if emoji.is_empty() {
+            return None;
+        }
+        Some(Insult::Emoji(emoji.into_iter().map(str::to_string).collect()))
+    }
+
+    // Unlike spice, there's no falling back: a word tagged otherwise is never used.
+    fn with_tag<'a>(&self, pos: &PartOfSpeech, words: &'a [String], tag: Option<&str>) -> Vec<&'a str> {
+        let all = words.iter().map(String::as_str);
+        let tag = match tag {
+            Some(tag) => tag,
+            None => return all.collect(),
+        };
+        all.filter(|word| match self.tags.get(&encode_word(pos, word.to_string())) {
+            Some(tags) => tags.contains(tag),
+            None => true,
+        }).collect()
+    }
+
+    // The words no spicier than `max_spice`, or all of them if none are that mild.
+    fn within_spice<'a>(&self, pos: &PartOfSpeech, words: &[&'a str], max_spice: u8) -> Vec<&'a str> {
+        let all = words.iter().copied();
+        let mild: Vec<_> = all.clone()
+            .filter(|word| {
+                let spice = self.spice.get(&encode_word(pos, word.to_string()));
+                spice.copied().unwrap_or(UNRATED_SPICE) <= max_spice
+            })
+            .collect();
+        if mild.is_empty() { all.collect() } else { mild }
+    }
+
+    pub fn noun_count(&self) -> usize {
+        self.nouns.len()
+    }
+
+    pub fn adjective_count(&self) -> usize {
+        self.adjectives.len()
+    }
+
+    fn words_mut(&mut self, pos: &PartOfSpeech) -> &mut Vec<String> {
+        match pos {
             PartOfSpeech::Noun => &mut self.nouns,
             PartOfSpeech::Adjective => &mut self.adjectives,
-        };
-        if list.iter().any(|w| w == &word) {
+            PartOfSpeech::Emoji => &mut self.emoji,
+        }
+    }
+
+    pub fn words(&self, pos: &PartOfSpeech) -> &[String] {
+        match pos {
+            PartOfSpeech::Noun => &self.nouns,
+            PartOfSpeech::Adjective => &self.adjectives,
+            PartOfSpeech::Emoji => &self.emoji,
+        }
+    }
+
+    // Case-insensitive, since words added before normalization may not be lowercase.
+    pub fn has_word(&self, pos: &PartOfSpeech, word: &str) -> bool {
+        self.words(pos).iter().any(|w| w.to_lowercase() == word.to_lowercase())
+    }
+
+    fn insert_word(&mut self, pos: &PartOfSpeech, word: String) -> bool {
+        if self.has_word(pos, &word) {
             return false;
         }
-        list.push(word);
+        self.words_mut(pos).push(word);
         true
     }
+
+    fn tag_word(&mut self, pos: &PartOfSpeech, word: &str, tag: &str) {
+        self.tags.entry(encode_word(pos, word.to_string())).or_default().insert(tag.to_string());
+    }
+
+    // Adds `other`'s words we don't have yet, along with their spice, tags and score.
+    fn merge(&mut self, other: InsultFactory) {
+        let InsultFactory { nouns, adjectives, emoji, mut spice, mut tags, mut scores } = other;
+        let words = nouns.into_iter().map(|word| (PartOfSpeech::Noun, word))
+            .chain(adjectives.into_iter().map(|word| (PartOfSpeech::Adjective, word)))
+            .chain(emoji.into_iter().map(|word| (PartOfSpeech::Emoji, word)));
+        for (pos, word) in words {
+            let data = encode_word(&pos, word.clone());
+            if !self.insert_word(&pos, word) {
+                continue;
+            }
+            if let Some(rating) = spice.remove(&data) {
+                self.spice.insert(data.clone(), rating);
+            }
+            if let Some(word_tags) = tags.remove(&data) {
+                self.tags.insert(data.clone(), word_tags);
+            }
+            if let Some(score) = scores.remove(&data) {
+                self.scores.insert(data, score);
+            }
+        }
+    }
+
+    pub fn remove_word(&mut self, pos: &PartOfSpeech, word: &str) -> bool {
+        self.tags.remove(&encode_word(pos, word.to_string()));
+        let list = self.words_mut(pos);
+        let before = list.len();
+        list.retain(|w| w != word);
+        list.len() != before
+    }
+
+    fn matching(&self, matcher: &WordMatcher) -> Vec<(PartOfSpeech, String)> {
+        let nouns = self.nouns.iter().map(|w| (PartOfSpeech::Noun, w));
+        let adjectives = self.adjectives.iter().map(|w| (PartOfSpeech::Adjective, w));
+        let emoji = self.emoji.iter().map(|w| (PartOfSpeech::Emoji, w));
+        nouns.chain(adjectives).chain(emoji)
+            .filter(|(_, word)| matcher.is_match(word))
+            .map(|(pos, word)| (pos, word.clone()))
+            .collect()
+    }
 }
 
-#[derive(Debug)]
-struct GenericError(String);
-impl std::error::Error for GenericError {}
-impl std::fmt::Display for GenericError {
-    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        fmtr.write_fmt(format_args!("GenericError({})", self.0))
+// "/regex/" matches by regex, anything else is a case insensitive substring.
+enum WordMatcher {
+    Substring(String),
+    Pattern(Regex),
+}
+
+impl WordMatcher {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let regex = pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/'));
+        match regex {
+            Some("") => Err("That matches everything.".to_string()),
+            Some(regex) => regex::RegexBuilder::new(regex)
+                .size_limit(1 << 16)
+                .build()
+                .map(WordMatcher::Pattern)
+                .map_err(|e| format!("That's not a regex I understand: {}", e)),
+            None => Ok(WordMatcher::Substring(pattern.to_lowercase())),
+        }
+    }
+
+    fn is_match(&self, word: &str) -> bool {
+        match self {
+            WordMatcher::Substring(substring) => word.to_lowercase().contains(substring),
+            WordMatcher::Pattern(regex) => regex.is_match(word),
+        }
+    }
+}
+
+// Snapshot of every stored word, as written to and read from backups.
+#[derive(Serialize, Deserialize)]
+pub struct Vocabulary {
+    pub nouns: Vec<String>,
+    pub adjectives: Vec<String>,
+    // Older backups predate emoji.
+    #[serde(default)]
+    pub emoji: Vec<String>,
+    // Everything else stored with each word, like who added it, its tags, spice and score, keyed by the encoded word.
+    // Older backups only have the words.
+    #[serde(default)]
+    pub details: HashMap<String, HashMap<String, AttributeValue>>,
+}
+
+impl Vocabulary {
+    fn from_items(items: Vec<HashMap<String, AttributeValue>>) -> Self {
+        let mut vocabulary = Vocabulary {
+            nouns: Vec::new(), adjectives: Vec::new(), emoji: Vec::new(), details: HashMap::new(),
+        };
+        for mut item in items {
+            let data = match item_word(&item) {
+                Some(data) => data,
+                None => continue,
+            };
+            match decode_word(data.clone()) {
+                Some((PartOfSpeech::Noun, word)) => vocabulary.nouns.push(word),
+                Some((PartOfSpeech::Adjective, word)) => vocabulary.adjectives.push(word),
+                Some((PartOfSpeech::Emoji, word)) => vocabulary.emoji.push(word),
+                None => continue,
+            }
+            // The key is rebuilt for whichever schema it's restored to.
+            item.remove("word");
+            item.remove("pos");
+            if !item.is_empty() {
+                vocabulary.details.insert(data, item);
+            }
+        }
+        vocabulary
+    }
+
+    // Every word as the item to store, with its details, one a word.
+    fn items(&self) -> Vec<HashMap<String, AttributeValue>> {
+        let mut words: Vec<_> = self.nouns.iter().map(|w| encode_word(&PartOfSpeech::Noun, w.clone()))
+            .chain(self.adjectives.iter().map(|w| encode_word(&PartOfSpeech::Adjective, w.clone())))
+            .chain(self.emoji.iter().map(|w| encode_word(&PartOfSpeech::Emoji, w.clone())))
+            .collect();
+        // A batch containing the same key twice is rejected outright.
+        words.sort();
+        words.dedup();
+        words.into_iter()
+            .map(|word| {
+                let mut item = self.details.get(&word).cloned().unwrap_or_default();
+                item.extend(word_key(word));
+                item
+            })
+            .collect()
     }
 }
 
-enum PartOfSpeech {
+// Not strictly a part of speech, but emoji are stored and added the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PartOfSpeech {
     Noun,
     Adjective,
+    Emoji,
+}
+
+impl PartOfSpeech {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartOfSpeech::Noun => "noun",
+            PartOfSpeech::Adjective => "adjective",
+            PartOfSpeech::Emoji => "emoji",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "noun" => Some(PartOfSpeech::Noun),
+            "adjective" => Some(PartOfSpeech::Adjective),
+            "emoji" => Some(PartOfSpeech::Emoji),
+            _ => None,
+        }
+    }
+
+    pub const ALL: [PartOfSpeech; 3] = [PartOfSpeech::Noun, PartOfSpeech::Adjective, PartOfSpeech::Emoji];
+}
+
+// Words are stored in dynamo with their part of speech as a trailing character.
+// Ex: "clownn" is the noun "clown", "dumba" is the adjective "dumb", "poope" is the emoji :poop:.
+pub fn encode_word(pos: &PartOfSpeech, mut word: String) -> String {
+    word.push(match pos {
+        PartOfSpeech::Noun => 'n',
+        PartOfSpeech::Adjective => 'a',
+        PartOfSpeech::Emoji => 'e',
+    });
+    word
+}
+
+pub fn decode_word(mut data: String) -> Option<(PartOfSpeech, String)> {
+    let pos = match data.pop()? {
+        'n' => PartOfSpeech::Noun,
+        'a' => PartOfSpeech::Adjective,
+        'e' => PartOfSpeech::Emoji,
+        _ => return None,
+    };
+    Some((pos, data))
 }
 
-fn to_user_tag(user_id: &str) -> String {
+pub fn to_user_tag(user_id: &str) -> String {
     format!("<@{}>", user_id)
 }
 
+// Ex: "<@U123>" -> "U123"
+fn from_user_tag(user_tag: &str) -> Option<&str> {
+    user_tag.strip_prefix("<@")?.strip_suffix('>')
+}
+
+// Whether `user_tag` has opted out of being insulted by `requester`. Anyone can still insult themselves.
+async fn is_opted_out(user_tag: &str, requester: &str) -> LambdaResult<bool> {
+    is_opted_out_in(&dynamo_client(), user_tag, requester).await
+}
+
+async fn is_opted_out_in(client: &impl DynamoDb, user_tag: &str, requester: &str) -> LambdaResult<bool> {
+    match from_user_tag(user_tag) {
+        Some(user) if user != requester => opt_out::is_opted_out_in(client, user).await,
+        _ => Ok(false),
+    }
+}
+
 async fn fetch_insults_rw() -> LambdaResult<RwLock<InsultFactory>> {
-    fetch_insults().await.map(RwLock::new)
+    Ok(RwLock::new(fetch_insults(&DynamoWords).await?))
+}
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = BotResult<T>> + Send + 'a>>;
+
+// Where the words are kept, as items shaped like the insult table's.
+pub trait WordStore: Sync {
+    fn items(&self) -> StoreFuture<'_, Vec<HashMap<String, AttributeValue>>>;
+    // Returns false without writing if the encoded `word` is already there.
+    fn insert<'a>(&'a self, word: String, added_by: &'a str, tag: Option<&'a str>) -> StoreFuture<'a, bool>;
+    // Returns false if the encoded `word` wasn't there.
+    fn delete(&self, word: String) -> StoreFuture<'_, bool>;
+    // The words insults are made from, kept in memory.
+    fn cache(&self) -> StoreFuture<'_, &RwLock<InsultFactory>>;
+}
+
+// The insult table.
+pub struct DynamoWords;
+
+impl WordStore for DynamoWords {
+    fn items(&self) -> StoreFuture<'_, Vec<HashMap<String, AttributeValue>>> {
+        Box::pin(scan_items())
+    }
+
+    fn insert<'a>(&'a self, word: String, added_by: &'a str, tag: Option<&'a str>) -> StoreFuture<'a, bool> {
+        Box::pin(insert_word_to_dynamo(word, added_by, tag))
+    }
+
+    fn delete(&self, word: String) -> StoreFuture<'_, bool> {
+        Box::pin(delete_word(word))
+    }
+
+    fn cache(&self) -> StoreFuture<'_, &RwLock<InsultFactory>> {
+        Box::pin(async {
+            let cache: &RwLock<InsultFactory> = insult_factory().await?;
+            Ok(cache)
+        })
+    }
+}
+
+// Every item in the insult table, across as many pages as it takes.
+async fn scan_items() -> BotResult<Vec<HashMap<String, AttributeValue>>> {
+    scan_items_from(&dynamo_client()).await
+}
+
+async fn scan_items_from(client: &impl DynamoDb) -> BotResult<Vec<HashMap<String, AttributeValue>>> {
+    let table_name = insult_table()?;
+    if !pos_keyed() {
+        let input = ScanInput { table_name, ..Default::default() };
+        return scan_all(client, input).await;
+    }
+    let mut items = Vec::new();
+    for pos in &PartOfSpeech::ALL {
+        let input = QueryInput {
+            table_name: table_name.clone(),
+            key_condition_expression: Some("#pos = :pos".to_string()),
+            expression_attribute_names: Some(std::iter::once(("#pos".to_string(), "pos".to_string())).collect()),
+            expression_attribute_values: Some(
+                std::iter::once((":pos".to_string(), string_attr(pos.name().to_string()))).collect()
+            ),
+            ..Default::default()
+        };
+        items.extend(query_all(client, input).await?);
+    }
+    Ok(items)
+}
+
+// The encoded word an item is for, whichever schema it was written with.
+pub fn item_word(item: &HashMap<String, AttributeValue>) -> Option<String> {
+    let word = item.get("word")?.s.clone()?;
+    match item.get("pos") {
+        Some(pos) => Some(encode_word(&PartOfSpeech::from_name(pos.s.as_deref()?)?, word)),
+        None => Some(word),
+    }
 }
 
-async fn fetch_insults() -> LambdaResult<InsultFactory> {
-    let table_name = std::env::var("INSULT_TABLE")?;
-    let client = DynamoDbClient::new(Region::UsEast1);
-    let input = ScanInput { table_name, ..Default::default() };
-    let ScanOutput { items, .. } = client.scan(input).await?;
-    let items = items.unwrap();  // when would this happen??
+async fn fetch_insults(store: &dyn WordStore) -> BotResult<InsultFactory> {
+    let (insults, discarded) = parse_words(store.items().await?);
+    if discarded > 0 {
+        log::warn!("Discarding dynamodb insult words: {} words were malformed", discarded);
+    }
+    Ok(insults)
+}
 
+// Builds a factory from insult table items, also returning how many items were malformed.
+fn parse_words(items: Vec<HashMap<String, AttributeValue>>) -> (InsultFactory, usize) {
     let mut nouns = Vec::new();
     let mut adjectives = Vec::new();
+    let mut emoji = Vec::new();
+    let mut spice = HashMap::new();
+    let mut tags = HashMap::new();
+    let mut scores = HashMap::new();
     let mut discarded = 0;
     for item in items {
-        let mut data = match item.get("word") {
-            Some(AttributeValue { s: Some(string), .. }) => string.to_string(),
-            _ => {
+        let data = match item_word(&item) {
+            Some(data) => data,
+            None => {
                 discarded += 1;
                 continue;
             },
         };
-        match data.pop() {
-            Some('n') => { nouns.push(data); }
-            Some('a') => { adjectives.push(data); }
-            _ => { discarded += 1; },
+        let rating = item.get("spice").and_then(|attr| attr.n.as_ref()?.parse::<u8>().ok());
+        if let Some(rating) = rating {
+            spice.insert(data.clone(), rating.clamp(1, MAX_SPICE));
+        }
+        if let Some(AttributeValue { ss: Some(word_tags), .. }) = item.get("tags") {
+            tags.insert(data.clone(), word_tags.iter().cloned().collect::<HashSet<_>>());
+        }
+        if let Some(score) = Score::from_item(&item) {
+            scores.insert(data.clone(), score);
+        }
+        match decode_word(data) {
+            Some((PartOfSpeech::Noun, word)) => { nouns.push(word); }
+            Some((PartOfSpeech::Adjective, word)) => { adjectives.push(word); }
+            Some((PartOfSpeech::Emoji, word)) => { emoji.push(word); }
+            None => { discarded += 1; },
         }
     }
-    if discarded > 0 {
-        log::warn!("Discarding dynamodb insult words: {} words were malformed", discarded);
-    }
-    Ok(InsultFactory { nouns, adjectives })
+    (InsultFactory { emoji, spice, tags, scores, ..InsultFactory::new(nouns, adjectives) }, discarded)
 }
 
-async fn insert_word_to_dynamo(word: String) -> LambdaResult<()> {
-    let table_name = std::env::var("INSULT_TABLE")?;
-    let mut item = HashMap::new();
-    item.insert("word".to_string(), AttributeValue { s: Some(word), ..Default::default() });
+// A stored word along with who added it and when, where we know.
+pub struct WordRecord {
+    pub pos: PartOfSpeech,
+    pub word: String,
+    pub added_by: Option<String>,
+    pub added: Option<u64>,
+}
 
-    let client = DynamoDbClient::new(Region::UsEast1);
-    let input = PutItemInput { item, table_name, ..Default::default() };
-    client.put_item(input).await?;
-    Ok(())
+pub async fn fetch_word_records() -> LambdaResult<Vec<WordRecord>> {
+    let records = DynamoWords.items().await?.into_iter()
+        .filter_map(|item| {
+            let (pos, word) = decode_word(item_word(&item)?)?;
+            let added_by = item.get("added_by").and_then(|attr| attr.s.clone());
+            let added = item.get("added").and_then(|attr| attr.n.as_ref()?.parse().ok());
+            Some(WordRecord { pos, word, added_by, added })
+        })
+        .collect();
+    Ok(records)
 }
 
-pub async fn handle_message(event: &MessageEvent) -> LambdaResult<()> {
-    let re = Regex::new(r"\binsult\s+(<@U\w+>)").unwrap();
-    if let Some(caps) = re.captures(&event.text) {
-        let name = caps.get(1).unwrap().as_str().to_string();
-        return handle_say_insult(event, name).await;
-    }
+// Reads the vocabulary straight from dynamo, bypassing the cache.
+pub async fn fetch_vocabulary() -> LambdaResult<Vocabulary> {
+    Ok(Vocabulary::from_items(DynamoWords.items().await?))
+}
 
-    let re = Regex::new(r"\binsult\s+me\b").unwrap();
-    if re.is_match(&event.text) {
-        return handle_say_insult(event, to_user_tag(event.user.as_str())).await;
-    }
+// Returns false without writing if the word is already in dynamo,
+// eg because another container added it since we scanned.
+async fn insert_word_to_dynamo(word: String, added_by: &str, tag: Option<&str>) -> BotResult<bool> {
+    put_new_item(&dynamo_client(), insult_table()?, word_item(word, added_by, tag)).await
+}
 
-    let re = Regex::new(r"^(?:<@U\w+>\s)?\s*add\s+(adjective|noun)\s+([\w ,-]+)$").unwrap();
-    if let Some(caps) = re.captures(&event.text) {
-        let pos = match caps.get(1).unwrap().as_str() {
-            "adjective" => PartOfSpeech::Adjective,
-            "noun" => PartOfSpeech::Noun,
-            _ => unreachable!(),
-        };
-        let insult = caps.get(2).unwrap().as_str().trim().to_string();
-        if insult == "" {
-            return send_message(&event.channel, "Nice try wise guy.").await;
-        }
-        return handle_add_word(&event, pos, insult).await;
+// Puts a word's `item` unless the table already has the word. Returns false if it did.
+async fn put_new_item(
+    client: &impl DynamoDb, table_name: String, item: HashMap<String, AttributeValue>,
+) -> BotResult<bool> {
+    let input = PutItemInput {
+        item,
+        table_name,
+        condition_expression: Some("attribute_not_exists(word)".to_string()),
+        ..Default::default()
+    };
+    match client.put_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
     }
-    Ok(())
 }
 
-async fn handle_say_insult(event: &MessageEvent, user_tag: String) -> LambdaResult<()> {
-    let insults = insult_factory().await?;
-    let message = match insults.read().map_err(|_| GenericError("somebody poisoned the insult cache!".to_string()))?.get_insult() {
-        Some(insult) => format!("{} is {}", user_tag, insult),
-        None => "Shut up.".to_string(),
+// Deletes the encoded `word` from dynamo. Returns false if it wasn't there.
+async fn delete_word(word: String) -> BotResult<bool> {
+    let table_name = insult_table()?;
+    let client = dynamo_client();
+    let input = DeleteItemInput {
+        key: word_key(word),
+        table_name,
+        condition_expression: Some("attribute_exists(word)".to_string()),
+        ..Default::default()
     };
-
-    send_message(&event.channel, &message).await
+    match client.delete_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
 }
 
-fn insert_word_to_cache(cache: &RwLock<InsultFactory>, pos: &PartOfSpeech, insult: String) -> LambdaResult<bool> {
-    let mut insults = match cache.write() {
-        Ok(i) => i,
-        _ => return Err(Box::new(GenericError("somebody poisoned the insult cache!".to_string()))),
-    };
-    Ok(insults.insert_word(&pos, insult))
+// Deletes the word from dynamo and this container's cache on `user`'s behalf. Returns false if it was already gone.
+pub async fn forget_word(pos: &PartOfSpeech, word: &str, user: &str) -> LambdaResult<bool> {
+    forget_word_from(&DynamoWords, pos, word, user).await
 }
 
-async fn handle_add_word(event: &MessageEvent, pos: PartOfSpeech, mut insult: String) -> LambdaResult<()> {
-    let cache = insult_factory().await?;
-    if !insert_word_to_cache(cache, &pos, insult.clone())? {
-       return send_message(&event.channel, "I already have that word!").await;
+async fn forget_word_from(store: &dyn WordStore, pos: &PartOfSpeech, word: &str, user: &str) -> LambdaResult<bool> {
+    if !store.delete(encode_word(pos, word.to_string())).await? {
+        return Ok(false);
+    }
+    audit::record("removed", &[encode_word(pos, word.to_string())], user).await;
+    insult_factory().await?.write()
+        .map_err(poisoned("insult cache"))?
+        .remove_word(pos, word);
+    Ok(true)
+}
+
+// The part of speech we know `word` as, if any. Nouns first, like "forget".
+pub async fn known_pos(word: &str) -> LambdaResult<Option<PartOfSpeech>> {
+    let insults = insult_factory().await?.read()
+        .map_err(poisoned("insult cache"))?;
+    Ok(PartOfSpeech::ALL.iter().find(|pos| insults.has_word(pos, word)).cloned())
+}
+
+// The words we know that appear in `text`, eg the ones in an insult we sent.
+pub async fn words_in(text: &str) -> LambdaResult<Vec<(PartOfSpeech, String)>> {
+    let tokens = |text: &str| -> Vec<String> {
+        text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '\'' || c == '+'))
+            .filter(|token| !token.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let text = tokens(text);
+    let insults = insult_factory().await?.read()
+        .map_err(poisoned("insult cache"))?;
+    let mut found = Vec::new();
+    for pos in PartOfSpeech::ALL {
+        for word in insults.words(&pos) {
+            let word_tokens = tokens(word);
+            if !word_tokens.is_empty() && text.windows(word_tokens.len()).any(|window| window == word_tokens) {
+                found.push((pos.clone(), word.clone()));
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Adds `points` to the word's score in dynamo and the cache, decaying what was there.
+// Two boosts at once can lose one, which isn't worth a transaction.
+pub async fn boost_word(pos: &PartOfSpeech, word: &str, points: f64) -> LambdaResult<()> {
+    let table_name = insult_table()?;
+    let client = dynamo_client();
+    let key = word_key(encode_word(pos, word.to_string()));
+    let input = GetItemInput { key: key.clone(), table_name: table_name.clone(), ..Default::default() };
+    let item = match client.get_item(input).await?.item {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    let score = Score::from_item(&item).unwrap_or_default().bumped(points, now());
+    let mut names = HashMap::new();
+    let mut values = HashMap::new();
+    for (name, value) in score.to_attrs() {
+        names.insert(format!("#{}", name), name.to_string());
+        values.insert(format!(":{}", name), value);
+    }
+    let input = UpdateItemInput {
+        key,
+        table_name,
+        update_expression: Some("SET #score = :score, #scored = :scored".to_string()),
+        condition_expression: Some("attribute_exists(word)".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    match client.update_item(input).await {
+        Ok(_) => (),
+        // Forgotten since we read it.
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    insult_factory().await?.write()
+        .map_err(poisoned("insult cache"))?
+        .scores.insert(encode_word(pos, word.to_string()), score);
+    Ok(())
+}
+
+pub fn word_item(word: String, added_by: &str, tag: Option<&str>) -> HashMap<String, AttributeValue> {
+    let mut item = word_key(word);
+    item.insert("added_by".to_string(), string_attr(added_by.to_string()));
+    item.insert("added".to_string(), number_attr(now()));
+    if let Some(tag) = tag {
+        item.insert("tags".to_string(), string_set_attr(vec![tag.to_string()]));
+    }
+    item
+}
+
+// The key for the encoded `word` in whichever schema the table uses.
+fn word_key(word: String) -> HashMap<String, AttributeValue> {
+    if pos_keyed() {
+        if let Some((pos, word)) = decode_word(word.clone()) {
+            return pos_word_key(&pos, word);
+        }
+    }
+    let mut key = HashMap::new();
+    key.insert("word".to_string(), AttributeValue { s: Some(word), ..Default::default() });
+    key
+}
+
+fn pos_word_key(pos: &PartOfSpeech, word: String) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("pos".to_string(), string_attr(pos.name().to_string()));
+    key.insert("word".to_string(), string_attr(word));
+    key
+}
+
+// BatchWriteItem accepts at most this many requests per call.
+const BATCH_SIZE: usize = 25;
+const BATCH_ATTEMPTS: usize = 5;
+
+pub async fn batch_write(requests: Vec<WriteRequest>) -> LambdaResult<()> {
+    batch_write_to(insult_table()?, requests).await
+}
+
+pub async fn batch_write_to(table_name: String, requests: Vec<WriteRequest>) -> LambdaResult<()> {
+    batch_write_with(&dynamo_client(), table_name, requests).await
+}
+
+async fn batch_write_with(client: &impl DynamoDb, table_name: String, requests: Vec<WriteRequest>) -> LambdaResult<()> {
+    for chunk in requests.chunks(BATCH_SIZE) {
+        let mut pending = chunk.to_vec();
+        for attempt in 0.. {
+            let mut request_items = HashMap::new();
+            request_items.insert(table_name.clone(), pending);
+            let input = BatchWriteItemInput { request_items, ..Default::default() };
+            let output = client.batch_write_item(input).await?;
+            pending = output.unprocessed_items
+                .and_then(|mut items| items.remove(&table_name))
+                .unwrap_or_default();
+            if pending.is_empty() {
+                break;
+            }
+            if attempt + 1 >= BATCH_ATTEMPTS {
+                return Err(Box::new(BotError::Dynamo(format!("dynamo left {} writes unprocessed", pending.len()))));
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100 << attempt)).await;
+        }
+    }
+    Ok(())
+}
+
+// Loads the vocabulary into dynamo and the cache, first deleting every existing word if `replace`.
+// Otherwise words we already have are left as they are, keeping who added them, their tags, spice and score.
+// Returns the number of words written.
+pub async fn restore_vocabulary(vocabulary: Vocabulary, replace: bool, user: &str) -> LambdaResult<usize> {
+    let (removed, restored) = restore_items(&DynamoWords, &dynamo_client(), insult_table()?, &vocabulary, replace).await?;
+    if replace {
+        audit::record("removed", &removed, user).await;
+    }
+    let words: Vec<_> = restored.iter().filter_map(item_word).collect();
+    audit::record("restored", &words, user).await;
+
+    let (restored, _) = parse_words(restored);
+    let cache = insult_factory().await?;
+    let mut insults = cache.write().map_err(poisoned("insult cache"))?;
+    if replace {
+        *insults = restored;
+    } else {
+        insults.merge(restored);
+    }
+    Ok(words.len())
+}
+
+// Writes the vocabulary into the table, first clearing out the words `store` has if it's a `replace`.
+// Returns the encoded words removed and the items written.
+async fn restore_items(
+    store: &dyn WordStore, client: &impl DynamoDb, table_name: String, vocabulary: &Vocabulary, replace: bool,
+) -> LambdaResult<(Vec<String>, Vec<HashMap<String, AttributeValue>>)> {
+    let mut removed = Vec::new();
+    if replace {
+        removed = store.items().await?.iter().filter_map(item_word).collect();
+        let deletes = removed.iter()
+            .map(|word| WriteRequest {
+                delete_request: Some(DeleteRequest { key: word_key(word.clone()) }),
+                ..Default::default()
+            })
+            .collect();
+        batch_write_with(client, table_name.clone(), deletes).await?;
+    }
+    let restored = write_vocabulary(client, table_name, vocabulary, replace).await?;
+    Ok((removed, restored))
+}
+
+// Writes the vocabulary's items, returning the ones written. Into an emptied table they all go in batches,
+// and otherwise one at a time, skipping words the table already has.
+async fn write_vocabulary(
+    client: &impl DynamoDb, table_name: String, vocabulary: &Vocabulary, replace: bool,
+) -> LambdaResult<Vec<HashMap<String, AttributeValue>>> {
+    let items = vocabulary.items();
+    if replace {
+        let puts = items.iter()
+            .map(|item| WriteRequest { put_request: Some(PutRequest { item: item.clone() }), ..Default::default() })
+            .collect();
+        batch_write_with(client, table_name, puts).await?;
+        return Ok(items);
+    }
+    let mut written = Vec::new();
+    for item in items {
+        if put_new_item(client, table_name.clone(), item.clone()).await? {
+            written.push(item);
+        }
+    }
+    Ok(written)
+}
+
+// Every message command this module handles, in the order they're tried.
+// Commands match case-insensitively against the normalized text, but user ids in mentions are case-sensitive.
+pub fn commands() -> Vec<Command> {
+    vec![
+        // Before "insult @someone", which would otherwise take it.
+        Command::new(
+            "insult me|@someone creatively", "An insult nobody wrote, made up from the words I know.",
+            r"(?i)\binsult\s+(me|(?-i:<@U\w+>))\s+creatively\b",
+            |event, args, services| Box::pin(handle_creative_insult(event, services, args)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "insult @someone [@someone else…]", "Insult someone, or a whole group.",
+            r"(?i)\binsult((?:\s+(?-i:<@U\w+>))+)",
+            |event, args, services| Box::pin(handle_insult_users(event, services, args)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "give me an insult", "An insult aimed at nobody in particular.",
+            r"(?i)\bgive\s+me\s+an\s+insult\b",
+            |event, _, services| Box::pin(handle_bare_insult(event, services)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "leave me alone", "Stop other people from insulting you.",
+            r"(?i)\bleave\s+me\s+alone\b",
+            |event, _, services| Box::pin(handle_opt_out(event, services.sink)),
+        ),
+        Command::new(
+            "insult me again", "Let people insult you again.",
+            r"(?i)\binsult\s+me\s+again\b",
+            |event, _, services| Box::pin(handle_opt_in(event, services)),
+        ),
+        Command::new(
+            "insult me hard", "Insult yourself, with extra adjectives.",
+            r"(?i)\binsult\s+me\s+hard\b",
+            |event, _, services| {
+                Box::pin(handle_say_insult_with(event, services, to_user_tag(&event.user), HARD_ADJECTIVES))
+            },
+        ).requires(Feature::Insults),
+        Command::new(
+            "insult me quietly", "Insult yourself where only you can see it.",
+            r"(?i)\binsult\s+me\s+quietly\b",
+            |event, _, services| Box::pin(handle_quiet_insult(event, services)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "insult me <tag>", "Insult yourself using only words tagged eg \"sfw\", or untagged ones.",
+            r"(?i)\binsult\s+me\s+(\w+)$",
+            |event, args, services| Box::pin(handle_tagged_insult(event, services, args)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "insult me", "Insult yourself.",
+            r"(?i)\binsult\s+me\b",
+            |event, _, services| Box::pin(handle_say_insult(event, services, to_user_tag(&event.user))),
+        ).requires(Feature::Insults),
+        Command::new(
+            "insult <name>", "Insult someone by their name in Slack, if it's only theirs.",
+            r"(?i)\binsult\s+@?([\w.-]+)$",
+            |event, args, services| Box::pin(handle_insult_by_name(event, services, args)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "use table <name>", "Admins only: read and write words from another table.",
+            r"(?i)^\s*use\s+table\s+([\w.-]+)$",
+            |event, args, services| Box::pin(handle_use_table_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "migrate words", "Admins only: copy INSULT_TABLE's words into WORD_TABLE, keyed by part of speech.",
+            r"(?i)^\s*migrate\s+words$",
+            |event, _, services| Box::pin(handle_migrate_words(event, services.sink)),
+        ).admin_only(),
+    ]
+}
+
+static USER_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<@U\w+>").unwrap());
+
+async fn handle_insult_users(event: &MessageEvent, services: Services<'_>, args: Args) -> LambdaResult<()> {
+    let mut names = mentioned_users(args.get(1));
+    if names.len() == 1 {
+        return handle_say_insult(event, services, names.remove(0)).await;
+    }
+    handle_group_insult(event, services, names).await
+}
+
+// Everyone mentioned in `text`, once each, in order.
+fn mentioned_users(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in USER_TAG_RE.find_iter(text) {
+        if !names.iter().any(|n| n == name.as_str()) {
+            names.push(name.as_str().to_string());
+        }
+    }
+    names
+}
+
+async fn handle_creative_insult(event: &MessageEvent, services: Services<'_>, args: Args) -> LambdaResult<()> {
+    let user_tag = if args.get(1).eq_ignore_ascii_case("me") { to_user_tag(&event.user) } else { args.get(1).to_string() };
+    if is_opted_out(&user_tag, &event.user).await? {
+        return event.reply(services.sink, &format!("{} has opted out.", user_tag)).await;
+    }
+    let generator = Creative { corpus: markov::corpus().await? };
+    let message = match make_insult_with(services.words, &event.channel, Some(&user_tag), &generator).await? {
+        Some(insult) => insult.aimed_at(&user_tag),
+        None => "Shut up.".to_string(),
+    };
+    event.reply(services.sink, &message).await
+}
+
+async fn handle_opt_out(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    opt_out::opt_out(&event.user).await?;
+    let message = "Fine. Nobody else can insult you now. Say \"insult me again\" if you miss it.";
+    event.reply(sink, message).await
+}
+
+async fn handle_opt_in(event: &MessageEvent, services: Services<'_>) -> LambdaResult<()> {
+    opt_out::opt_in(&event.user).await?;
+    handle_say_insult(event, services, to_user_tag(&event.user)).await
+}
+
+async fn handle_quiet_insult(event: &MessageEvent, services: Services<'_>) -> LambdaResult<()> {
+    let message = insult_message(services.words, &event.channel, &to_user_tag(&event.user), 1, None).await?;
+    send_ephemeral(&event.channel, &event.user, &message).await
+}
+
+async fn handle_tagged_insult(event: &MessageEvent, services: Services<'_>, args: Args) -> LambdaResult<()> {
+    let tag = args.get(1).to_lowercase();
+    let message = insult_message(services.words, &event.channel, &to_user_tag(&event.user), 1, Some(&tag)).await?;
+    event.reply(services.sink, &message).await
+}
+
+async fn handle_use_table_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_use_table(event, sink, args.get(1)).await
+}
+
+static PLAIN_WORD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-z]{1,20}$").unwrap());
+
+// Logs what people try to ask the bot for that it doesn't understand, to see what's worth adding.
+// Only messages that @mention the bot up front count; everything else is just channel chatter.
+// To stay out of people's business we only keep the first word, and only if it's a plain word.
+pub async fn log_unmatched_command(text: &str) -> LambdaResult<()> {
+    if !text.trim_start().starts_with("<@") {
+        return Ok(());
+    }
+    if let Some((command, words)) = unmatched_command(text, bot_user_id().await?.as_deref()) {
+        log::info!("{}", serde_json::json!({ "unmatched_command": command, "words": words }));
+    }
+    Ok(())
+}
+
+// The redacted first word of a message @mentioning `bot_user_id` up front, and how many words followed it.
+fn unmatched_command(text: &str, bot_user_id: Option<&str>) -> Option<(String, usize)> {
+    let rest = text.trim_start().strip_prefix(&to_user_tag(bot_user_id?))?;
+    let command = match rest.split_whitespace().next().map(str::to_lowercase) {
+        Some(word) if PLAIN_WORD_RE.is_match(&word) => word,
+        Some(_) => "<redacted>".to_string(),
+        None => "<empty>".to_string(),
+    };
+    Some((command, rest.split_whitespace().count()))
+}
+
+// Adjectives stacked by "insult me hard".
+const HARD_ADJECTIVES: usize = 3;
+
+async fn handle_insult_by_name(event: &MessageEvent, services: Services<'_>, args: Args) -> LambdaResult<()> {
+    let name = args.get(1);
+    match users::find_by_name(name).await? {
+        Some(user) => handle_say_insult(event, services, to_user_tag(&user)).await,
+        None => {
+            let message = format!("I don't know who {} is. Try @mentioning them.", name);
+            event.reply_privately(services.sink, &message).await
+        }
+    }
+}
+
+pub async fn handle_say_insult(event: &MessageEvent, services: Services<'_>, user_tag: String) -> LambdaResult<()> {
+    handle_say_insult_with(event, services, user_tag, 1).await
+}
+
+async fn handle_say_insult_with(
+    event: &MessageEvent, services: Services<'_>, user_tag: String, adjectives: usize,
+) -> LambdaResult<()> {
+    if is_opted_out(&user_tag, &event.user).await? {
+        let message = format!("{} has opted out.", user_tag);
+        return services.sink.post(&event.channel, &message, &event.thread_options()).await.map(|_| ());
+    }
+    let options = event.thread_options();
+    say_insult(services, &event.channel, user_tag.clone(), adjectives, &options).await?;
+    war::score(services.sink, &event.channel, &options, &event.user, &user_tag, adjectives).await;
+    Ok(())
+}
+
+// One insult per person, a line each. The word cooldown keeps them from all getting the same one.
+async fn handle_group_insult(event: &MessageEvent, services: Services<'_>, user_tags: Vec<String>) -> LambdaResult<()> {
+    let mut lines = Vec::new();
+    for user_tag in user_tags {
+        let line = if is_opted_out(&user_tag, &event.user).await? {
+            format!("{} has opted out.", user_tag)
+        } else {
+            insult_message(services.words, &event.channel, &user_tag, 1, None).await?
+        };
+        lines.push(line);
+    }
+    let blocks: Vec<_> = lines.iter().map(|line| Block::section(line)).collect();
+    let options = SendOptions { blocks: Some(blocks::to_value(&blocks)), ..event.reply_options() };
+    services.sink.post(&event.channel, &lines.join("\n"), &options).await.map(|_| ())
+}
+
+// Pinning things is a serious responsibility, and should be treated as such.
+pub async fn handle_pin_added(event: &PinAddedEvent) -> LambdaResult<()> {
+    if !is_human_pin(event, bot_user_id().await?.as_deref()) || opt_out::is_opted_out(&event.user).await? {
+        return Ok(());
+    }
+    if let Some(reason) = router::refusal(&event.channel_id, &event.user, Feature::Insults, "pin").await? {
+        return send_ephemeral(&event.channel_id, &event.user, reason).await;
+    }
+    say_insult(Services::production(), &event.channel_id, to_user_tag(&event.user), 1, &SendOptions::default()).await
+}
+
+// Our own pins, from the pin of the day, don't get anyone insulted.
+fn is_human_pin(event: &PinAddedEvent, bot_user_id: Option<&str>) -> bool {
+    bot_user_id != Some(event.user.as_str())
+}
+
+// Generates an insult for `user_tag` in `channel`, or None if we don't know enough words.
+// Without a `user_tag` it's a bare phrase aimed at nobody. A `tag` like "sfw" limits the words used.
+pub async fn make_insult(
+    words: &dyn WordStore, channel: &str, user_tag: Option<&str>, adjectives: usize, tag: Option<&str>,
+) -> LambdaResult<Option<Insult>> {
+    make_insult_with(words, channel, user_tag, &Classic { adjectives, tag }).await
+}
+
+async fn make_insult_with(
+    words: &dyn WordStore, channel: &str, user_tag: Option<&str>, generator: &dyn Generator,
+) -> LambdaResult<Option<Insult>> {
+    let insults = words.cache().await?;
+    let config = config::get(channel).await?;
+    let max_spice = match user_tag {
+        Some(user_tag) => spice_cap(user_tag)?,
+        None => MAX_SPICE,
+    };
+    let insult = {
+        let mut recent_words = RECENT_WORDS.lock()
+            .map_err(poisoned("recent words"))?;
+        let recent = recent_words.entry(channel.to_string()).or_default();
+        let insults = insults.read()
+            .map_err(poisoned("insult cache"))?;
+        let insult = generate_for(&config, &insults, generator, recent, max_spice);
+        if let Some(insult) = &insult {
+            remember_words(recent, insult);
+        }
+        insult
+    };
+    if let Some(insult) = &insult {
+        metrics::count("InsultsGenerated", 1);
+        insult_stats::record(user_tag, insult).await;
+    }
+    if let (Some(insult), true) = (&insult, insult_log::enabled()) {
+        let template = if user_tag.is_some() { insult.template() } else { "{insult}" };
+        insult_log::record(channel, user_tag, template, insult).await;
+    }
+    Ok(insult)
+}
+
+// Channels with the emoji theme are insulted in emoji instead of words, and the rest in their language.
+fn generate_for(
+    config: &ChannelConfig, insults: &InsultFactory, generator: &dyn Generator, recent: &VecDeque<String>, max_spice: u8,
+) -> Option<Insult> {
+    if config.emoji_theme() {
+        return insults.get_emoji_insult(recent);
+    }
+    generator.generate(insults, grammar::for_code(config.lang.as_deref()), recent, max_spice)
+}
+
+async fn handle_bare_insult(event: &MessageEvent, services: Services<'_>) -> LambdaResult<()> {
+    event.reply(services.sink, &bare_insult(services.words, &event.channel).await?).await
+}
+
+// An insult aimed at nobody in particular, for `channel`.
+pub async fn bare_insult(words: &dyn WordStore, channel: &str) -> LambdaResult<String> {
+    Ok(match make_insult(words, channel, None, 1, None).await? {
+        Some(insult) => insult.to_string(),
+        None => "I'm fresh out.".to_string(),
+    })
+}
+
+pub async fn insult_message(
+    words: &dyn WordStore, channel: &str, user_tag: &str, adjectives: usize, tag: Option<&str>,
+) -> LambdaResult<String> {
+    Ok(match make_insult(words, channel, Some(user_tag), adjectives, tag).await? {
+        Some(insult) => insult.aimed_at(user_tag),
+        None => "Shut up.".to_string(),
+    })
+}
+
+// With "Escape channels, users, and links" on, Slack sends mentions as eg "<@U123|bob>".
+static SLASH_MENTION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<@(U\w+)(?:\|[^>]*)?>").unwrap());
+
+// `/insult` insults whoever ran it, and `/insult @someone` insults them.
+// Returns the immediate response, which Slack posts in the channel.
+pub async fn handle_slash_command(command: &SlashCommand) -> LambdaResult<Value> {
+    if let Some(reason) = router::refusal(&command.channel_id, &command.user_id, Feature::Insults, &command.command).await? {
+        return Ok(json!({ "response_type": "ephemeral", "text": reason }));
+    }
+    let text = command.text.trim();
+    let user_tag = match SLASH_MENTION_RE.captures(text) {
+        Some(caps) => to_user_tag(caps.get(1).unwrap().as_str()),
+        None if text.is_empty() => to_user_tag(&command.user_id),
+        None => match users::find_by_name(text).await? {
+            Some(user) => to_user_tag(&user),
+            None => {
+                let usage = format!("Try `{}` or `{} @someone`.", command.command, command.command);
+                return Ok(json!({ "response_type": "ephemeral", "text": usage }));
+            },
+        },
+    };
+    if is_opted_out(&user_tag, &command.user_id).await? {
+        let message = format!("{} has opted out.", user_tag);
+        return Ok(json!({ "response_type": "ephemeral", "text": message }));
+    }
+    let message = insult_message(&DynamoWords, &command.channel_id, &user_tag, 1, None).await?;
+    Ok(json!({ "response_type": "in_channel", "text": message }))
+}
+
+async fn say_insult(
+    services: Services<'_>, channel: &str, user_tag: String, adjectives: usize, options: &SendOptions,
+) -> LambdaResult<()> {
+    // A cold scan can take a moment, so let them know we're working on it.
+    // Once the words are cached we answer fast enough that this would just be noise.
+    let thinking = env_flag("SHOW_THINKING") && !factory_loaded();
+    let message = insult_message(services.words, channel, &user_tag, adjectives, None);
+    say_after_thinking(services.sink, channel, thinking, message, &user_tag, options).await
+}
+
+// Posts THINKING if `thinking`, and then `message` in its place once it's ready.
+async fn say_after_thinking(
+    sink: &dyn MessageSink, channel: &str, thinking: bool, message: impl Future<Output = LambdaResult<String>>,
+    user_tag: &str, options: &SendOptions,
+) -> LambdaResult<()> {
+    let thinking = if thinking {
+        sink.post(channel, THINKING, options).await?
+    } else { None };
+
+    let message = message.await?;
+
+    match thinking {
+        Some(ts) => sink.update(channel, &ts, &message).await,
+        None if interactive::enabled() => {
+            let blocks = [
+                Block::section(&message),
+                Block::actions(vec![Button::new("Insult again", interactive::INSULT_AGAIN, user_tag)]),
+            ];
+            let options = SendOptions { blocks: Some(blocks::to_value(&blocks)), ..options.clone() };
+            sink.post(channel, &message, &options).await.map(|_| ())
+        },
+        None => sink.post(channel, &message, options).await.map(|_| ()),
+    }
+}
+
+// The "Insult again" button, clicked by `user`. Returns the response message.
+pub async fn handle_insult_again(channel: &str, user: &str, user_tag: &str) -> LambdaResult<Value> {
+    if from_user_tag(user_tag).is_none() {
+        return Ok(json!({ "response_type": "ephemeral", "replace_original": false, "text": "Who?" }));
+    }
+    if let Some(reason) = router::refusal(channel, user, Feature::Insults, interactive::INSULT_AGAIN).await? {
+        return Ok(json!({ "response_type": "ephemeral", "replace_original": false, "text": reason }));
+    }
+    if is_opted_out(user_tag, user).await? {
+        let message = format!("{} has opted out.", user_tag);
+        return Ok(json!({ "response_type": "ephemeral", "replace_original": false, "text": message }));
+    }
+    let message = insult_message(&DynamoWords, channel, user_tag, 1, None).await?;
+    Ok(json!({ "response_type": "in_channel", "replace_original": false, "text": message }))
+}
+
+pub fn insert_word_to_cache(
+    cache: &RwLock<InsultFactory>, pos: &PartOfSpeech, insult: String, tag: Option<&str>,
+) -> LambdaResult<bool> {
+    let mut insults = match cache.write() {
+        Ok(i) => i,
+        _ => return Err(Box::new(BotError::Poisoned("insult cache"))),
+    };
+    if !insults.insert_word(pos, insult.clone()) {
+        return Ok(false);
+    }
+    if let Some(tag) = tag {
+        insults.tag_word(pos, &insult, tag);
+    }
+    Ok(true)
+}
+
+// Adds the word to the cache and persists it to dynamo.
+// Returns false without persisting if we or dynamo already had the word.
+pub async fn add_word(pos: &PartOfSpeech, insult: String, added_by: &str, tag: Option<&str>) -> LambdaResult<bool> {
+    add_word_to(&DynamoWords, pos, insult, added_by, tag).await
+}
+
+async fn add_word_to(
+    store: &dyn WordStore, pos: &PartOfSpeech, insult: String, added_by: &str, tag: Option<&str>,
+) -> LambdaResult<bool> {
+    let cache = insult_factory().await?;
+    if !insert_word_to_cache(cache, pos, insult.clone(), tag)? {
+        return Ok(false);
+    }
+    match store.insert(encode_word(pos, insult.clone()), added_by, tag).await {
+        Ok(inserted) => {
+            if inserted {
+                metrics::count("WordsAdded", 1);
+                audit::record("added", &[encode_word(pos, insult.clone())], added_by).await;
+                remember_added(added_by, encode_word(pos, insult)).await;
+            }
+            Ok(inserted)
+        },
+        Err(e) => {
+            // Don't offer up a word that never made it to dynamo.
+            cache.write()
+                .map_err(poisoned("insult cache"))?
+                .remove_word(pos, &insult);
+            Err(e.into())
+        },
+    }
+}
+
+// The word's already saved by now, so failing to remember it only costs an "undo".
+pub async fn remember_added(user: &str, word: String) {
+    if let Err(e) = last_added::record(user, word).await {
+        log::error!("Error recording {}'s last added word: {}", user, e);
+    }
+}
+
+// What happened to each word `insert_words` was given.
+#[derive(Debug, Default)]
+pub struct InsertOutcome {
+    pub added: Vec<String>,
+    pub duplicates: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+// Saves each word with its own conditional put, so a word already in dynamo, but not yet in this
+// container's cache, is turned away as a duplicate instead of having its added_by, tags and score written over.
+pub async fn insert_words(
+    store: &dyn WordStore, cache: &RwLock<InsultFactory>, pos: &PartOfSpeech, words: Vec<String>, user: &str,
+    tag: Option<&str>,
+) -> LambdaResult<InsertOutcome> {
+    let mut outcome = InsertOutcome::default();
+    for word in words {
+        if !insert_word_to_cache(cache, pos, word.clone(), tag)? {
+            outcome.duplicates.push(word);
+            continue;
+        }
+        match store.insert(encode_word(pos, word.clone()), user, tag).await {
+            Ok(true) => outcome.added.push(word),
+            // It's in dynamo, so it can stay in the cache.
+            Ok(false) => outcome.duplicates.push(word),
+            Err(e) => {
+                log::error!("Error adding word: {}", e);
+                // Don't offer up a word that never made it to dynamo.
+                cache.write()
+                    .map_err(poisoned("insult cache"))?
+                    .remove_word(pos, &word);
+                outcome.failed.push(word);
+            },
+        }
+    }
+    Ok(outcome)
+}
+
+// Reacting to a message with TRIGGER_EMOJI (eg "clown_face") insults whoever posted it, in a thread on the message.
+pub async fn handle_trigger_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    let trigger = match std::env::var("TRIGGER_EMOJI") {
+        Ok(trigger) if !trigger.is_empty() => trigger,
+        _ => return Ok(()),
+    };
+    if event.reaction.split("::").next() != Some(trigger.as_str()) {
+        return Ok(());
+    }
+    let (channel, ts, author) = match (&event.item, &event.item_user) {
+        (ReactionItem::Message { channel, ts }, Some(author)) => (channel, ts, author),
+        _ => return Ok(()),
+    };
+    if bot_user_id().await?.as_deref() == Some(author.as_str()) || opt_out::is_opted_out(author).await? {
+        return Ok(());
+    }
+    if let Some(reason) = router::refusal(channel, &event.user, Feature::Insults, &trigger).await? {
+        return send_ephemeral(channel, &event.user, reason).await;
+    }
+    let options = SendOptions { thread_ts: Some(ts.clone()), ..Default::default() };
+    say_insult(Services::production(), channel, to_user_tag(author), 1, &options).await
+}
+
+// "use table default" goes back to INSULT_TABLE.
+// The function's IAM policy has to grant access to the other table for this to work.
+async fn handle_use_table(event: &MessageEvent, sink: &dyn MessageSink, table_name: &str) -> LambdaResult<()> {
+    let previous = TABLE_OVERRIDE.read()
+        .map_err(poisoned("table override"))?
+        .clone();
+    let table_override = match table_name {
+        "default" => None,
+        _ => Some(table_name.to_string()),
+    };
+    set_table_override(table_override)?;
+
+    // Swap the cached words for the new table's, so reads agree with writes.
+    let cache = insult_factory().await?;
+    let insults = match fetch_insults(&DynamoWords).await {
+        Ok(insults) => insults,
+        Err(e) => {
+            set_table_override(previous)?;
+            let message = format!("Couldn't read from `{}`, staying put: {}", table_name, e);
+            return event.reply(sink, &message).await;
+        },
+    };
+    *cache.write().map_err(poisoned("insult cache"))? = insults;
+
+    let message = format!(
+        "Using table `{}` in this container until it goes cold. Other containers are unaffected.",
+        insult_table()?,
+    );
+    event.reply(sink, &message).await
+}
+
+// Copies every word from INSULT_TABLE into WORD_TABLE, keeping who added it, its spice, its tags, and its score.
+// Safe to run again: words already in WORD_TABLE are overwritten with the same thing.
+async fn handle_migrate_words(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let client = dynamo_client();
+    let input = ScanInput { table_name: env_var("INSULT_TABLE")?, ..Default::default() };
+    let mut skipped = 0;
+    let mut puts = Vec::new();
+    for mut item in scan_all(&client, input).await? {
+        let (pos, word) = match item_word(&item).and_then(decode_word) {
+            Some(decoded) => decoded,
+            None => {
+                skipped += 1;
+                continue;
+            },
+        };
+        item.extend(pos_word_key(&pos, word));
+        puts.push(WriteRequest { put_request: Some(PutRequest { item }), ..Default::default() });
+    }
+    let count = puts.len();
+    let table_name = env_var("WORD_TABLE")?;
+    batch_write_to(table_name.clone(), puts).await?;
+
+    let mut message = format!("Copied {} words into `{}`.", count, table_name);
+    if skipped > 0 {
+        message.push_str(&format!(" Skipped {} malformed items.", skipped));
+    }
+    if !pos_keyed() {
+        message.push_str(" Set POS_KEYED_WORDS to start using it.");
+    }
+    event.reply(sink, &message).await
+}
+
+// Most words "remove matching" will delete at once, in case of an overly broad pattern.
+const REMOVE_MATCHING_CAP: usize = 50;
+const REMOVE_MATCHING_PREVIEW: usize = 10;
+
+// Deletes the words in `cache` that match `pattern` from the table and the cache, once it's `confirmed`.
+// Returns the words removed, or else what to tell the admin.
+pub async fn remove_matching(
+    client: &impl DynamoDb, table_name: String, cache: &RwLock<InsultFactory>, pattern: &str, confirmed: bool,
+) -> LambdaResult<Result<Vec<(PartOfSpeech, String)>, String>> {
+    let matcher = match WordMatcher::parse(pattern) {
+        Ok(matcher) => matcher,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    let matches = cache.read()
+        .map_err(poisoned("insult cache"))?
+        .matching(&matcher);
+
+    if matches.is_empty() {
+        return Ok(Err("Nothing matches that.".to_string()));
+    }
+    if matches.len() > REMOVE_MATCHING_CAP {
+        return Ok(Err(format!(
+            "That matches {} words, and I won't remove more than {} at once. Try something narrower.",
+            matches.len(), REMOVE_MATCHING_CAP,
+        )));
+    }
+    if !confirmed {
+        let mut preview: Vec<_> = matches.iter()
+            .take(REMOVE_MATCHING_PREVIEW)
+            .map(|(_, word)| format!("\"{}\"", word))
+            .collect();
+        if matches.len() > REMOVE_MATCHING_PREVIEW {
+            preview.push(format!("and {} more", matches.len() - REMOVE_MATCHING_PREVIEW));
+        }
+        return Ok(Err(format!(
+            "That matches {} words: {}. Say `remove matching {} confirm` to delete them.",
+            matches.len(), preview.join(", "), pattern,
+        )));
+    }
+
+    let deletes = matches.iter()
+        .map(|(pos, word)| WriteRequest {
+            delete_request: Some(DeleteRequest { key: word_key(encode_word(pos, word.clone())) }),
+            ..Default::default()
+        })
+        .collect();
+    batch_write_with(client, table_name, deletes).await?;
+    let mut insults = cache.write()
+        .map_err(poisoned("insult cache"))?;
+    for (pos, word) in &matches {
+        insults.remove_word(pos, word);
+    }
+    Ok(Ok(matches))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusoto_core::Region;
+    use rusoto_dynamodb::DynamoDbClient;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher, MultipleMockRequestDispatcher};
+
+    use super::*;
+    use crate::dynamo::mock::recording_client;
+    use crate::tests::RecordingSink;
+
+    // Encoded words, some already stored, and some that fail to save, with a cache starting out with the stored ones.
+    struct FakeStore {
+        stored: Mutex<HashSet<String>>,
+        broken: HashSet<String>,
+        cache: RwLock<InsultFactory>,
+    }
+
+    impl FakeStore {
+        fn new(stored: &[&str], broken: &[&str]) -> Self {
+            FakeStore {
+                stored: Mutex::new(stored.iter().map(|word| word.to_string()).collect()),
+                broken: broken.iter().map(|word| word.to_string()).collect(),
+                cache: cache_of(stored),
+            }
+        }
+    }
+
+    impl WordStore for FakeStore {
+        fn items(&self) -> StoreFuture<'_, Vec<HashMap<String, AttributeValue>>> {
+            let items = self.stored.lock().unwrap().iter()
+                .map(|word| std::iter::once(("word".to_string(), string_attr(word.clone()))).collect())
+                .collect();
+            Box::pin(async move { Ok(items) })
+        }
+
+        fn insert<'a>(&'a self, word: String, _: &'a str, _: Option<&'a str>) -> StoreFuture<'a, bool> {
+            Box::pin(async move {
+                if self.broken.contains(&word) {
+                    return Err(BotError::Dynamo("throttled".to_string()));
+                }
+                Ok(self.stored.lock().unwrap().insert(word))
+            })
+        }
+
+        fn delete(&self, word: String) -> StoreFuture<'_, bool> {
+            Box::pin(async move { Ok(self.stored.lock().unwrap().remove(&word)) })
+        }
+
+        fn cache(&self) -> StoreFuture<'_, &RwLock<InsultFactory>> {
+            Box::pin(async move { Ok(&self.cache) })
+        }
+    }
+
+    fn item_for(word: &str, attrs: &[(&str, AttributeValue)]) -> HashMap<String, AttributeValue> {
+        let mut item = word_key(word.to_string());
+        item.extend(attrs.iter().map(|(name, value)| (name.to_string(), value.clone())));
+        item
+    }
+
+    fn sorted(mut items: Vec<HashMap<String, AttributeValue>>) -> Vec<HashMap<String, AttributeValue>> {
+        items.sort_by_key(item_word);
+        items
+    }
+
+    #[test]
+    fn vocabulary_round_trips_details() {
+        let items = vec![
+            item_for("clownn", &[
+                ("added_by", string_attr("U1".to_string())),
+                ("added", number_attr(1600000000)),
+                ("tags", string_set_attr(vec!["work".to_string()])),
+                ("spice", number_attr(3)),
+                ("score", AttributeValue { n: Some("2.5".to_string()), ..Default::default() }),
+                ("scored", number_attr(1600000100)),
+            ]),
+            item_for("smellya", &[]),
+            item_for("clown_facee", &[("added_by", string_attr("U2".to_string()))]),
+        ];
+        let vocabulary = Vocabulary::from_items(items.clone());
+        assert_eq!(vocabulary.nouns, ["clown"]);
+        assert_eq!(vocabulary.emoji, ["clown_face"]);
+        let backup = serde_json::to_vec(&vocabulary).unwrap();
+        let restored: Vocabulary = serde_json::from_slice(&backup).unwrap();
+        assert_eq!(sorted(restored.items()), sorted(items));
+    }
+
+    #[test]
+    fn old_backups_restore_bare_words() {
+        let restored: Vocabulary = serde_json::from_str(r#"{ "nouns": ["clown", "clown"], "adjectives": [] }"#).unwrap();
+        assert_eq!(restored.items(), [word_key("clownn".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn merging_vocabulary_skips_words_already_stored() {
+        let conditional_check_failed = r#"{
+            "__type": "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException",
+            "message": "The conditional request failed"
+        }"#;
+        let dispatcher = MultipleMockRequestDispatcher::new(vec![
+            MockRequestDispatcher::with_status(400).with_body(conditional_check_failed),
+            MockRequestDispatcher::with_status(200).with_body("{}"),
+        ]);
+        let client = DynamoDbClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1);
+        let vocabulary = Vocabulary::from_items(vec![item_for("clownn", &[]), item_for("smellya", &[])]);
+        // Items go in order of their encoded word, so "clownn" is first.
+        let written = write_vocabulary(&client, "words".to_string(), &vocabulary, false).await.unwrap();
+        assert_eq!(written, [item_for("smellya", &[])]);
+    }
+
+    #[tokio::test]
+    async fn replacing_clears_the_table_first() {
+        let (client, requests) = recording_client(200, "{}");
+        let store = FakeStore::new(&["clownn", "uglya"], &[]);
+        let vocabulary = Vocabulary::from_items(vec![item_for("buffoonn", &[])]);
+        let (removed, restored) = restore_items(&store, &client, "words".to_string(), &vocabulary, true).await.unwrap();
+        assert_eq!(sorted_words(removed), ["clownn", "uglya"]);
+        assert_eq!(restored, [item_for("buffoonn", &[])]);
+
+        let requests = requests.lock().unwrap();
+        let deleted: Vec<_> = requests[0]["RequestItems"]["words"].as_array().unwrap().iter()
+            .map(|request| request["DeleteRequest"]["Key"]["word"]["S"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(sorted_words(deleted), ["clownn", "uglya"]);
+        assert_eq!(requests[1]["RequestItems"]["words"][0]["PutRequest"]["Item"]["word"]["S"], "buffoonn");
+        assert_eq!(requests.len(), 2);
+    }
+
+    fn sorted_words(mut words: Vec<String>) -> Vec<String> {
+        words.sort();
+        words
+    }
+
+    #[tokio::test]
+    async fn batch_write_sends_at_most_25_a_request() {
+        let (client, requests) = recording_client(200, "{}");
+        let puts = (0..60)
+            .map(|i| WriteRequest {
+                put_request: Some(PutRequest { item: word_key(format!("clown{}n", i)) }),
+                ..Default::default()
+            })
+            .collect();
+        batch_write_with(&client, "words".to_string(), puts).await.unwrap();
+        let sizes: Vec<_> = requests.lock().unwrap().iter()
+            .map(|request| request["RequestItems"]["words"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(sizes, [25, 25, 10]);
+    }
+
+    #[tokio::test]
+    async fn insert_words_reports_duplicates_and_failures() {
+        let store = FakeStore::new(&["buffoonn"], &["jestern"]);
+        let cache = RwLock::new(InsultFactory::new(vec!["clown".to_string()], Vec::new()));
+        let words = ["clown", "buffoon", "jester", "oaf"].iter().map(|word| word.to_string()).collect();
+        let outcome = insert_words(&store, &cache, &PartOfSpeech::Noun, words, "U1", None).await.unwrap();
+        assert_eq!(outcome.added, ["oaf"]);
+        // "clown" was cached, but "buffoon" only turned up in the store.
+        assert_eq!(outcome.duplicates, ["clown", "buffoon"]);
+        assert_eq!(outcome.failed, ["jester"]);
+        let cache = cache.read().unwrap();
+        assert!(cache.has_word(&PartOfSpeech::Noun, "buffoon"));
+        assert!(!cache.has_word(&PartOfSpeech::Noun, "jester"));
+    }
+
+    #[tokio::test]
+    async fn put_new_item_tells_duplicates_from_failures() {
+        let (client, requests) = recording_client(200, "{}");
+        assert!(put_new_item(&client, "words".to_string(), item_for("clownn", &[])).await.unwrap());
+        assert_eq!(requests.lock().unwrap()[0]["ConditionExpression"], "attribute_not_exists(word)");
+
+        let duplicate = r#"{"__type":"com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException","message":"exists"}"#;
+        let (client, _) = recording_client(400, duplicate);
+        assert!(!put_new_item(&client, "words".to_string(), item_for("clownn", &[])).await.unwrap());
+
+        let throttled = r#"{"__type":"com.amazonaws.dynamodb.v20120810#ProvisionedThroughputExceededException","message":"slow down"}"#;
+        let (client, _) = recording_client(400, throttled);
+        assert!(put_new_item(&client, "words".to_string(), item_for("clownn", &[])).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn thinking_posts_then_updates_the_same_message() {
+        let sink = RecordingSink::default();
+        let message = async { Ok("<@U1> is a clown".to_string()) };
+        say_after_thinking(&sink, "C1", true, message, "<@U1>", &SendOptions::default()).await.unwrap();
+        assert_eq!(*sink.sent.lock().unwrap(), [format!("post C1 {}", THINKING), "update C1 1 <@U1> is a clown".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn without_thinking_the_insult_is_posted_once() {
+        let sink = RecordingSink::default();
+        let message = async { Ok("<@U1> is a clown".to_string()) };
+        say_after_thinking(&sink, "C1", false, message, "<@U1>", &SendOptions::default()).await.unwrap();
+        assert_eq!(*sink.sent.lock().unwrap(), ["post C1 <@U1> is a clown"]);
+    }
+
+    #[test]
+    fn recent_words_are_avoided_until_they_age_out() {
+        let pool = ["clown", "fool"];
+        let mut recent = VecDeque::new();
+        remember_words(&mut recent, &Insult::Emoji(vec!["clown".to_string()]));
+        for _ in 0..50 {
+            assert_eq!(choose_fresh(&pool, &recent, &|_| 1.0), Some("fool"));
+        }
+        // Enough other words to push "clown" out of the last WORD_COOLDOWN insults' worth, "fool" among them.
+        let others: Vec<_> = (1..cooldown_insults() * 2).map(|i| format!("word{}", i)).chain(["fool".to_string()]).collect();
+        remember_words(&mut recent, &Insult::Emoji(others));
+        assert!(!recent.iter().any(|word| word == "clown"));
+        for _ in 0..50 {
+            assert_eq!(choose_fresh(&pool, &recent, &|_| 1.0), Some("clown"));
+        }
+    }
+
+    #[test]
+    fn pins_by_people_get_them_insulted_but_ours_dont() {
+        let pin = |user: &str| -> PinAddedEvent {
+            serde_json::from_value(json!({
+                "type": "pin_added",
+                "user": user,
+                "channel_id": "C1",
+                "item": { "type": "message", "channel": "C1", "message": { "ts": "1.2", "text": "hi" } },
+                "event_ts": "1.3",
+            })).unwrap()
+        };
+        assert!(is_human_pin(&pin("U1"), Some("UBOT")));
+        assert!(is_human_pin(&pin("U1"), None));
+        assert!(!is_human_pin(&pin("UBOT"), Some("UBOT")));
+    }
+
+    // Along with a missing INSULT_TABLE, in one test since both change what insult_table reads.
+    #[tokio::test]
+    async fn an_overridden_table_is_used_until_it_goes_back_to_default() {
+        let _globals = crate::tests::GLOBALS.lock().await;
+        let original = std::env::var("INSULT_TABLE");
+        set_table_override(Some("staging".to_string())).unwrap();
+        let (client, requests) = recording_client(200, r#"{"Items": []}"#);
+        scan_items_from(&client).await.unwrap();
+        set_table_override(None).unwrap();
+        assert_eq!(requests.lock().unwrap()[0]["TableName"], "staging");
+
+        std::env::remove_var("INSULT_TABLE");
+        assert!(matches!(insult_table(), Err(BotError::MissingEnv(name)) if name == "INSULT_TABLE"));
+        let (client, requests) = recording_client(200, r#"{"Items": []}"#);
+        assert!(matches!(scan_items_from(&client).await, Err(BotError::MissingEnv(_))));
+        assert!(requests.lock().unwrap().is_empty());
+
+        std::env::set_var("INSULT_TABLE", "insults");
+        assert_eq!(insult_table().unwrap(), "insults");
+        match original {
+            Ok(table_name) => std::env::set_var("INSULT_TABLE", table_name),
+            Err(_) => std::env::remove_var("INSULT_TABLE"),
+        }
+    }
+
+    #[tokio::test]
+    async fn opted_out_targets_can_still_insult_themselves() {
+        std::env::set_var("OPT_OUT_TABLE", "opt_out");
+        let (client, requests) = recording_client(200, r#"{"Item": {"user": {"S": "U2"}}}"#);
+        assert!(is_opted_out_in(&client, "<@U2>", "U1").await.unwrap());
+        assert!(!is_opted_out_in(&client, "<@U2>", "U2").await.unwrap());
+        // Only asking about someone else needs the table.
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(requests.lock().unwrap()[0]["Key"], json!({ "user": { "S": "U2" } }));
+
+        let (client, _) = recording_client(200, "{}");
+        assert!(!is_opted_out_in(&client, "<@U3>", "U1").await.unwrap());
+    }
+
+    fn cache_of(words: &[&str]) -> RwLock<InsultFactory> {
+        let (factory, _) = parse_words(words.iter().map(|word| item_for(word, &[])).collect());
+        RwLock::new(factory)
+    }
+
+    #[tokio::test]
+    async fn remove_matching_previews_then_deletes() {
+        let cache = cache_of(&["clownn", "clodn", "goblinn", "smellya"]);
+        let (client, requests) = recording_client(200, "{}");
+        let preview = remove_matching(&client, "words".to_string(), &cache, "clo", false).await.unwrap().unwrap_err();
+        assert!(preview.starts_with("That matches 2 words: "), "{}", preview);
+        assert!(requests.lock().unwrap().is_empty());
+
+        let mut removed = remove_matching(&client, "words".to_string(), &cache, "clo", true).await.unwrap().unwrap();
+        removed.sort_by(|(_, a), (_, b)| a.cmp(b));
+        assert_eq!(removed.iter().map(|(_, word)| word.as_str()).collect::<Vec<_>>(), ["clod", "clown"]);
+        let requests = requests.lock().unwrap();
+        let deleted: Vec<_> = requests[0]["RequestItems"]["words"].as_array().unwrap().iter()
+            .map(|request| request["DeleteRequest"]["Key"]["word"]["S"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(sorted_words(deleted), ["clodn", "clownn"]);
+        let cache = cache.read().unwrap();
+        assert!(!cache.has_word(&PartOfSpeech::Noun, "clown"));
+        assert!(cache.has_word(&PartOfSpeech::Noun, "goblin"));
+    }
+
+    #[tokio::test]
+    async fn remove_matching_refuses_more_than_the_cap() {
+        let words: Vec<_> = (0..=REMOVE_MATCHING_CAP).map(|i| format!("clown{}n", i)).collect();
+        let cache = cache_of(&words.iter().map(String::as_str).collect::<Vec<_>>());
+        let (client, requests) = recording_client(200, "{}");
+        let refusal = remove_matching(&client, "words".to_string(), &cache, "clown", true).await.unwrap().unwrap_err();
+        assert!(refusal.contains("I won't remove more than 50"), "{}", refusal);
+        assert!(requests.lock().unwrap().is_empty());
+        assert_eq!(cache.read().unwrap().nouns.len(), REMOVE_MATCHING_CAP + 1);
+    }
+
+    #[test]
+    fn rapid_insults_get_milder() {
+        let rated = [("nastya", 3), ("rudea", 2), ("meeka", 1), ("bruten", 3), ("oafn", 2), ("lambn", 1)];
+        let items = rated.iter().map(|(word, spice)| item_for(word, &[("spice", number_attr(*spice))])).collect();
+        let (factory, _) = parse_words(items);
+        let spice_of = |word: &str| rated.iter()
+            .find(|(encoded, _)| encoded[..encoded.len() - 1] == *word)
+            .map(|(_, spice)| *spice as u8)
+            .unwrap();
+
+        let mut times = VecDeque::new();
+        let caps: Vec<_> = (0..6).map(|i| decayed_spice(&mut times, 1_000 + i)).collect();
+        assert_eq!(caps, [3, 3, 2, 2, 1, 1]);
+        for cap in caps {
+            for _ in 0..20 {
+                let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), cap, 1, None).unwrap();
+                assert!(insult.words().iter().all(|word| spice_of(word) <= cap), "{} over {}", insult.words().join(" "), cap);
+            }
+        }
+        // Once the window's passed they're back to full strength.
+        assert_eq!(decayed_spice(&mut times, 1_006 + decay_window()), MAX_SPICE);
+    }
+
+    #[test]
+    fn bare_insults_are_just_the_phrase() {
+        let (factory, _) = parse_words(vec![item_for("dumba", &[]), item_for("clownn", &[])]);
+        let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert_eq!(insult.to_string(), "a dumb clown");
+        let (factory, _) = parse_words(vec![item_for("uglya", &[]), item_for("ogren", &[])]);
+        let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert_eq!(insult.to_string(), "an ugly ogre");
+
+        let (empty, _) = parse_words(Vec::new());
+        assert!(empty.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).is_none());
+    }
+
+    #[test]
+    fn emoji_channels_get_a_string_of_shortcodes() {
+        let words = ["clown_facee", "poope", "skulle", "dumba", "clownn"];
+        let (factory, _) = parse_words(words.iter().map(|word| item_for(word, &[])).collect());
+        let classic = Classic { adjectives: 1, tag: None };
+        let emoji = ChannelConfig { theme: Some("emoji".to_string()), ..Default::default() };
+        let insult = generate_for(&emoji, &factory, &classic, &VecDeque::new(), MAX_SPICE).unwrap().to_string();
+        let shortcodes: Vec<_> = insult.split(' ').map(str::to_string).collect();
+        assert_eq!(sorted_words(shortcodes), [":clown_face:", ":poop:", ":skull:"]);
+
+        let insult = generate_for(&ChannelConfig::default(), &factory, &classic, &VecDeque::new(), MAX_SPICE).unwrap();
+        assert_eq!(insult.to_string(), "a dumb clown");
+    }
+
+    #[test]
+    fn one_sided_pools_still_insult() {
+        let (adjectives_only, _) = parse_words(vec![item_for("dumba", &[])]);
+        let insult = adjectives_only.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert_eq!(insult.aimed_at("<@U1>"), "<@U1> is so dumb");
+        let (nouns_only, _) = parse_words(vec![item_for("clownn", &[])]);
+        let insult = nouns_only.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert_eq!(insult.aimed_at("<@U1>"), "<@U1> is an absolute clown");
+    }
+
+    #[test]
+    fn only_misses_aimed_at_us_are_counted() {
+        let bot = Some("UBOT");
+        assert_eq!(unmatched_command("<@UBOT> roast <@U1>", bot), Some(("roast".to_string(), 2)));
+        assert_eq!(unmatched_command("<@UBOT> 1234 secret stuff", bot), Some(("<redacted>".to_string(), 3)));
+        assert_eq!(unmatched_command("<@UBOT>", bot), Some(("<empty>".to_string(), 0)));
+        assert_eq!(unmatched_command("roast <@UBOT>", bot), None);
+        assert_eq!(unmatched_command("<@U1> roast them", bot), None);
+        assert_eq!(unmatched_command("lunch anyone?", bot), None);
+        assert_eq!(unmatched_command("<@UBOT> roast", None), None);
+    }
+
+    #[test]
+    fn words_are_keyed_with_their_suffix() {
+        assert_eq!(encode_word(&PartOfSpeech::Noun, "clown".to_string()), "clownn");
+        assert_eq!(encode_word(&PartOfSpeech::Adjective, "smelly".to_string()), "smellya");
+        assert_eq!(encode_word(&PartOfSpeech::Emoji, "poop".to_string()), "poope");
+        let key = word_key(encode_word(&PartOfSpeech::Noun, "clown".to_string()));
+        assert_eq!(key.len(), 1);
+        assert_eq!(key["word"].s.as_deref(), Some("clownn"));
+        let key = pos_word_key(&PartOfSpeech::Adjective, "smelly".to_string());
+        assert_eq!(key["pos"].s.as_deref(), Some("adjective"));
+        assert_eq!(key["word"].s.as_deref(), Some("smelly"));
+    }
+
+    #[test]
+    fn added_words_come_up_straight_away() {
+        let cache = cache_of(&["dumba"]);
+        let before = cache.read().unwrap().get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None);
+        assert_eq!(before.unwrap().to_string(), "so dumb");
+
+        assert!(insert_word_to_cache(&cache, &PartOfSpeech::Noun, "clown".to_string(), None).unwrap());
+        let insults = cache.read().unwrap();
+        let after = insults.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None);
+        assert_eq!(after.unwrap().to_string(), "a dumb clown");
+        drop(insults);
+        assert!(!insert_word_to_cache(&cache, &PartOfSpeech::Noun, "clown".to_string(), None).unwrap());
+    }
+
+    #[test]
+    fn stacked_adjectives_differ_and_take_the_first_ones_article() {
+        let (factory, _) = parse_words(["uglya", "smellya", "dumba", "oafisha", "clownn"].iter()
+            .map(|word| item_for(word, &[])).collect());
+        for _ in 0..20 {
+            let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 3, None).unwrap();
+            let adjectives = match &insult {
+                Insult::Phrase { adjectives, .. } => adjectives.clone(),
+                _ => panic!("{} isn't a phrase", insult),
+            };
+            assert_eq!(adjectives.iter().collect::<HashSet<_>>().len(), 3, "{:?}", adjectives);
+            let article = if "aeiou".contains(&adjectives[0][..1]) { "an" } else { "a" };
+            assert_eq!(insult.to_string(), format!("{} {} clown", article, adjectives.join(", ")));
+        }
+    }
+
+    #[test]
+    fn malformed_words_are_discarded() {
+        let no_word: HashMap<_, _> = std::iter::once(("added_by".to_string(), string_attr("U1".to_string()))).collect();
+        let mut items = vec![no_word];
+        items.extend(["", "clownx", "clownn", "dumba"].iter().map(|word| item_for(word, &[])));
+        let (factory, discarded) = parse_words(items);
+        assert_eq!(discarded, 3);
+        assert_eq!(factory.nouns, ["clown"]);
+        assert_eq!(factory.adjectives, ["dumb"]);
+    }
+
+    #[test]
+    fn an_empty_factory_has_no_insult() {
+        let factory = InsultFactory::new(Vec::new(), Vec::new());
+        assert!(factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).is_none());
+        assert!(factory.get_emoji_insult(&VecDeque::new()).is_none());
+        let (factory, discarded) = parse_words(Vec::new());
+        assert_eq!(discarded, 0);
+        assert!(factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).is_none());
+    }
+
+    #[test]
+    fn groups_get_an_insult_each() {
+        let (factory, _) = parse_words(["dumba", "smellya", "uglya", "clownn", "ogren", "goblinn"].iter()
+            .map(|word| item_for(word, &[])).collect());
+        for (text, count) in [("insult <@U1> <@U2>", 2), ("insult <@U1> <@U2> <@U3>", 3), ("insult <@U1> <@U2> <@U1> <@U3>", 3)] {
+            let (syntax, args) = crate::COMMANDS.parse(text).unwrap();
+            assert_eq!(syntax, "insult @someone [@someone else…]");
+            let users = mentioned_users(args.get(1));
+            assert_eq!(users.len(), count, "{}", text);
+
+            // As the group insult does, each one avoiding the words the last ones used.
+            let mut recent = VecDeque::new();
+            let insults: Vec<_> = users.iter().map(|user| {
+                let insult = factory.get_insult_with(&grammar::English, &recent, MAX_SPICE, 1, None).unwrap();
+                remember_words(&mut recent, &insult);
+                insult.aimed_at(user)
+            }).collect();
+            assert_eq!(insults.len(), count);
+            let phrases: HashSet<_> = insults.iter().map(|line| line.split_once(' ').unwrap().1).collect();
+            assert_eq!(phrases.len(), count, "{:?}", insults);
+        }
+    }
+
+    #[test]
+    fn the_bundled_words_make_insults() {
+        let factory = default_insults();
+        assert!(factory.nouns.contains(&"clown".to_string()));
+        assert!(!factory.adjectives.is_empty());
+        let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert!(matches!(insult, Insult::Phrase { .. }), "{}", insult);
+    }
+
+    #[test]
+    fn counts_are_of_each_part_of_speech() {
+        let cache = cache_of(&["clownn", "ogren", "goblinn", "dumba", "smellya", "poope"]);
+        assert_eq!((cache.read().unwrap().noun_count(), cache.read().unwrap().adjective_count()), (3, 2));
+        assert!(insert_word_to_cache(&cache, &PartOfSpeech::Adjective, "ugly".to_string(), None).unwrap());
+        cache.write().unwrap().remove_word(&PartOfSpeech::Noun, "ogre");
+        let insults = cache.read().unwrap();
+        assert_eq!((insults.noun_count(), insults.adjective_count()), (2, 3));
+        let empty = InsultFactory::new(Vec::new(), Vec::new());
+        assert_eq!((empty.noun_count(), empty.adjective_count()), (0, 0));
+    }
+
+    #[test]
+    fn tagged_insults_skip_words_with_other_tags() {
+        let tagged = |tag: &str| vec![("tags", string_set_attr(vec![tag.to_string()]))];
+        let items = vec![
+            item_for("clownn", &tagged("sfw")),
+            item_for("ogren", &tagged("spicy")),
+            item_for("goblinn", &[]),
+            item_for("dumba", &tagged("sfw")),
+            item_for("vilea", &tagged("spicy")),
+        ];
+        let (factory, _) = parse_words(items);
+        assert_eq!(factory.tags.get("clownn"), Some(&std::iter::once("sfw".to_string()).collect()));
+        assert_eq!(factory.tags.get("goblinn"), None);
+
+        let mut seen = HashSet::new();
+        for _ in 0..50 {
+            let insult = factory.get_insult_with(&grammar::English, &VecDeque::new(), MAX_SPICE, 1, Some("sfw")).unwrap();
+            seen.extend(insult.words().into_iter().map(str::to_string));
+        }
+        assert_eq!(sorted_words(seen.into_iter().collect()), ["clown", "dumb", "goblin"]);
+
+        assert!(word_item("clownn".to_string(), "U1", Some("sfw")).contains_key("tags"));
+        assert!(!word_item("clownn".to_string(), "U1", None).contains_key("tags"));
+    }
+
+    #[test]
+    fn insults_dont_repeat_back_to_back() {
+        let (factory, _) = parse_words(["dumba", "smellya", "clownn", "ogren"].iter().map(|word| item_for(word, &[])).collect());
+        let mut recent = VecDeque::new();
+        let mut last = String::new();
+        for _ in 0..200 {
+            let insult = factory.get_insult_with(&grammar::English, &recent, MAX_SPICE, 1, None).unwrap();
+            remember_words(&mut recent, &insult);
+            assert_ne!(insult.to_string(), last);
+            last = insult.to_string();
+        }
+        // With one of each there's nothing else to say, but it still says it.
+        let (factory, _) = parse_words(vec![item_for("dumba", &[]), item_for("clownn", &[])]);
+        let mut recent = VecDeque::new();
+        for _ in 0..3 {
+            let insult = factory.get_insult_with(&grammar::English, &recent, MAX_SPICE, 1, None).unwrap();
+            remember_words(&mut recent, &insult);
+            assert_eq!(insult.to_string(), "a dumb clown");
+        }
+    }
+
+    // From a command, through the words in a store, to what gets posted, with fakes for dynamo and Slack.
+    #[tokio::test]
+    async fn insult_commands_post_an_insult_from_the_stored_words() {
+        let store = FakeStore::new(&["clownn", "dumba", "bogus"], &[]);
+        let sink = RecordingSink::default();
+        // A channel of its own, so no other test's recent words or config get in the way.
+        config::prime("CSTORED", ChannelConfig::default());
+        let event: MessageEvent = serde_json::from_value(json!({
+            "channel": "CSTORED", "user": "U2", "text": "insult me", "ts": "1.2",
+        })).unwrap();
+        let handled = crate::COMMANDS.run(&event, Services { sink: &sink, words: &store }).await.unwrap();
+        assert!(handled);
+
+        let sent = sink.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("post CSTORED <@U2>"), "{}", sent[0]);
+        assert!(sent[0].contains("dumb") && sent[0].contains("clown"), "{}", sent[0]);
+        // Outside a thread, the insult starts one under the message.
+        assert_eq!(*sink.threads.lock().unwrap(), vec![Some("1.2".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn insults_asked_for_in_a_thread_stay_in_it() {
+        let store = FakeStore::new(&["clownn", "dumba"], &[]);
+        let sink = RecordingSink::default();
+        config::prime("CTHREAD", ChannelConfig::default());
+        let event: MessageEvent = serde_json::from_value(json!({
+            "channel": "CTHREAD", "user": "U2", "text": "insult me", "ts": "1.3", "thread_ts": "1.1",
+        })).unwrap();
+        let handled = crate::COMMANDS.run(&event, Services { sink: &sink, words: &store }).await.unwrap();
+        assert!(handled);
+        assert_eq!(*sink.threads.lock().unwrap(), vec![Some("1.1".to_string())]);
+    }
+
+    #[test]
+    fn insults_are_put_together_in_the_channels_language() {
+        let (factory, _) = parse_words(vec![item_for("tontoa", &[]), item_for("ratan", &[])]);
+        let insult = factory.get_insult_with(&grammar::Spanish, &VecDeque::new(), MAX_SPICE, 1, None).unwrap();
+        assert_eq!(insult.to_string(), "una rata tonta");
+        let spanish = ChannelConfig { lang: Some("es".to_string()), ..Default::default() };
+        let classic = Classic { adjectives: 1, tag: None };
+        let insult = generate_for(&spanish, &factory, &classic, &VecDeque::new(), MAX_SPICE).unwrap();
+        assert_eq!(insult.to_string(), "una rata tonta");
     }
-    let c = match pos {
-        PartOfSpeech::Noun => 'n',
-        PartOfSpeech::Adjective => 'a',
-    };
-    insult.push(c);
-    insert_word_to_dynamo(insult).await?;
-    send_message(&event.channel, "Added.").await
 }
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use rand::random;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, PutItemInput, ScanInput};
+
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr, string_set_attr};
+use crate::insult::{encode_word, Insult, PartOfSpeech};
+use crate::{env_flag, env_parse, now, LambdaResult};
+
+// Maintainer-facing record of every insult we generate, for weeding out words that don't land.
+pub fn enabled() -> bool {
+    env_flag("LOG_GENERATED_INSULTS")
+}
+
+// Days before dynamo expires a log entry.
+fn retention_days() -> u64 {
+    env_parse("INSULT_LOG_RETENTION_DAYS", 90)
+}
+
+fn log_item(channel: &str, target: Option<&str>, template: &str, insult: &Insult, created: u64) -> HashMap<String, AttributeValue> {
+    let mut item = HashMap::new();
+    item.insert("id".to_string(), string_attr(format!("{}-{:08x}", created, random::<u32>())));
+    item.insert("channel".to_string(), string_attr(channel.to_string()));
+    if let Some(target) = target {
+        item.insert("target".to_string(), string_attr(target.to_string()));
+    }
+    item.insert("template".to_string(), string_attr(template.to_string()));
+    // The words' keys in INSULT_TABLE, so a bad one can be found and removed.
+    match insult {
+        Insult::Phrase { adjectives, noun, other_noun, .. } => {
+            let ids = adjectives.iter().map(|a| encode_word(&PartOfSpeech::Adjective, a.clone())).collect();
+            item.insert("adjectives".to_string(), string_set_attr(adjectives.clone()));
+            item.insert("adjective_ids".to_string(), string_set_attr(ids));
+            item.insert("noun".to_string(), string_attr(noun.clone()));
+            item.insert("noun_id".to_string(), string_attr(encode_word(&PartOfSpeech::Noun, noun.clone())));
+            if let Some(other_noun) = other_noun {
+                item.insert("other_noun".to_string(), string_attr(other_noun.clone()));
+                item.insert("other_noun_id".to_string(), string_attr(encode_word(&PartOfSpeech::Noun, other_noun.clone())));
+            }
+        },
+        Insult::AdjectiveOnly(adjective, _) => {
+            item.insert("adjective".to_string(), string_attr(adjective.clone()));
+            item.insert("adjective_id".to_string(), string_attr(encode_word(&PartOfSpeech::Adjective, adjective.clone())));
+        },
+        Insult::NounOnly(noun, _) => {
+            item.insert("noun".to_string(), string_attr(noun.clone()));
+            item.insert("noun_id".to_string(), string_attr(encode_word(&PartOfSpeech::Noun, noun.clone())));
+        },
+        Insult::Emoji(emoji) => {
+            let ids = emoji.iter().map(|e| encode_word(&PartOfSpeech::Emoji, e.clone())).collect();
+            item.insert("emoji".to_string(), string_attr(insult.to_string()));
+            item.insert("emoji_ids".to_string(), string_set_attr(ids));
+        },
+        // Not all of it comes from the vocabulary, so there's nothing to point back to.
+        Insult::Creative(..) => {
+            item.insert("creative".to_string(), string_attr(insult.to_string()));
+        },
+    }
+    item.insert("created".to_string(), number_attr(created));
+    item.insert("expires".to_string(), number_attr(created + retention_days() * 24 * 60 * 60));
+    item
+}
+
+async fn put_log_item(client: &impl DynamoDb, item: HashMap<String, AttributeValue>) -> LambdaResult<()> {
+    let table_name = std::env::var("INSULT_LOG_TABLE")?;
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+// Logging is best effort: a failure here shouldn't cost anyone their insult.
+// `target` is None for insults aimed at nobody in particular.
+pub async fn record(channel: &str, target: Option<&str>, template: &str, insult: &Insult) {
+    record_to(&dynamo_client(), channel, target, template, insult).await
+}
+
+async fn record_to(client: &impl DynamoDb, channel: &str, target: Option<&str>, template: &str, insult: &Insult) {
+    let item = log_item(channel, target, template, insult, now());
+    let id = item.get("id").and_then(|id| id.s.as_deref()).unwrap_or_default();
+    log::info!("Logging insult {} in {} ({})", id, channel, template);
+    if let Err(e) = put_log_item(client, item).await {
+        log::error!("Error logging generated insult: {}", e);
+    }
+}
+
+pub struct LogEntry {
+    pub target: Option<String>,
+    pub adjectives: Vec<String>,
+    pub noun: String,
+}
+
+impl LogEntry {
+    // Emoji and one-sided insults don't have both an adjective and a noun, so they're left out.
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name)?.s.clone();
+        // Entries from before stacked adjectives have a single `adjective`.
+        let adjectives = match item.get("adjectives").and_then(|attr| attr.ss.clone()) {
+            Some(adjectives) => adjectives,
+            None => vec![s("adjective")?],
+        };
+        Some(LogEntry { target: s("target"), adjectives, noun: s("noun")? })
+    }
+}
+
+// Every insult logged since the `since` epoch second.
+pub async fn fetch_since(since: u64) -> LambdaResult<Vec<LogEntry>> {
+    let table_name = std::env::var("INSULT_LOG_TABLE")?;
+    let mut values = HashMap::new();
+    values.insert(":since".to_string(), number_attr(since));
+    let input = ScanInput {
+        table_name,
+        filter_expression: Some("created >= :since".to_string()),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    let client = dynamo_client();
+    let items = scan_all(&client, input).await?;
+    Ok(items.iter().filter_map(LogEntry::from_item).collect())
+}
+
+// The words that `word` was paired with across `entries`, once per insult.
+pub fn pairings<'a>(entries: &'a [LogEntry], word: &'a str) -> impl Iterator<Item = &'a str> {
+    entries.iter().flat_map(move |entry| {
+        if entry.adjectives.iter().any(|adjective| adjective == word) {
+            vec![entry.noun.as_str()]
+        } else if entry.noun == word {
+            entry.adjectives.iter().map(String::as_str).collect()
+        } else {
+            vec![]
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::dynamo::mock::recording_client;
+    use crate::{digest, grammar};
+
+    #[tokio::test]
+    async fn generated_insults_are_recorded_with_their_word_ids() {
+        std::env::set_var("INSULT_LOG_TABLE", "insult_log");
+        let (client, sent) = recording_client(200, "{}");
+        let insult = Insult::Phrase {
+            adjectives: vec!["smelly".to_string()],
+            noun: "clown".to_string(),
+            other_noun: None,
+            template: "{target} is {a adjectives} {noun}".to_string(),
+            grammar: &grammar::English,
+        };
+        record_to(&client, "C1", Some("<@U1>"), "{target} is {a adjectives} {noun}", &insult).await;
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["TableName"], "insult_log");
+        let item = &sent[0]["Item"];
+        assert_eq!(item["channel"], json!({ "S": "C1" }));
+        assert_eq!(item["target"], json!({ "S": "<@U1>" }));
+        assert_eq!(item["template"], json!({ "S": "{target} is {a adjectives} {noun}" }));
+        assert_eq!(item["adjectives"], json!({ "SS": ["smelly"] }));
+        assert_eq!(item["adjective_ids"], json!({ "SS": ["smellya"] }));
+        assert_eq!(item["noun"], json!({ "S": "clown" }));
+        assert_eq!(item["noun_id"], json!({ "S": "clownn" }));
+        assert!(item.get("other_noun").is_none());
+        let number = |name: &str| item[name]["N"].as_str().unwrap().parse::<u64>().unwrap();
+        assert_eq!(number("expires") - number("created"), retention_days() * 24 * 60 * 60);
+    }
+
+    fn entry(adjectives: &[&str], noun: &str) -> LogEntry {
+        LogEntry { target: None, adjectives: adjectives.iter().map(|a| a.to_string()).collect(), noun: noun.to_string() }
+    }
+
+    #[test]
+    fn pairings_count_each_partner_once_an_insult() {
+        let entries = vec![
+            entry(&["smelly"], "clown"),
+            entry(&["smelly", "dumb"], "clown"),
+            entry(&["smelly"], "goblin"),
+            entry(&["dumb"], "ogre"),
+        ];
+        assert_eq!(digest::top(pairings(&entries, "smelly"), 5), [("clown", 2), ("goblin", 1)]);
+        assert_eq!(digest::top(pairings(&entries, "clown"), 5), [("smelly", 2), ("dumb", 1)]);
+        assert_eq!(digest::top(pairings(&entries, "clown"), 1), [("smelly", 2)]);
+        assert!(pairings(&entries, "ugly").next().is_none());
+    }
+}
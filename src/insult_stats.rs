@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::future::Future;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, ScanInput, UpdateItemInput};
+
+use crate::blocks::{self, Block};
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr};
+use crate::insult::Insult;
+use crate::router::Command;
+use crate::{now, LambdaResult, MessageEvent, MessageSink, SendOptions};
+
+// Running totals of who gets insulted, who asks for it, and which words get used, kept in STATS_TABLE.
+// Each is a counter keyed like "target:<@U123>", "insulter:<@U456>" or "word:clown", bumped as insults go out.
+
+// Slack allows ten fields a section, which is five rows of name and count.
+const LEADERBOARD_SIZE: usize = 5;
+
+tokio::task_local! {
+    // Whoever asked for the insults made while handling their message, click, or slash command.
+    static INSULTER: String;
+}
+
+// Runs `future` on behalf of `user`, so the insults it makes count as theirs.
+pub async fn as_insulter<F: Future>(user: &str, future: F) -> F::Output {
+    INSULTER.scope(user.to_string(), future).await
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "insult stats", "Who gets insulted the most, and with what.",
+            r"(?i)^\s*insult\s+(?:stats|leaderboard)$",
+            |event, _, services| Box::pin(handle_leaderboard(event, services.sink)),
+        ),
+    ]
+}
+
+// Counting is best effort: a failure here shouldn't cost anyone their insult.
+pub async fn record(target: Option<&str>, insult: &Insult) {
+    let mut stats = Vec::new();
+    if let Some(target) = target {
+        stats.push(format!("target:{}", target));
+    }
+    if let Ok(insulter) = INSULTER.try_with(Clone::clone) {
+        stats.push(format!("insulter:<@{}>", insulter));
+    }
+    stats.extend(insult.words().into_iter().map(|word| format!("word:{}", word)));
+    for stat in stats {
+        if let Err(e) = bump(&stat).await {
+            log::error!("Error counting {}: {}", stat, e);
+        }
+    }
+}
+
+async fn bump(stat: &str) -> LambdaResult<()> {
+    let table_name = std::env::var("STATS_TABLE")?;
+    let mut key = HashMap::new();
+    key.insert("stat".to_string(), string_attr(stat.to_string()));
+    let mut names = HashMap::new();
+    names.insert("#count".to_string(), "count".to_string());
+    names.insert("#last".to_string(), "last".to_string());
+    let mut values = HashMap::new();
+    values.insert(":one".to_string(), number_attr(1));
+    values.insert(":now".to_string(), number_attr(now()));
+    let input = UpdateItemInput {
+        key,
+        table_name,
+        update_expression: Some("ADD #count :one SET #last = :now".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    dynamo_client().update_item(input).await?;
+    Ok(())
+}
+
+fn count_of(item: &HashMap<String, AttributeValue>) -> u64 {
+    item.get("count").and_then(|attr| attr.n.as_ref()?.parse().ok()).unwrap_or(0)
+}
+
+// The biggest counts of one kind, biggest first.
+fn top(counts: &[(String, u64)], kind: &str) -> Vec<(String, u64)> {
+    let prefix = format!("{}:", kind);
+    let mut top: Vec<_> = counts.iter()
+        .filter_map(|(stat, count)| Some((stat.strip_prefix(&prefix)?.to_string(), *count)))
+        .collect();
+    top.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+    top.truncate(LEADERBOARD_SIZE);
+    top
+}
+
+// Names down the left and counts down the right.
+fn table(title: &str, entries: &[(String, u64)]) -> Block {
+    if entries.is_empty() {
+        return Block::section(&format!("*{}*\n_Nothing yet._", title));
+    }
+    let fields: Vec<_> = entries.iter()
+        .enumerate()
+        .flat_map(|(i, (name, count))| vec![format!("{}. {}", i + 1, name), count.to_string()])
+        .collect();
+    let fields: Vec<_> = fields.iter().map(String::as_str).collect();
+    Block::section_with_fields(&format!("*{}*", title), &fields)
+}
+
+async fn handle_leaderboard(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let table_name = std::env::var("STATS_TABLE")?;
+    let client = dynamo_client();
+    let counts: Vec<_> = scan_all(&client, ScanInput { table_name, ..Default::default() }).await?.iter()
+        .filter_map(|item| Some((item.get("stat")?.s.clone()?, count_of(item))))
+        .collect();
+    let blocks = [
+        Block::header("Insult stats"),
+        table("Most insulted", &top(&counts, "target")),
+        table("Most insults handed out", &top(&counts, "insulter")),
+        table("Most used words", &top(&counts, "word")),
+    ];
+    let options = SendOptions { blocks: Some(blocks::to_value(&blocks)), ..event.reply_options() };
+    sink.post(&event.channel, "Insult stats", &options).await.map(|_| ())
+}
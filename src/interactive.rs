@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::slack::{self, post_response};
+use crate::{audit, env_flag, insult, insult_stats, polls, teams, words, LambdaResult};
+
+// Buttons on our messages, and what happens when someone clicks them.
+// Slack only sends clicks if the app's Interactivity request URL points at us, so they're behind INTERACTIVE_BUTTONS.
+
+pub const INSULT_AGAIN: &str = "insult_again";
+pub const UNDO_ADD: &str = "undo_add";
+pub const POLL_VOTE: &str = "poll_vote";
+pub const OPEN_ADD_WORD: &str = "open_add_word";
+// The callback id of both the "Add word" shortcut and the modal it opens.
+pub const ADD_WORD: &str = "add_word";
+
+pub fn enabled() -> bool {
+    env_flag("INTERACTIVE_BUTTONS")
+}
+
+// Interactivity requests are form encoded, with the JSON in a single field.
+#[derive(Deserialize)]
+pub struct InteractionForm {
+    payload: String,
+}
+
+// https://api.slack.com/reference/interaction-payloads
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Interaction {
+    BlockActions(BlockActions),
+    Shortcut(Shortcut),
+    ViewSubmission(ViewSubmission),
+    #[serde(other)]
+    Unsupported,
+}
+
+// https://api.slack.com/reference/interaction-payloads/block-actions
+#[derive(Deserialize, Debug)]
+struct BlockActions {
+    user: Id,
+    #[serde(default)]
+    team: Option<Id>,
+    channel: Option<Id>,
+    // The message the buttons are on.
+    message: Option<Message>,
+    // Clicks on the Home tab aren't on a message, so there's nothing to respond to.
+    #[serde(default)]
+    response_url: Option<String>,
+    trigger_id: String,
+    actions: Vec<Action>,
+}
+
+// https://api.slack.com/reference/interaction-payloads/shortcuts
+#[derive(Deserialize, Debug)]
+struct Shortcut {
+    #[serde(default)]
+    team: Option<Id>,
+    callback_id: String,
+    trigger_id: String,
+}
+
+// https://api.slack.com/reference/interaction-payloads/views#view_submission
+#[derive(Deserialize, Debug)]
+struct ViewSubmission {
+    user: Id,
+    #[serde(default)]
+    team: Option<Id>,
+    view: View,
+}
+
+#[derive(Deserialize, Debug)]
+struct View {
+    callback_id: String,
+    state: ViewState,
+    // What we set when opening it. For the "Add word" modal, the channel it was opened from, if any.
+    #[serde(default)]
+    private_metadata: String,
+}
+
+// Each input's value, by block id and then action id.
+#[derive(Deserialize, Debug)]
+struct ViewState {
+    values: HashMap<String, HashMap<String, InputValue>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InputValue {
+    value: Option<String>,
+    selected_option: Option<SelectedOption>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SelectedOption {
+    value: String,
+}
+
+impl ViewState {
+    // What was typed or picked in each block.
+    fn by_block(&self) -> HashMap<String, String> {
+        self.values.iter()
+            .filter_map(|(block_id, inputs)| {
+                let input = inputs.values().next()?;
+                let value = input.value.clone().or_else(|| Some(input.selected_option.as_ref()?.value.clone()))?;
+                Some((block_id.clone(), value))
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Id {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Message {
+    ts: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Action {
+    action_id: String,
+    #[serde(default)]
+    value: String,
+}
+
+// Most interactions are answered separately, but a modal submission's answer is the response body.
+pub async fn handle_interaction(form: &InteractionForm) -> LambdaResult<Option<Value>> {
+    handle_payload(serde_json::from_str(&form.payload)?).await
+}
+
+// Socket Mode hands us the payload as JSON already, rather than in a form.
+pub async fn handle_payload(payload: Value) -> LambdaResult<Option<Value>> {
+    let interaction: Interaction = serde_json::from_value(payload)?;
+    log::info!("Interaction {:?}", interaction);
+    match interaction {
+        Interaction::BlockActions(block_actions) => {
+            let team_id = block_actions.team.as_ref().map(|team| team.id.clone());
+            let channel = block_actions.channel.as_ref().map(|channel| channel.id.as_str());
+            let handled = audit::in_channel(channel, handle_block_actions(&block_actions));
+            let handled = insult_stats::as_insulter(&block_actions.user.id, handled);
+            teams::scope(team_id, handled).await?;
+            Ok(None)
+        },
+        Interaction::Shortcut(shortcut) if shortcut.callback_id == ADD_WORD => {
+            let team_id = shortcut.team.as_ref().map(|team| team.id.clone());
+            teams::scope(team_id, open_add_word_modal(&shortcut.trigger_id, None)).await?;
+            Ok(None)
+        },
+        Interaction::ViewSubmission(submission) if submission.view.callback_id == ADD_WORD => {
+            let team_id = submission.team.as_ref().map(|team| team.id.clone());
+            let values = submission.view.state.by_block();
+            let channel = Some(submission.view.private_metadata.as_str()).filter(|channel| !channel.is_empty());
+            let handled = words::handle_add_word_submission(&submission.user.id, channel, &values);
+            let response = teams::scope(team_id, handled).await?;
+            Ok(Some(response))
+        },
+        _ => Ok(None),
+    }
+}
+
+async fn open_add_word_modal(trigger_id: &str, channel: Option<&str>) -> LambdaResult<()> {
+    Ok(slack::client().await?.open_view(trigger_id, words::add_word_modal(channel)).await?)
+}
+
+async fn handle_block_actions(block_actions: &BlockActions) -> LambdaResult<()> {
+    let user = block_actions.user.id.as_str();
+    for action in &block_actions.actions {
+        if action.action_id == OPEN_ADD_WORD {
+            let channel = block_actions.channel.as_ref().map(|channel| channel.id.as_str());
+            open_add_word_modal(&block_actions.trigger_id, channel).await?;
+            continue;
+        }
+        let (channel, response_url) = match (&block_actions.channel, &block_actions.response_url) {
+            (Some(channel), Some(response_url)) => (channel.id.as_str(), response_url),
+            _ => continue,
+        };
+        let response = match action.action_id.as_str() {
+            INSULT_AGAIN => insult::handle_insult_again(channel, user, &action.value).await?,
+            UNDO_ADD => words::handle_undo_add(user, &action.value).await?,
+            // Votes show up by redrawing the poll, rather than as a response.
+            POLL_VOTE => {
+                if let Some(message) = &block_actions.message {
+                    polls::handle_vote(user, &message.ts, &action.value).await?;
+                }
+                continue;
+            },
+            _ => continue,
+        };
+        post_response(response_url, response).await?;
+    }
+    Ok(())
+}
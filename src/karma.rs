@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, GetItemInput, ScanInput, UpdateItemInput};
+
+use crate::dynamo::{dynamo_client, scan_all, string_attr};
+use crate::router::{Args, Command};
+use crate::{LambdaResult, MessageEvent, MessageSink};
+
+// Points for people and things: "pizza++", "mondays--". Kept in KARMA_TABLE, keyed by thing.
+
+const LEADERBOARD_SIZE: usize = 10;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "karma top", "Who and what has the most karma.",
+            r"(?i)^\s*karma\s+top$",
+            |event, _, services| Box::pin(handle_leaderboard(event, services.sink)),
+        ),
+        Command::verbatim(
+            "karma <thing>", "How much karma something has.",
+            r"(?i)^\s*karma\s+(\S+?)[.!?]*$",
+            |event, args, services| Box::pin(handle_karma_command(event, services.sink, args)),
+        ),
+        Command::verbatim(
+            "<thing>++ / <thing>--", "Give or take a point of karma. Works anywhere in a message.",
+            r"\S(?:\+\+|--)(?:\s|$)",
+            |event, _, services| Box::pin(handle_votes(event, services.sink)),
+        ),
+    ]
+}
+
+static VOTE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)([^\s+-][^\s]*?)(\+\+|--)(?:\s|$)").unwrap());
+
+// Mentions stay as sent, since user ids are case-sensitive. Anything else counts the same however it's typed.
+fn normalize_thing(thing: &str) -> String {
+    if thing.starts_with("<@") {
+        thing.to_string()
+    } else {
+        thing.trim_start_matches('@').to_lowercase()
+    }
+}
+
+fn thing_key(thing: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("thing".to_string(), string_attr(thing.to_string()));
+    key
+}
+
+fn karma_of(item: &HashMap<String, AttributeValue>) -> i64 {
+    item.get("karma").and_then(|attr| attr.n.as_ref()?.parse().ok()).unwrap_or(0)
+}
+
+// Adds `delta` in one atomic update, returning the new total.
+async fn add_karma(thing: &str, delta: i64) -> LambdaResult<i64> {
+    let table_name = std::env::var("KARMA_TABLE")?;
+    let mut names = HashMap::new();
+    names.insert("#karma".to_string(), "karma".to_string());
+    let mut values = HashMap::new();
+    values.insert(":delta".to_string(), AttributeValue { n: Some(delta.to_string()), ..Default::default() });
+    let client = dynamo_client();
+    let input = UpdateItemInput {
+        key: thing_key(thing),
+        table_name,
+        update_expression: Some("ADD #karma :delta".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("UPDATED_NEW".to_string()),
+        ..Default::default()
+    };
+    let output = client.update_item(input).await?;
+    Ok(output.attributes.as_ref().map(karma_of).unwrap_or(delta))
+}
+
+async fn get_karma(thing: &str) -> LambdaResult<i64> {
+    let table_name = std::env::var("KARMA_TABLE")?;
+    let client = dynamo_client();
+    let input = GetItemInput { key: thing_key(thing), table_name, ..Default::default() };
+    Ok(client.get_item(input).await?.item.as_ref().map(karma_of).unwrap_or(0))
+}
+
+async fn handle_votes(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    // Each thing once per message, however many times it's bumped.
+    let mut votes: Vec<(String, i64)> = Vec::new();
+    for caps in VOTE_RE.captures_iter(&event.text) {
+        let thing = normalize_thing(&caps[1]);
+        let delta = if &caps[2] == "++" { 1 } else { -1 };
+        if thing.is_empty() || votes.iter().any(|(voted, _)| *voted == thing) {
+            continue;
+        }
+        votes.push((thing, delta));
+    }
+    let own_tag = format!("<@{}>", event.user);
+    let mut lines = Vec::new();
+    for (thing, delta) in votes {
+        if thing == own_tag {
+            lines.push("Nice try. You can't give yourself karma.".to_string());
+            continue;
+        }
+        let karma = add_karma(&thing, delta).await?;
+        lines.push(format!("{}'s karma is now {}.", thing, karma));
+    }
+    if lines.is_empty() {
+        return Ok(());
+    }
+    event.reply(sink, &lines.join("\n")).await
+}
+
+async fn handle_karma_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let thing = normalize_thing(args.get(1));
+    let karma = get_karma(&thing).await?;
+    event.reply(sink, &format!("{} has {} karma.", thing, karma)).await
+}
+
+async fn handle_leaderboard(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let table_name = std::env::var("KARMA_TABLE")?;
+    let client = dynamo_client();
+    let input = ScanInput { table_name, ..Default::default() };
+    let mut scores: Vec<_> = scan_all(&client, input).await?.iter()
+        .filter_map(|item| Some((item.get("thing")?.s.clone()?, karma_of(item))))
+        .collect();
+    if scores.is_empty() {
+        return event.reply(sink, "Nobody has any karma yet.").await;
+    }
+    scores.sort_by(|(a_thing, a), (b_thing, b)| b.cmp(a).then_with(|| a_thing.cmp(b_thing)));
+    let lines: Vec<_> = scores.iter()
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+        .map(|(i, (thing, karma))| format!("{}. {} ({})", i + 1, thing, karma))
+        .collect();
+    event.reply(sink, &format!("Most karma:\n{}", lines.join("\n"))).await
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, PutItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::{now, LambdaResult};
+
+// The word each user added most recently, so "undo" knows what to take back.
+// Words are stored encoded, eg "clownn", like the insult table's.
+
+fn user_key(user: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("user".to_string(), string_attr(user.to_string()));
+    key
+}
+
+pub async fn record(user: &str, word: String) -> LambdaResult<()> {
+    let table_name = std::env::var("LAST_ADDED_TABLE")?;
+    let mut item = user_key(user);
+    item.insert("word".to_string(), string_attr(word));
+    item.insert("added".to_string(), number_attr(now()));
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+// Forgets and returns the word `user` added most recently, if there's one left to undo.
+pub async fn take(user: &str) -> LambdaResult<Option<String>> {
+    let table_name = std::env::var("LAST_ADDED_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput {
+        key: user_key(user),
+        table_name,
+        return_values: Some("ALL_OLD".to_string()),
+        ..Default::default()
+    };
+    let output = client.delete_item(input).await?;
+    Ok(output.attributes.and_then(|attributes| attributes.get("word")?.s.clone()))
+}
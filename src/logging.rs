@@ -0,0 +1,144 @@
+use std::fmt::Debug;
+use std::time::Instant;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record as SpanRecord};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry, SpanRef};
+
+// Logs as one JSON object a line, so CloudWatch Logs Insights can filter on any field,
+// eg `filter event_id = "Ev123"`. Each line carries the fields of every span it happened in:
+// the invocation, the event's id, team, channel, and user, and the Slack or dynamo call if any.
+// Our own `log::info!` and friends come out the same way as tracing events from our dependencies.
+
+pub fn init() {
+    let subscriber = Registry::default().with(JsonLayer);
+    tracing::subscriber::set_global_default(subscriber).expect("a tracing subscriber was already set");
+    log::set_logger(&LogBridge).expect("a logger was already set");
+    log::set_max_level(LevelFilter::Info);
+}
+
+// A span's fields so far.
+struct Fields(Map<String, Value>);
+
+struct Started(Instant);
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+// The fields of `span` and everything it's inside, outermost first so inner spans win.
+fn span_fields<'a, R: LookupSpan<'a>>(span: SpanRef<'a, R>) -> Map<String, Value> {
+    let mut fields = Map::new();
+    for span in span.from_root().chain(std::iter::once(span)) {
+        if let Some(Fields(own)) = span.extensions().get::<Fields>() {
+            fields.extend(own.clone());
+        }
+    }
+    fields
+}
+
+fn print_line(mut line: Map<String, Value>, level: &str, target: &str) {
+    line.insert("level".to_string(), Value::from(level));
+    line.insert("target".to_string(), Value::from(target));
+    println!("{}", Value::Object(line));
+}
+
+struct JsonLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for JsonLayer {
+    fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Map::new();
+        attrs.record(&mut JsonVisitor(&mut fields));
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            extensions.insert(Fields(fields));
+            extensions.insert(Started(Instant::now()));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &SpanRecord<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(Fields(fields)) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(&mut JsonVisitor(fields));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > tracing::Level::INFO {
+            return;
+        }
+        let mut line = ctx.lookup_current().map(span_fields).unwrap_or_default();
+        event.record(&mut JsonVisitor(&mut line));
+        print_line(line, &metadata.level().to_string(), metadata.target());
+    }
+
+    // How long each span took, eg a single Slack call.
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        // Our dependencies' debug and trace spans would drown out everything else.
+        if *span.metadata().level() > tracing::Level::INFO {
+            return;
+        }
+        let (name, target) = (span.name(), span.metadata().target());
+        let elapsed = span.extensions().get::<Started>().map(|Started(started)| started.elapsed().as_millis() as u64);
+        let mut line = span_fields(span);
+        if let Some(elapsed) = elapsed {
+            line.insert("elapsed_ms".to_string(), Value::from(elapsed));
+        }
+        line.insert("span".to_string(), Value::from(name));
+        print_line(line, "INFO", target);
+    }
+}
+
+// Sends `log` records out as JSON lines too, with the fields of the tracing span they're logged in.
+struct LogBridge;
+
+impl Log for LogBridge {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line = tracing::dispatcher::get_default(|dispatch| {
+            match (dispatch.current_span().id(), dispatch.downcast_ref::<Registry>()) {
+                (Some(id), Some(registry)) => registry.span(id).map(span_fields).unwrap_or_default(),
+                _ => Map::new(),
+            }
+        });
+        line.insert("message".to_string(), Value::from(record.args().to_string()));
+        print_line(line, record.level().as_str(), record.target());
+    }
+
+    fn flush(&self) {}
+}
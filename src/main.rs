@@ -1,40 +1,255 @@
-use hyper::{body, Body, Client, Method, Request};
-use hyper_openssl::HttpsConnector;
 use lambda_runtime::{handler_fn, Context, Error as LambdaError};
-use log::LevelFilter;
+use rusoto_dynamodb::DynamoDb;
+use once_cell::sync::Lazy;
 use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
-use simple_logger::SimpleLogger;
 use std::collections::HashMap;
 use std::env;
-use std::time::Duration;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+use tokio::sync::OnceCell;
 
+use blocks::Block;
+use error::{BotError, BotResult};
+
+mod admin;
+mod audit;
+mod backup;
+mod blocks;
+mod config;
+mod daily;
+mod dice;
+mod digest;
+mod dynamo;
+mod echo;
+mod error;
+mod filter;
+mod grammar;
+mod health;
+mod home;
+mod image;
+mod import;
 mod insult;
+mod insult_log;
+mod insult_stats;
+mod interactive;
+mod karma;
+mod last_added;
+mod logging;
+mod markov;
+mod metrics;
+mod moderation;
+mod oauth;
+mod opt_out;
+mod outbox;
+mod polls;
+mod popularity;
+mod queue;
+mod rate_limit;
+mod reminders;
+mod router;
+mod secrets;
+mod serve;
+mod signature;
+mod slack;
+mod socket_mode;
+mod teams;
+mod users;
+mod vote;
+mod war;
+mod websocket;
+mod words;
 type LambdaResult<T> = Result<T, LambdaError>;
 
 #[tokio::main]
 async fn main() -> LambdaResult<()> {
-    SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
+    logging::init();
     openssl_probe::init_ssl_cert_env_vars();
 
-    let func = handler_fn(api_gateway_func);
+    if env::args().any(|arg| arg == "--serve") {
+        return serve::serve(env_parse("PORT", 3000)).await;
+    }
+    if env::args().any(|arg| arg == "--socket-mode") {
+        return socket_mode::run().await;
+    }
+    let func = handler_fn(lambda_func);
     lambda_runtime::run(func).await?;
     Ok(())
 }
 
+// Input of our EventBridge schedules, see the `Schedule` events in cloudformation.yml.
+#[derive(Deserialize)]
+struct ScheduledEvent {
+    scheduled_task: ScheduledTask,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ScheduledTask {
+    Backup,
+    WeeklyDigest,
+    OutboxSweep,
+    DailyInsults,
+}
+
 // https://docs.aws.amazon.com/lambda/latest/dg/services-apigateway.html
+// Also the 2.0 payload from HTTP APIs and function URLs, which keeps the method and path in `requestContext`.
+// https://docs.aws.amazon.com/apigateway/latest/developerguide/http-api-develop-integrations-lambda.html
 #[derive(Deserialize)]
 struct ApiGatewayEvent {
-    #[serde(deserialize_with = "deserialize_str")]
-    body: Value
+    // Kept as sent, since the signature is over the exact bytes. GETs don't have one.
+    #[serde(default, deserialize_with = "deserialize_null_as_empty")]
+    body: String,
+    #[serde(default, rename = "isBase64Encoded")]
+    is_base64_encoded: bool,
+    headers: Option<HashMap<String, String>>,
+    #[serde(default, rename = "httpMethod")]
+    http_method: String,
+    #[serde(default)]
+    path: String,
+    #[serde(default, rename = "queryStringParameters")]
+    query: Option<HashMap<String, String>>,
+    #[serde(default, rename = "requestContext")]
+    request_context: Option<RequestContext>,
 }
 
-// Deserializer for a json encoded string.
-// Ex: json!("\"5\"") -> 5
-fn deserialize_str<'de, D>(deserializer: D) -> Result<Value, D::Error> where D: Deserializer<'de> {
-    let s: String = Deserialize::deserialize(deserializer)?;
-    serde_json::from_str(&s).map_err(D::Error::custom)
+#[derive(Deserialize)]
+struct RequestContext {
+    http: Option<HttpContext>,
+}
+
+#[derive(Deserialize)]
+struct HttpContext {
+    method: String,
+    path: String,
+}
+
+fn deserialize_null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// Slack's payloads are a few KB. Anything this big isn't from Slack, and isn't worth decoding to find out.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+// Why a request's body couldn't be read, answered with a 4xx rather than failing the invocation.
+#[derive(Debug)]
+enum BodyError {
+    NotText,
+    TooLarge(usize),
+    BadBase64(base64::DecodeError),
+    NotUtf8,
+}
+
+impl BodyError {
+    fn status_code(&self) -> u16 {
+        match self {
+            BodyError::TooLarge(_) => 413,
+            _ => 400,
+        }
+    }
+
+    // For the response, so callers can tell the cases apart without parsing the message.
+    fn code(&self) -> &'static str {
+        match self {
+            BodyError::NotText => "body_not_text",
+            BodyError::TooLarge(_) => "body_too_large",
+            BodyError::BadBase64(_) => "bad_base64",
+            BodyError::NotUtf8 => "body_not_utf8",
+        }
+    }
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BodyError::NotText => fmtr.write_str("expected the body to be a string"),
+            BodyError::TooLarge(len) =>
+                fmtr.write_fmt(format_args!("body is {} bytes, over the limit of {}", len, MAX_BODY_BYTES)),
+            BodyError::BadBase64(e) => fmtr.write_fmt(format_args!("bad base64 body: {}", e)),
+            BodyError::NotUtf8 => fmtr.write_str("body is not valid utf-8"),
+        }
+    }
+}
+
+// Checks the size before decoding: base64 only ever shrinks.
+fn check_body_size(body: &str) -> Result<(), BodyError> {
+    match body.len() {
+        len if len > MAX_BODY_BYTES => Err(BodyError::TooLarge(len)),
+        _ => Ok(()),
+    }
+}
+
+impl ApiGatewayEvent {
+    // Both payload versions, looking like the 1.0 one. The body comes base64 encoded
+    // whenever the integration decides it isn't text, which for function URLs is form posts.
+    fn normalized(mut self) -> Result<Self, BodyError> {
+        if let Some(http) = self.request_context.take().and_then(|context| context.http) {
+            self.http_method = http.method;
+            self.path = http.path;
+        }
+        check_body_size(&self.body)?;
+        if self.is_base64_encoded {
+            let bytes = base64::decode(&self.body).map_err(BodyError::BadBase64)?;
+            self.body = String::from_utf8(bytes).map_err(|_| BodyError::NotUtf8)?;
+            self.is_base64_encoded = false;
+        }
+        Ok(self)
+    }
+
+    // Header names are case insensitive, and API Gateway passes them through however they were sent.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.as_ref()?.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.as_ref()?.get(name).map(String::as_str)
+    }
+
+    // Where Slack sends people's browsers after they install the app.
+    fn is_oauth_redirect(&self) -> bool {
+        self.http_method == "GET" && self.path.ends_with("/slack/oauth")
+    }
+
+    // The "Add to Slack" link, which sends people on to Slack to install the app.
+    fn is_install_link(&self) -> bool {
+        self.http_method == "GET" && self.path.ends_with("/slack/install")
+    }
+
+    // A load balancer or an operator checking on us.
+    fn is_health_check(&self) -> bool {
+        self.http_method == "GET" && self.path.ends_with("/healthz")
+    }
+
+    fn retry_num(&self) -> u32 {
+        self.header("X-Slack-Retry-Num").and_then(|num| num.parse().ok()).unwrap_or(0)
+    }
+
+    // Why Slack is retrying, eg "http_timeout" or "http_error".
+    fn retry_reason(&self) -> &str {
+        self.header("X-Slack-Retry-Reason").unwrap_or("unknown")
+    }
+
+    async fn is_from_slack(&self) -> bool {
+        let signing_secret = match secrets::get("SLACK_SIGNING_SECRET").await {
+            Ok(secret) if !secret.is_empty() => secret,
+            _ => {
+                log::error!("No SLACK_SIGNING_SECRET configured, rejecting request");
+                return false;
+            },
+        };
+        match (self.header("X-Slack-Request-Timestamp"), self.header("X-Slack-Signature")) {
+            (Some(timestamp), Some(signature)) =>
+                signature::fresh_timestamp(timestamp)
+                && signature::verify_signature(&self.body, timestamp, signature, &signing_secret),
+            _ => false,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -47,13 +262,69 @@ struct ApiGatewayResponse {
 
 impl ApiGatewayResponse {
     fn ok(body: Value) -> Self {
+        Self::with_status(200, body)
+    }
+
+    fn bad_request(message: &str) -> Self {
+        Self::error(400, message)
+    }
+
+    fn unauthorized(message: &str) -> Self {
+        Self::error(401, message)
+    }
+
+    fn error(status_code: u16, message: &str) -> Self {
+        Self::with_status(status_code, json!({"error": message}))
+    }
+
+    // What went wrong stays in the logs: the caller only hears what kind of failure it was.
+    fn bot_error(error: &BotError) -> Self {
+        log::error!("Error handling request: {}", error);
+        let message = match error.status_code() {
+            400 => "bad request",
+            502 => "upstream service failed",
+            _ => "internal error",
+        };
+        Self::error(error.status_code(), message)
+    }
+
+    fn body_error(error: &BodyError) -> Self {
+        log::warn!("Rejecting request body: {}", error);
+        Self::with_status(error.status_code(), json!({"error": error.to_string(), "code": error.code()}))
+    }
+
+    fn html(status_code: u16, body: String) -> Self {
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type".to_string(), "text/html; charset=utf-8".to_string());
+        ApiGatewayResponse { status_code, headers, body }
+    }
+
+    fn redirect(location: String) -> Self {
+        let mut headers = HashMap::new();
+        headers.insert("Location".to_string(), location);
+        ApiGatewayResponse { status_code: 302, headers, body: String::new() }
+    }
+
+    fn with_status(status_code: u16, body: Value) -> Self {
         let body = body.to_string();
         let mut headers = HashMap::new();
         headers.insert("Content-Type".to_string(), "application/json".to_string());
-        ApiGatewayResponse { status_code: 200, headers, body }
+        ApiGatewayResponse { status_code, headers, body }
     }
 }
 
+// https://api.slack.com/interactivity/slash-commands
+#[derive(Deserialize, Debug)]
+pub struct SlashCommand {
+    command: String,
+    #[serde(default)]
+    text: String,
+    user_id: String,
+    channel_id: String,
+    #[serde(default)]
+    team_id: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct ChallengeEvent {
     challenge: String,
@@ -62,14 +333,157 @@ struct ChallengeEvent {
 #[derive(Deserialize, Debug)]
 pub struct MessageEvent {
     subtype: Option<String>,
+    // Set on messages posted by bots and integrations, which may not have a `user`.
+    bot_id: Option<String>,
+    bot_profile: Option<BotProfile>,
     channel: String,
+    // "im" in a direct message with us.
+    #[serde(default)]
+    channel_type: Option<String>,
+    #[serde(default)]
     user: String,
     text: String,
     ts: String,
+    // Set when the message was posted in a thread.
+    thread_ts: Option<String>,
+    // Set on edits: what the message said before.
+    #[serde(skip)]
+    previous_text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct BotProfile {
+    app_id: Option<String>,
+    name: Option<String>,
+}
+
+impl MessageEvent {
+    // https://api.slack.com/events/message/message_changed
+    // The edited message comes nested, without its channel, alongside what it used to say.
+    fn from_edit(mut value: Value) -> serde_json::Result<Self> {
+        let mut message = value["message"].take();
+        message["channel"] = value["channel"].take();
+        message["channel_type"] = value["channel_type"].take();
+        let mut event: MessageEvent = serde_json::from_value(message)?;
+        event.previous_text = Some(value["previous_message"]["text"].as_str().unwrap_or_default().to_string());
+        Ok(event)
+    }
+
+    pub fn is_direct_message(&self) -> bool {
+        self.channel_type.as_deref() == Some("im")
+    }
+
+    // Whether a bot we shouldn't answer sent this. We never answer ourselves, and answering other bots
+    // risks a feedback loop, so only those listed in ALLOWED_BOTS (by bot id, app id, or name) get through.
+    pub async fn is_from_ignored_bot(&self) -> LambdaResult<bool> {
+        if bot_user_id().await?.as_deref() == Some(self.user.as_str()) {
+            return Ok(true);
+        }
+        if self.subtype.as_deref() != Some("bot_message") && self.bot_id.is_none() {
+            return Ok(false);
+        }
+        let profile = self.bot_profile.as_ref();
+        let names = [
+            self.bot_id.as_deref(),
+            profile.and_then(|profile| profile.app_id.as_deref()),
+            profile.and_then(|profile| profile.name.as_deref()),
+        ];
+        Ok(!names.iter().flatten().any(|name| is_allowed_bot(name)))
+    }
+
+    // Options for replying alongside the message: in its thread if it's in one, otherwise to the channel.
+    pub fn reply_options(&self) -> SendOptions {
+        SendOptions { thread_ts: self.thread_ts.clone(), ..Default::default() }
+    }
+
+    // Options for starting a thread under the message, or replying in the one it's already in.
+    pub fn thread_options(&self) -> SendOptions {
+        let thread_ts = self.thread_ts.clone().or_else(|| Some(self.ts.clone()));
+        SendOptions { thread_ts, ..Default::default() }
+    }
+
+    // Replies alongside the message, per `reply_options`.
+    pub async fn reply(&self, sink: &dyn MessageSink, message: &str) -> LambdaResult<()> {
+        sink.post(&self.channel, message, &self.reply_options()).await.map(|_| ())
+    }
+
+    // Replies so only the sender sees it, where Slack allows. See `send_private`.
+    pub async fn reply_privately(&self, sink: &dyn MessageSink, message: &str) -> LambdaResult<()> {
+        sink.post_private(&self.channel, &self.user, message, &self.reply_options()).await
+    }
+
+    // Says that it worked, per `acknowledge`.
+    pub async fn acknowledge(&self, sink: &dyn MessageSink, message: &str) -> LambdaResult<()> {
+        acknowledge(sink, &self.channel, &self.user, &self.ts, message, &self.reply_options()).await
+    }
+}
+
+// https://api.slack.com/events/reaction_added, and https://api.slack.com/events/reaction_removed
+#[derive(Deserialize, Debug)]
+pub struct ReactionEvent {
+    reaction: String,
+    user: String,
+    item: ReactionItem,
+    // Who posted the item that got the reaction, when it's something with an author.
+    item_user: Option<String>,
+}
+
+// https://api.slack.com/events/pin_added
+#[derive(Deserialize, Debug)]
+pub struct PinAddedEvent {
+    user: String,
+    channel_id: String,
+}
+
+// https://api.slack.com/events/member_joined_channel
+#[derive(Deserialize, Debug)]
+pub struct MemberJoinedEvent {
+    user: String,
+    channel: String,
+}
+
+// https://api.slack.com/events/app_home_opened
+#[derive(Deserialize, Debug)]
+pub struct AppHomeOpenedEvent {
+    user: String,
+    // "home", or "messages" for the DM tab.
+    #[serde(default)]
+    tab: String,
+}
+
+// https://api.slack.com/events/file_shared
+#[derive(Deserialize, Debug)]
+pub struct FileSharedEvent {
+    file_id: String,
+    user_id: String,
+    channel_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ReactionItem {
+    #[serde(rename = "message")]
+    Message { channel: String, ts: String },
+    #[serde(other)]
+    Other,
+}
+
+impl ReactionItem {
+    fn channel(&self) -> Option<&str> {
+        match self {
+            ReactionItem::Message { channel, .. } => Some(channel),
+            ReactionItem::Other => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct CallbackEvent {
+    #[serde(default)]
+    event_id: Option<String>,
+    // The workspace the event happened in.
+    #[serde(default)]
+    team_id: Option<String>,
     #[serde(deserialize_with = "deserialize_event_callback")]
     event: EventType,
 }
@@ -77,9 +491,31 @@ pub struct CallbackEvent {
 #[derive(Debug)]
 enum EventType {
     Message(MessageEvent),
+    Reaction(ReactionEvent),
+    ReactionRemoved(ReactionEvent),
+    PinAdded(PinAddedEvent),
+    MemberJoined(MemberJoinedEvent),
+    AppHomeOpened(AppHomeOpenedEvent),
+    FileShared(FileSharedEvent),
     Unsupported,
 }
 
+impl EventType {
+    // The name it gets in metrics.
+    fn kind(&self) -> &'static str {
+        match self {
+            EventType::Message(_) => "message",
+            EventType::Reaction(_) => "reaction_added",
+            EventType::ReactionRemoved(_) => "reaction_removed",
+            EventType::PinAdded(_) => "pin_added",
+            EventType::MemberJoined(_) => "member_joined_channel",
+            EventType::AppHomeOpened(_) => "app_home_opened",
+            EventType::FileShared(_) => "file_shared",
+            EventType::Unsupported => "unsupported",
+        }
+    }
+}
+
 fn deserialize_event_callback<'de, D>(deserializer: D) -> Result<EventType, D::Error> where D: Deserializer<'de> {
     let s: Value = Deserialize::deserialize(deserializer)?;
     let type_ = match s.get("type") {
@@ -88,8 +524,22 @@ fn deserialize_event_callback<'de, D>(deserializer: D) -> Result<EventType, D::E
         None => return Err(D::Error::custom("slack event missing field 'type'")),
     };
     Ok(match type_.as_str() {
+        "message" if s["subtype"] == "message_changed" =>
+            EventType::Message(MessageEvent::from_edit(s).map_err(D::Error::custom)?),
         "message" | "app_mention" =>
             EventType::Message(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "reaction_added" =>
+            EventType::Reaction(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "reaction_removed" =>
+            EventType::ReactionRemoved(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "pin_added" =>
+            EventType::PinAdded(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "member_joined_channel" =>
+            EventType::MemberJoined(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "app_home_opened" =>
+            EventType::AppHomeOpened(serde_json::from_value(s).map_err(D::Error::custom)?),
+        "file_shared" =>
+            EventType::FileShared(serde_json::from_value(s).map_err(D::Error::custom)?),
         _ => EventType::Unsupported,
     })
 }
@@ -100,71 +550,878 @@ fn respond_to_challenge(event: Value) -> LambdaResult<Value> {
     Ok(json!({ "challenge": event.challenge }))
 }
 
-async fn handle_event_callback(event: Value) -> LambdaResult<()> {
-    let event: CallbackEvent = serde_json::from_value(event)?;
+// Slack gives up retrying an event well within this.
+const EVENT_DEDUP_SECS: u64 = 60 * 60;
+
+async fn handle_event_callback(body: Value) -> LambdaResult<()> {
+    let table_name = env::var("RATE_LIMIT_TABLE")?;
+    handle_event_callback_once(&dynamo::dynamo_client(), table_name, body, |event, body| async move {
+        // Deduplicated before queueing, so the queue's own redeliveries of a failed event still get handled.
+        if queue::enabled() {
+            queue::enqueue(&body).await
+        } else {
+            process_event_callback(event).await
+        }
+    }).await
+}
+
+// Handles an event callback with `handle`, unless it already was. Slack redelivers events we were slow
+// to acknowledge, and ones we failed to handle, so only the latter get handled again.
+// The events we've seen are kept in `table_name`.
+async fn handle_event_callback_once<F, Fut>(
+    client: &impl DynamoDb, table_name: String, body: Value, handle: F,
+) -> LambdaResult<()>
+where
+    F: FnOnce(CallbackEvent, Value) -> Fut,
+    Fut: Future<Output = LambdaResult<()>>,
+{
+    let event: CallbackEvent = serde_json::from_value(body.clone())?;
     log::info!("Event callback event {:?}", event);
-    if let EventType::Message(mevent) = &event.event {
-        insult::handle_message(mevent).await?;
+    let seen = event.event_id.as_ref().map(|event_id| format!("event:{}", event_id));
+    if let Some(seen) = &seen {
+        if !rate_limit::first_seen_in(client, table_name.clone(), seen, EVENT_DEDUP_SECS).await? {
+            log::info!("Ignoring already handled event {}", seen);
+            metrics::count("DuplicateEvents", 1);
+            return Ok(());
+        }
+    }
+    let handled = handle(event, body).await;
+    // An event that failed wasn't handled, so it shouldn't be skipped as a duplicate if it comes again.
+    if let (Err(_), Some(seen)) = (&handled, &seen) {
+        if let Err(e) = rate_limit::forget_in(client, table_name, seen).await {
+            log::error!("Error forgetting {} after it failed: {}", seen, e);
+        }
+    }
+    handled
+}
+
+async fn process_event_callback(event: CallbackEvent) -> LambdaResult<()> {
+    // The channel and user are filled in once we know what kind of event it is.
+    let span = tracing::info_span!(
+        "event",
+        event_id = event.event_id.as_deref().unwrap_or_default(),
+        team_id = event.team_id.as_deref().unwrap_or_default(),
+        channel = tracing::field::Empty,
+        user = tracing::field::Empty,
+    );
+    teams::scope(event.team_id.clone(), handle_callback_event(&event.event)).instrument(span).await
+}
+
+// The other half of USE_EVENT_QUEUE: the event callbacks we acknowledged and queued.
+async fn handle_queued_events(event: queue::SqsEvent) -> LambdaResult<()> {
+    for record in event.records {
+        let event: CallbackEvent = serde_json::from_str(&record.body)?;
+        log::info!("Queued event callback {:?}", event);
+        process_event_callback(event).await?;
     }
     Ok(())
 }
 
+async fn handle_callback_event(event: &EventType) -> LambdaResult<()> {
+    metrics::count_by("EventsProcessed", "EventType", event.kind(), 1);
+    match event {
+        EventType::Message(mevent) => { handle_message(mevent).await?; },
+        EventType::Reaction(revent) => { audit::in_channel(revent.item.channel(), handle_reaction(revent)).await?; },
+        EventType::ReactionRemoved(revent) => {
+            audit::in_channel(revent.item.channel(), handle_reaction_removed(revent)).await?;
+        },
+        EventType::PinAdded(pevent) if env_flag("REACT_TO_PINS") => { insult::handle_pin_added(pevent).await?; },
+        EventType::MemberJoined(jevent) => { handle_member_joined(jevent).await?; },
+        EventType::AppHomeOpened(hevent) if hevent.tab == "home" => { home::publish(&hevent.user).await?; },
+        EventType::FileShared(fevent) => {
+            audit::in_channel(Some(&fevent.channel_id), import::handle_file_shared(fevent)).await?;
+        },
+        EventType::Unsupported => { metrics::count("UnsupportedEvents", 1); },
+        _ => (),
+    }
+    Ok(())
+}
+
+// Every message command, tried in order. Add new ones here.
+static COMMANDS: Lazy<router::Registry> = Lazy::new(|| {
+    // Before insult's, where "insult <name>" would take "insult stats" or "insult war".
+    let mut commands = insult_stats::commands();
+    commands.extend(war::commands());
+    // Before insult's, where "insult me" would take it.
+    commands.extend(image::commands());
+    commands.extend(insult::commands());
+    commands.extend(words::commands());
+    commands.extend(backup::commands());
+    commands.extend(config::commands());
+    commands.extend(admin::commands());
+    commands.extend(health::commands());
+    commands.extend(audit::commands());
+    commands.extend(filter::commands());
+    commands.extend(daily::commands());
+    commands.extend(reminders::commands());
+    commands.extend(polls::commands());
+    commands.extend(popularity::commands());
+    commands.extend(dice::commands());
+    // After insult's, so "echo insult me" still insults.
+    commands.extend(echo::commands());
+    // Last, since votes can be anywhere in a message.
+    commands.extend(karma::commands());
+    let notes = vec![
+        ("/insult [@someone]", "Insult yourself or someone else with a slash command, if it's set up."),
+        ("/addword noun|adjective <word>", "Teach me a word without telling the whole channel, if it's set up."),
+    ];
+    router::Registry::new(commands, notes)
+});
+
+async fn handle_message(event: &MessageEvent) -> LambdaResult<()> {
+    tracing::Span::current().record("channel", &event.channel.as_str()).record("user", &event.user.as_str());
+    if event.is_from_ignored_bot().await? {
+        return Ok(());
+    }
+    if let Some(previous_text) = &event.previous_text {
+        return handle_edit(event, previous_text).await;
+    }
+    if COMMANDS.dispatch(event).await? {
+        return Ok(());
+    }
+    insult::log_unmatched_command(&event.text).await?;
+    // Everything said to us directly is meant for us, so it shouldn't go unanswered.
+    if event.is_direct_message() || router::is_addressed(&event.text).await? {
+        let message = match COMMANDS.suggest(&event.text).await? {
+            Some(syntax) => format!("I didn't get that. Did you mean `{}`? Or try `help`.", syntax),
+            None => "I didn't get that, try `help`.".to_string(),
+        };
+        return event.reply_privately(&SlackSink, &message).await;
+    }
+    Ok(())
+}
+
+// An edit only counts if it turns a message that wasn't a command into one, like fixing "isnult me".
+// Anything that was already a command got handled the first time, and only the first edit that works counts.
+async fn handle_edit(event: &MessageEvent, previous_text: &str) -> LambdaResult<()> {
+    // Slack also reports unfurls as changes, without the text changing.
+    if previous_text == event.text || COMMANDS.matches(previous_text).await? || !COMMANDS.matches(&event.text).await? {
+        return Ok(());
+    }
+    if !rate_limit::first_seen(&format!("edit:{}:{}", event.channel, event.ts), EVENT_DEDUP_SECS).await? {
+        return Ok(());
+    }
+    COMMANDS.dispatch(event).await?;
+    Ok(())
+}
+
+const INTRO: &str = "Hi, I'm the insult bot — try 'insult me' or 'help'.";
+// Don't reintroduce ourselves to a channel that keeps kicking and reinviting us.
+const INTRO_COOLDOWN_SECS: u64 = 24 * 60 * 60;
+
+async fn handle_member_joined(event: &MemberJoinedEvent) -> LambdaResult<()> {
+    if !is_own_join(event, bot_user_id().await?.as_deref()) {
+        return welcome(event).await;
+    }
+    introduce(&SlackSink, &dynamo::dynamo_client(), env::var("RATE_LIMIT_TABLE")?, &event.channel).await
+}
+
+// Slack tells us we've joined a channel the same way it tells us anyone else has.
+fn is_own_join(event: &MemberJoinedEvent, bot_user_id: Option<&str>) -> bool {
+    bot_user_id == Some(event.user.as_str())
+}
+
+// Says hello in `channel`, unless we already did lately. When we did is kept in `table_name`.
+async fn introduce(
+    sink: &dyn MessageSink, client: &impl DynamoDb, table_name: String, channel: &str,
+) -> LambdaResult<()> {
+    if rate_limit::hit_many_in(client, table_name, &format!("intro:{}", channel), INTRO_COOLDOWN_SECS, 1).await? > 1 {
+        return Ok(());
+    }
+    sink.post(channel, INTRO, &SendOptions::default()).await.map(|_| ())
+}
+
+// Greets someone joining a channel, if the channel's config says how.
+async fn welcome(event: &MemberJoinedEvent) -> LambdaResult<()> {
+    let config = config::get(&event.channel).await?;
+    let welcome = match &config.welcome {
+        Some(welcome) => welcome,
+        None => return Ok(()),
+    };
+    let user_tag = format!("<@{}>", event.user);
+    let message = if welcome == "insult" {
+        if !config.insults || opt_out::is_opted_out(&event.user).await? {
+            return Ok(());
+        }
+        insult::insult_message(&insult::DynamoWords, &event.channel, &user_tag, 1, None).await?
+    } else {
+        welcome.replace("{user}", &user_tag)
+    };
+    if config.welcome_privately {
+        return send_ephemeral(&event.channel, &event.user, &message).await;
+    }
+    send_message(&event.channel, &message).await
+}
+
+async fn handle_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    if vote::enabled() {
+        vote::handle_reaction(event).await?;
+    }
+    if env_flag("ADD_VIA_REACTION") {
+        words::handle_add_reaction(event).await?;
+    }
+    if moderation::enabled() {
+        moderation::handle_reaction(event).await?;
+    }
+    admin::handle_delete_reaction(event).await?;
+    popularity::handle_reaction(event).await?;
+    insult::handle_trigger_reaction(event).await
+}
+
+// Taking a 👍 or 👎 back takes the vote back.
+async fn handle_reaction_removed(event: &ReactionEvent) -> LambdaResult<()> {
+    if vote::enabled() {
+        vote::handle_reaction_removed(event).await?;
+    }
+    if moderation::enabled() {
+        moderation::handle_reaction_removed(event).await?;
+    }
+    Ok(())
+}
+
+// Whether the feature flag env var `name` is set to a truthy value.
+pub fn env_flag(name: &str) -> bool {
+    matches!(env::var(name).as_deref(), Ok("1") | Ok("true") | Ok("yes"))
+}
+
+// Reads and parses the env var `name`, falling back to `default` when unset or malformed.
+pub fn env_parse<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// The bot's own Slack user id, from the `BOT_USER_ID` env var or else asked of Slack.
+// Other workspaces' installs have their own, saved alongside their tokens.
+pub async fn bot_user_id() -> LambdaResult<Option<String>> {
+    if let Some(id) = teams::current().await?.and_then(|team| team.bot_user_id) {
+        return Ok(Some(id));
+    }
+    static INSTANCE: OnceCell<Option<String>> = OnceCell::const_new();
+    let id = INSTANCE.get_or_try_init(fetch_bot_user_id).await?;
+    Ok(id.clone())
+}
+
+async fn fetch_bot_user_id() -> LambdaResult<Option<String>> {
+    if let Ok(id) = env::var("BOT_USER_ID") {
+        if !id.is_empty() {
+            return Ok(Some(id));
+        }
+    }
+    Ok(slack::client().await?.auth_test().await?)
+}
+
+// Bots are listed by bot id, app id, or name in the comma separated `ALLOWED_BOTS` env var.
+fn is_allowed_bot(name: &str) -> bool {
+    env::var("ALLOWED_BOTS")
+        .map(|bots| bots.split(',').any(|bot| !bot.trim().is_empty() && bot.trim() == name))
+        .unwrap_or(false)
+}
+
+// How a message gets posted. The default is a plain formatted message.
+#[derive(Clone, Default)]
+pub struct SendOptions {
+    // Post the text verbatim, without Slack interpreting *bold*, >quotes and the like.
+    pub raw: bool,
+    // Post as a reply in this thread rather than to the channel.
+    pub thread_ts: Option<String>,
+    // Block Kit blocks to show instead of the text, which stays as the notification and fallback.
+    pub blocks: Option<Value>,
+}
+
 pub async fn send_message(channel: &str, message: &str) -> LambdaResult<()> {
-    let result = _send_message(channel, message).await;
+    post_message(channel, message).await.map(|_| ())
+}
+
+pub async fn send_message_with(channel: &str, message: &str, options: &SendOptions) -> LambdaResult<()> {
+    post_message_with(channel, message, options).await.map(|_| ())
+}
+
+// Like `send_message`, but returns the `ts` Slack assigned the posted message.
+pub async fn post_message(channel: &str, message: &str) -> LambdaResult<Option<String>> {
+    post_message_with(channel, message, &SendOptions::default()).await
+}
+
+pub async fn post_message_with(channel: &str, message: &str, options: &SendOptions) -> LambdaResult<Option<String>> {
+    let result = _send_message(channel, message, options).await;
     if let Err(e) = &result {
         log::error!("Error sending message: {}", e);
+        if outbox::enabled() && e.is_retryable() {
+            if let Err(e) = outbox::enqueue(channel, message, options).await {
+                log::error!("Error saving message to the outbox: {}", e);
+            }
+        }
+    }
+    Ok(result?)
+}
+
+pub type SinkFuture<'a, T> = Pin<Box<dyn Future<Output = LambdaResult<T>> + Send + 'a>>;
+
+// Somewhere the bot's messages go, so what to say can be worked out apart from posting it.
+pub trait MessageSink: Sync {
+    // Returns the `ts` of the posted message, where there is one.
+    fn post<'a>(&'a self, channel: &'a str, message: &'a str, options: &'a SendOptions) -> SinkFuture<'a, Option<String>>;
+    fn update<'a>(&'a self, channel: &'a str, ts: &'a str, message: &'a str) -> SinkFuture<'a, ()>;
+    // Posts so only `user` sees it, where Slack allows. See `send_private`.
+    fn post_private<'a>(
+        &'a self, channel: &'a str, user: &'a str, message: &'a str, options: &'a SendOptions,
+    ) -> SinkFuture<'a, ()>;
+    // Reacts to the message at `ts` with the emoji `name`.
+    fn react<'a>(&'a self, channel: &'a str, ts: &'a str, name: &'a str) -> SinkFuture<'a, ()>;
+}
+
+// Posts to Slack, same as `post_message_with`, `update_message`, `send_private` and `react`.
+pub struct SlackSink;
+
+impl MessageSink for SlackSink {
+    fn post<'a>(&'a self, channel: &'a str, message: &'a str, options: &'a SendOptions) -> SinkFuture<'a, Option<String>> {
+        Box::pin(post_message_with(channel, message, options))
+    }
+
+    fn update<'a>(&'a self, channel: &'a str, ts: &'a str, message: &'a str) -> SinkFuture<'a, ()> {
+        Box::pin(update_message(channel, ts, message))
+    }
+
+    fn post_private<'a>(
+        &'a self, channel: &'a str, user: &'a str, message: &'a str, options: &'a SendOptions,
+    ) -> SinkFuture<'a, ()> {
+        Box::pin(send_private(channel, user, message, options))
+    }
+
+    fn react<'a>(&'a self, channel: &'a str, ts: &'a str, name: &'a str) -> SinkFuture<'a, ()> {
+        Box::pin(react(channel, ts, name))
     }
-    result
 }
 
-async fn _send_message(channel: &str, message: &str) -> LambdaResult<()> {
-    let token = env::var("SLACK_TOKEN")?;
-    let https = HttpsConnector::new()?;
-    let client: Client<_, Body> = Client::builder()
-        .pool_idle_timeout(Duration::from_secs(58))
-        .build(https);
-
-    let request = Request::builder()
-        .method(Method::POST)
-        .uri("https://slack.com/api/chat.postMessage")
-        .header("content-type", "application/json; charset=utf-8")
-        .header("accept", "*/*")
-        .header("Authorization", format!("Bearer {}", token))
-        .body(Body::from(json!({
-            "text": message,
-            "channel": channel,
-        }).to_string()))?;
-
-    let response = client.request(request).await?;
-    let bytes = body::to_bytes(response.into_body()).await?;
-    let body: Value = serde_json::from_slice(&bytes)?;
-    match body.get("ok") {
-        Some(Value::Bool(true)) => (),
-        Some(Value::Bool(false)) => { log::error!("Slack error: {}", body); },
-        _ => { log::error!("Malformed Slack response: {}", body); },
+// For the outbox to retry a message without queueing it again when it fails.
+pub async fn resend_message(channel: &str, message: &str, options: &SendOptions) -> BotResult<()> {
+    let result = _send_message(channel, message, options).await;
+    if let Err(e) = &result {
+        log::error!("Error resending message: {}", e);
     }
+    result?;
     Ok(())
 }
 
-async fn route_request(event: ApiGatewayEvent) -> LambdaResult<Value> {
-    let ApiGatewayEvent { body, .. } = event;
+fn message_payload(channel: &str, message: &str, options: &SendOptions) -> Value {
+    let mut payload = json!({
+        "text": message,
+        "channel": channel,
+    });
+    if options.raw {
+        payload["mrkdwn"] = json!(false);
+    }
+    if let Some(thread_ts) = &options.thread_ts {
+        payload["thread_ts"] = json!(thread_ts);
+    }
+    if let Some(blocks) = &options.blocks {
+        payload["blocks"] = blocks.clone();
+    }
+    payload
+}
+
+async fn _send_message(channel: &str, message: &str, options: &SendOptions) -> BotResult<Option<String>> {
+    slack::client().await?.post_message(message_payload(channel, message, options)).await
+}
+
+// Posts a message only `user` can see. It's gone once they reload Slack.
+pub async fn send_ephemeral(channel: &str, user: &str, message: &str) -> LambdaResult<()> {
+    let result = _send_ephemeral(channel, user, message, &SendOptions::default()).await;
+    if let Err(e) = &result {
+        log::error!("Error sending ephemeral message: {}", e);
+    }
+    Ok(result?)
+}
+
+// Ephemeral messages don't get a `ts`, they answer with a `message_ts` that can't be updated or threaded on.
+async fn _send_ephemeral(channel: &str, user: &str, message: &str, options: &SendOptions) -> BotResult<()> {
+    let mut payload = message_payload(channel, message, options);
+    payload["user"] = json!(user);
+    slack::client().await?.post_ephemeral(payload).await
+}
+
+// Confirmations that only matter to whoever asked go just to them, so they don't clutter the channel.
+// Slack won't always allow that, eg for users outside the channel, so then everyone sees it.
+pub async fn send_private(channel: &str, user: &str, message: &str, options: &SendOptions) -> LambdaResult<()> {
+    match _send_ephemeral(channel, user, message, options).await {
+        Err(BotError::Slack(e)) => {
+            log::warn!("Couldn't send an ephemeral message, sending it to everyone: {}", e);
+            send_message_with(channel, message, options).await
+        },
+        result => Ok(result?),
+    }
+}
+
+// Posts a Block Kit message. `text` is what notifications and clients without blocks show.
+pub async fn send_blocks(channel: &str, text: &str, blocks: &[Block]) -> LambdaResult<()> {
+    send_message_with(channel, text, &SendOptions { blocks: Some(blocks::to_value(blocks)), ..Default::default() }).await
+}
+
+// What we react with to say a command worked, in channels that would rather not hear about it.
+const ACK_EMOJI: &str = "white_check_mark";
+
+// Tells `user` the message at `ts` did what they asked: with a ✅ on it where the channel has reactions on,
+// otherwise by replying privately with `message`.
+pub async fn acknowledge(
+    sink: &dyn MessageSink, channel: &str, user: &str, ts: &str, message: &str, options: &SendOptions,
+) -> LambdaResult<()> {
+    if config::get(channel).await?.reactions {
+        return sink.react(channel, ts, ACK_EMOJI).await;
+    }
+    sink.post_private(channel, user, message, options).await
+}
+
+// Reacts to the message at `ts` with the emoji `name`, eg "white_check_mark".
+pub async fn react(channel: &str, ts: &str, name: &str) -> LambdaResult<()> {
+    let result = slack::client().await?.add_reaction(channel, ts, name).await;
+    if let Err(e) = &result {
+        log::error!("Error reacting to a message: {}", e);
+    }
+    Ok(result?)
+}
+
+// Takes down the message the bot previously posted at `ts`.
+pub async fn delete_message(channel: &str, ts: &str) -> LambdaResult<()> {
+    let result = slack::client().await?.delete_message(channel, ts).await;
+    if let Err(e) = &result {
+        log::error!("Error deleting message: {}", e);
+    }
+    Ok(result?)
+}
+
+// Replaces the text of the message the bot previously posted at `ts`.
+pub async fn update_message(channel: &str, ts: &str, message: &str) -> LambdaResult<()> {
+    let result = slack::client().await?.update_message(channel, ts, message).await;
+    if let Err(e) = &result {
+        log::error!("Error updating message: {}", e);
+    }
+    Ok(result?)
+}
+
+// Looks up the text of the top level message at `ts`.
+pub async fn fetch_message_text(channel: &str, ts: &str) -> LambdaResult<Option<String>> {
+    Ok(slack::client().await?.message_text(channel, ts).await?)
+}
+
+// Shares a file in `channel`, in the thread at `thread_ts` if there is one.
+pub async fn upload_file(
+    channel: &str, thread_ts: Option<&str>, filename: &str, bytes: Vec<u8>, comment: &str,
+) -> LambdaResult<()> {
+    let result = slack::client().await?.upload_file(channel, thread_ts, filename, bytes, comment).await;
+    if let Err(e) = &result {
+        log::error!("Error uploading file: {}", e);
+    }
+    Ok(result?)
+}
+
+// Returns the message to answer the command with.
+async fn handle_slash_command(command: &SlashCommand) -> LambdaResult<Value> {
+    log::info!("Slash command {:?}", command);
+    let handled = async {
+        match command.command.as_str() {
+            "/addword" => words::handle_add_word_slash_command(command).await,
+            _ => insult::handle_slash_command(command).await,
+        }
+    };
+    let handled = audit::in_channel(Some(&command.channel_id), insult_stats::as_insulter(&command.user_id, handled));
+    teams::scope(command.team_id.clone(), handled).await
+}
+
+async fn route_request(event: ApiGatewayEvent) -> LambdaResult<ApiGatewayResponse> {
+    // A person's browser rather than Slack itself, so there's no signature to check.
+    if event.is_oauth_redirect() {
+        let (code, error, state) = (event.query_param("code"), event.query_param("error"), event.query_param("state"));
+        let (status_code, page) = oauth::handle_redirect(code, error, state).await;
+        return Ok(ApiGatewayResponse::html(status_code, page));
+    }
+    if event.is_install_link() {
+        return Ok(ApiGatewayResponse::redirect(oauth::install_url().await?));
+    }
+    if event.is_health_check() {
+        return Ok(ApiGatewayResponse::ok(health::check()));
+    }
+    if !event.is_from_slack().await {
+        log::warn!("Rejecting request with a missing or invalid Slack signature");
+        return Ok(ApiGatewayResponse::unauthorized("invalid signature"));
+    }
+    // Retries go through like anything else: `handle_event_callback_once` knows whether the original was handled.
+    if event.retry_num() > 0 {
+        log::info!("Slack retry #{} ({})", event.retry_num(), event.retry_reason());
+    }
+    let is_form = event.header("Content-Type")
+        .is_some_and(|content_type| content_type.starts_with("application/x-www-form-urlencoded"));
+    if is_form {
+        if let Ok(form) = serde_urlencoded::from_str::<interactive::InteractionForm>(&event.body) {
+            let response = interactive::handle_interaction(&form).await?;
+            return Ok(ApiGatewayResponse::ok(response.unwrap_or_else(|| json!({ "ok": true }))));
+        }
+        let command: SlashCommand = match serde_urlencoded::from_str(&event.body) {
+            Ok(command) => command,
+            Err(_) => return Ok(ApiGatewayResponse::bad_request("malformed slash command")),
+        };
+        return Ok(ApiGatewayResponse::ok(handle_slash_command(&command).await?));
+    }
+    let body: Value = match serde_json::from_str(&event.body) {
+        Ok(body) => body,
+        Err(_) => return Ok(ApiGatewayResponse::bad_request("body is not valid json")),
+    };
     let type_ = match body.get("type") {
         Some(Value::String(t)) => t,
-        Some(_) => return Ok(json!({"error": "expected string for field 'type'"})),
-        None => return Ok(json!({"error": "slack event missing field 'type'"})),
+        Some(_) => return Ok(ApiGatewayResponse::bad_request("expected string for field 'type'")),
+        None => return Ok(ApiGatewayResponse::bad_request("slack event missing field 'type'")),
     };
     log::info!("Payload body: {:?}", body);
     match type_.as_str() {
-        "url_verification" => { return respond_to_challenge(body); },
+        "url_verification" => { return respond_to_challenge(body).map(ApiGatewayResponse::ok); },
         "event_callback" => { handle_event_callback(body).await?; },
         _ => (),
     };
 
-    Ok(json!( { "ok": true } ))
+    Ok(ApiGatewayResponse::ok(json!( { "ok": true } )))
+}
+
+// Like `route_request`, but answers with the error when we know what it means.
+// A payload we couldn't make sense of is Slack's problem, not ours, so it's a 400 rather than a failed invocation.
+async fn respond(event: ApiGatewayEvent) -> LambdaResult<ApiGatewayResponse> {
+    let e = match route_request(event).await {
+        Ok(response) => return Ok(response),
+        Err(e) => e,
+    };
+    if let Some(bot_error) = e.downcast_ref::<BotError>() {
+        return Ok(ApiGatewayResponse::bot_error(bot_error));
+    }
+    if let Some(serde_error) = e.downcast_ref::<serde_json::Error>() {
+        log::error!("Error reading request: {}", serde_error);
+        return Ok(ApiGatewayResponse::bad_request(&serde_error.to_string()));
+    }
+    Err(e)
 }
 
 async fn api_gateway_func(event: Value, _: Context) -> LambdaResult<Value> {
-    let event: ApiGatewayEvent = serde_json::from_value(event)?;
-    let body = route_request(event).await?;
-    Ok(serde_json::to_value(ApiGatewayResponse::ok(body))?)
+    // Test invokes sometimes put the JSON itself in `body`, which would otherwise read as no API Gateway event at all.
+    if !matches!(event.get("body"), None | Some(Value::Null) | Some(Value::String(_))) {
+        return Ok(serde_json::to_value(ApiGatewayResponse::body_error(&BodyError::NotText))?);
+    }
+    let event: ApiGatewayEvent = match serde_json::from_value(event) {
+        Ok(event) => event,
+        Err(e) => {
+            log::error!("Not an API Gateway event: {}", e);
+            return Ok(serde_json::to_value(ApiGatewayResponse::bad_request("unrecognized request"))?);
+        },
+    };
+    let event = match event.normalized() {
+        Ok(event) => event,
+        Err(e) => return Ok(serde_json::to_value(ApiGatewayResponse::body_error(&e))?),
+    };
+    Ok(serde_json::to_value(respond(event).await?)?)
+}
+
+async fn handle_scheduled_event(event: ScheduledEvent) -> LambdaResult<()> {
+    match event.scheduled_task {
+        ScheduledTask::Backup => backup::backup_vocabulary().await,
+        ScheduledTask::WeeklyDigest => digest::post_weekly_digest().await,
+        ScheduledTask::OutboxSweep => outbox::sweep().await,
+        ScheduledTask::DailyInsults => daily::post_due().await,
+    }
+}
+
+async fn lambda_func(event: Value, context: Context) -> LambdaResult<Value> {
+    let span = tracing::info_span!("invocation", request_id = %context.request_id);
+    let result = handle_invocation(event, context).instrument(span).await;
+    if let Err(e) = &result {
+        health::record_error("invocation", e);
+    }
+    metrics::flush();
+    result
+}
+
+async fn handle_invocation(event: Value, context: Context) -> LambdaResult<Value> {
+    if event.get("scheduled_task").is_some() {
+        handle_scheduled_event(serde_json::from_value(event)?).await?;
+        return Ok(json!({ "ok": true }));
+    }
+    if queue::is_sqs_event(&event) {
+        handle_queued_events(serde_json::from_value(event)?).await?;
+        return Ok(json!({ "ok": true }));
+    }
+    api_gateway_func(event, context).await
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::{Hmac, Mac, NewMac};
+    use sha2::Sha256;
+    use std::sync::Mutex;
+
+    use super::*;
+    use rusoto_core::Region;
+    use rusoto_dynamodb::DynamoDbClient;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher, MultipleMockRequestDispatcher};
+
+    use crate::dynamo::mock::{client_answering, recording, Requests};
+
+    // What got sent, as "post <channel> <message>", "update <channel> <ts> <message>",
+    // "private <channel> <user> <message>" and "react <channel> <ts> <name>". Posts get ts "1", "2" and so on.
+    // `threads` has the thread each post and private message went to, in order.
+    #[derive(Default)]
+    pub struct RecordingSink {
+        pub sent: Mutex<Vec<String>>,
+        pub threads: Mutex<Vec<Option<String>>>,
+    }
+
+    impl MessageSink for RecordingSink {
+        fn post<'a>(
+            &'a self, channel: &'a str, message: &'a str, options: &'a SendOptions,
+        ) -> SinkFuture<'a, Option<String>> {
+            self.threads.lock().unwrap().push(options.thread_ts.clone());
+            let mut sent = self.sent.lock().unwrap();
+            sent.push(format!("post {} {}", channel, message));
+            let ts = sent.len().to_string();
+            Box::pin(async move { Ok(Some(ts)) })
+        }
+
+        fn update<'a>(&'a self, channel: &'a str, ts: &'a str, message: &'a str) -> SinkFuture<'a, ()> {
+            self.sent.lock().unwrap().push(format!("update {} {} {}", channel, ts, message));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn post_private<'a>(
+            &'a self, channel: &'a str, user: &'a str, message: &'a str, options: &'a SendOptions,
+        ) -> SinkFuture<'a, ()> {
+            self.threads.lock().unwrap().push(options.thread_ts.clone());
+            self.sent.lock().unwrap().push(format!("private {} {} {}", channel, user, message));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn react<'a>(&'a self, channel: &'a str, ts: &'a str, name: &'a str) -> SinkFuture<'a, ()> {
+            self.sent.lock().unwrap().push(format!("react {} {} {}", channel, ts, name));
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    // Held by tests that set process-wide env vars or statics, and by those that read what they set,
+    // so tests running in parallel don't see each other's values.
+    pub static GLOBALS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    const SIGNING_SECRET: &str = "signing secret";
+
+    // A message event as Slack would send it, signed, with `retry_num` in X-Slack-Retry-Num if it's a retry.
+    fn signed_event(retry_num: Option<u32>) -> ApiGatewayEvent {
+        let body = json!({
+            "type": "event_callback",
+            "event_id": "Ev0123",
+            "event": { "type": "message", "channel": "C1", "user": "U1", "text": "insult <@U2>", "ts": "1.2" },
+        });
+        signed_request(body.to_string(), retry_num)
+    }
+
+    fn signed_request(body: String, retry_num: Option<u32>) -> ApiGatewayEvent {
+        let timestamp = now().to_string();
+        let mut mac = Hmac::<Sha256>::new_varkey(SIGNING_SECRET.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        let mut headers = HashMap::new();
+        headers.insert("X-Slack-Request-Timestamp".to_string(), timestamp);
+        headers.insert("X-Slack-Signature".to_string(), format!("v0={}", hex::encode(mac.finalize().into_bytes())));
+        headers.insert("Content-Type".to_string(), "application/json".to_string());
+        if let Some(retry_num) = retry_num {
+            headers.insert("X-Slack-Retry-Num".to_string(), retry_num.to_string());
+            headers.insert("X-Slack-Retry-Reason".to_string(), "http_timeout".to_string());
+        }
+        let event = json!({ "body": body, "headers": headers, "httpMethod": "POST", "path": "/" });
+        serde_json::from_value(event).unwrap()
+    }
+
+    // Without RATE_LIMIT_TABLE, handling the event fails before it gets anywhere near Slack,
+    // so an error means it was handled, not acknowledged and dropped.
+    #[tokio::test]
+    async fn retries_are_handled_like_first_attempts() {
+        let _globals = GLOBALS.lock().await;
+        env::set_var("SLACK_SIGNING_SECRET", SIGNING_SECRET);
+        env::remove_var("RATE_LIMIT_TABLE");
+        assert!(route_request(signed_event(None)).await.is_err());
+        assert!(route_request(signed_event(Some(1))).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_events_that_failed_are_handled_again() {
+        let body = json!({
+            "type": "event_callback",
+            "event_id": "Ev0123",
+            "event": { "type": "message", "channel": "C1", "user": "U1", "text": "insult <@U2>", "ts": "1.2" },
+        });
+        let already_seen = r#"{"__type":"com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException"}"#;
+        let requests = Requests::default();
+        let answers = [(200, "{}"), (200, "{}"), (200, "{}"), (400, already_seen)];
+        let dispatchers: Vec<_> = answers.iter()
+            .map(|(status, body)| recording(MockRequestDispatcher::with_status(*status).with_body(body), &requests))
+            .collect();
+        let dispatcher = MultipleMockRequestDispatcher::new(dispatchers);
+        let client = DynamoDbClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1);
+        let attempts = Mutex::new(0);
+        let attempt = |fails: bool| {
+            let attempts = &attempts;
+            move |_, _| async move {
+                *attempts.lock().unwrap() += 1;
+                if fails { Err(BotError::Http("timed out".to_string()).into()) } else { Ok(()) }
+            }
+        };
+
+        let once = |fails| handle_event_callback_once(&client, "rate_limits".to_string(), body.clone(), attempt(fails));
+        assert!(once(true).await.is_err());
+        // The retry of the one that failed gets handled, but not the retry of that.
+        once(false).await.unwrap();
+        once(false).await.unwrap();
+        assert_eq!(*attempts.lock().unwrap(), 2);
+        // Between the two, the failed attempt was forgotten.
+        assert_eq!(requests.lock().unwrap()[1]["Key"]["key"]["S"], "event:Ev0123");
+    }
+
+    fn joined(user: &str) -> MemberJoinedEvent {
+        serde_json::from_value(json!({
+            "type": "member_joined_channel", "user": user, "channel": "C1", "channel_type": "C", "team": "T1",
+        })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn we_introduce_ourselves_once_when_we_join() {
+        assert!(is_own_join(&joined("UBOT"), Some("UBOT")));
+        assert!(!is_own_join(&joined("U1"), Some("UBOT")));
+        assert!(!is_own_join(&joined("U1"), None));
+
+        let hits = |hits: u64| json!({ "Attributes": { "hits": { "N": hits.to_string() } } });
+        let (client, _) = client_answering(vec![hits(1), hits(2)]);
+        let sink = RecordingSink::default();
+        introduce(&sink, &client, "rate_limits".to_string(), "C1").await.unwrap();
+        introduce(&sink, &client, "rate_limits".to_string(), "C1").await.unwrap();
+        assert_eq!(*sink.sent.lock().unwrap(), [format!("post C1 {}", INTRO)]);
+    }
+
+    #[test]
+    fn raw_messages_turn_off_mrkdwn() {
+        let raw = message_payload("C1", "*not bold*", &SendOptions { raw: true, ..Default::default() });
+        assert_eq!(raw, json!({ "text": "*not bold*", "channel": "C1", "mrkdwn": false }));
+        let formatted = message_payload("C1", "*bold*", &SendOptions::default());
+        assert_eq!(formatted, json!({ "text": "*bold*", "channel": "C1" }));
+    }
+
+    fn message(fields: Value) -> MessageEvent {
+        let mut event = json!({ "channel": "C1", "text": "insult me", "ts": "1.1" });
+        event.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(event).unwrap()
+    }
+
+    #[tokio::test]
+    async fn bot_messages_are_ignored_before_any_slack_call() {
+        let _globals = GLOBALS.lock().await;
+        env::set_var("BOT_USER_ID", "UBOT");
+        let from_a_bot = message(json!({ "subtype": "bot_message", "bot_id": "B1" }));
+        assert!(from_a_bot.is_from_ignored_bot().await.unwrap());
+        // Anything past the check would need Slack, or dynamo for the channel's config, and fail here.
+        handle_message(&from_a_bot).await.unwrap();
+        let from_us = message(json!({ "user": "UBOT" }));
+        assert!(from_us.is_from_ignored_bot().await.unwrap());
+        handle_message(&from_us).await.unwrap();
+
+        let from_a_person = message(json!({ "user": "U1" }));
+        assert!(!from_a_person.is_from_ignored_bot().await.unwrap());
+    }
+
+    #[test]
+    fn each_command_is_reached_with_its_arguments() {
+        let cases = [
+            ("insult me", "insult me", ""),
+            ("insult <@U1>", "insult @someone [@someone else…]", " <@U1>"),
+            ("add noun clown", "add noun|adjective <word> [tagged <tag>]", "noun"),
+            ("add adjective smelly tagged sfw", "add noun|adjective <word> [tagged <tag>]", "adjective"),
+            ("insult stats", "insult stats", ""),
+            ("insult war <@U1> <@U2>", "insult war @someone @someone", "U1"),
+            ("insult me hard", "insult me hard", ""),
+            ("insult me sfw", "insult me <tag>", "sfw"),
+            ("insult bob", "insult <name>", "bob"),
+            ("remove matching /^c/ confirm", "remove matching <text or /regex/>", "/^c/"),
+            ("config set theme emoji", "config set theme emoji|default", "emoji"),
+            ("remind me in 20m to stretch", "remind me in <20m, 2 hours, 1d…> to <thing>", "20m"),
+            ("roll 3d6+2", "roll <dice, eg 3d6+2 or d20 advantage>", "3d6+2"),
+            ("karma top", "karma top", ""),
+            ("rust++", "<thing>++ / <thing>--", ""),
+        ];
+        for (text, syntax, first) in cases {
+            let (parsed, args) = COMMANDS.parse(text).unwrap_or_else(|| panic!("{:?} didn't parse", text));
+            assert_eq!((parsed, args.get(1)), (syntax, first), "{:?}", text);
+        }
+        let (_, args) = COMMANDS.parse("add adjective smelly tagged sfw").unwrap();
+        assert_eq!((args.get(2), args.get(3)), ("smelly", "sfw"));
+        assert!(COMMANDS.parse("what's for lunch").is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_payloads_are_bad_requests() {
+        let _globals = GLOBALS.lock().await;
+        env::set_var("SLACK_SIGNING_SECRET", SIGNING_SECRET);
+        for body in [r#"{"event_id": "Ev0123"}"#, r#"{"type": 5}"#, r#"{"type": null}"#, "not json"] {
+            let response = route_request(signed_request(body.to_string(), None)).await.unwrap();
+            assert_eq!(response.status_code, 400, "{}", body);
+        }
+        let body = json!({ "type": "url_verification", "challenge": "abc" }).to_string();
+        assert_eq!(route_request(signed_request(body, None)).await.unwrap().status_code, 200);
+    }
+
+    #[test]
+    fn failures_keep_their_detail_out_of_the_response() {
+        let response = ApiGatewayResponse::bot_error(&BotError::Dynamo("no table named prod-insults".to_string()));
+        assert_eq!(response.status_code, 502);
+        assert!(!response.body.contains("prod-insults"), "{}", response.body);
+        let response = ApiGatewayResponse::bot_error(&BotError::MissingEnv("SLACK_CLIENT_SECRET".to_string()));
+        assert_eq!(response.status_code, 500);
+        assert!(!response.body.contains("SLACK_CLIENT_SECRET"), "{}", response.body);
+    }
+
+    #[test]
+    fn reaction_added_events_deserialize() {
+        let body = json!({
+            "type": "event_callback",
+            "team_id": "T1",
+            "event_id": "Ev0123",
+            "event": {
+                "type": "reaction_added",
+                "user": "U1",
+                "reaction": "clown_face",
+                "item_user": "U2",
+                "item": { "type": "message", "channel": "C1", "ts": "1360782400.498405" },
+                "event_ts": "1360782804.083113",
+            },
+        });
+        let callback: CallbackEvent = serde_json::from_value(body).unwrap();
+        let event = match callback.event {
+            EventType::Reaction(event) => event,
+            other => panic!("{:?} isn't a reaction", other),
+        };
+        assert_eq!((event.reaction.as_str(), event.user.as_str()), ("clown_face", "U1"));
+        assert_eq!(event.item_user.as_deref(), Some("U2"));
+        assert!(matches!(&event.item, ReactionItem::Message { channel, ts } if channel == "C1" && ts == "1360782400.498405"));
+
+        // Reactions to files don't have a channel to answer in.
+        let on_a_file: ReactionEvent = serde_json::from_value(json!({
+            "type": "reaction_added", "user": "U1", "reaction": "clown_face",
+            "item": { "type": "file", "file": "F1" },
+        })).unwrap();
+        assert!(matches!(on_a_file.item, ReactionItem::Other));
+        assert_eq!(on_a_file.item_user, None);
+    }
+
+    #[test]
+    fn blocks_ride_alongside_the_text() {
+        let plain = message_payload("C1", "you clown", &SendOptions::default());
+        assert_eq!(plain.to_string(), r#"{"channel":"C1","text":"you clown"}"#);
+
+        let blocks = blocks::to_value(&[blocks::Block::section("*you clown*")]);
+        let options = SendOptions { blocks: Some(blocks.clone()), ..Default::default() };
+        let with_blocks = message_payload("C1", "you clown", &options);
+        assert_eq!(with_blocks["text"], "you clown");
+        assert_eq!(with_blocks["blocks"], blocks);
+        assert_eq!(blocks[0], json!({ "type": "section", "text": { "type": "mrkdwn", "text": "*you clown*" } }));
+    }
 }
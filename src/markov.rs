@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rusoto_dynamodb::ScanInput;
+use tokio::sync::OnceCell;
+
+use crate::dynamo::{dynamo_client, scan_all};
+use crate::LambdaResult;
+
+// A word-level Markov chain, for insults nobody wrote. Trained on the vocabulary and, optionally,
+// lines of insulting prose kept as `text` in MARKOV_CORPUS_TABLE.
+
+// Longer than this and it stops making sense.
+const MAX_WORDS: usize = 8;
+const ATTEMPTS: usize = 10;
+
+#[derive(Default)]
+pub struct Chain {
+    // The words seen after each word. None is the start and the end of a sequence.
+    next: HashMap<Option<String>, Vec<Option<String>>>,
+    // What it was trained on, so it doesn't just repeat one back.
+    seen: HashSet<Vec<String>>,
+}
+
+impl Chain {
+    pub fn train(&mut self, sequence: Vec<String>) {
+        if sequence.is_empty() {
+            return;
+        }
+        let mut previous = None;
+        for word in &sequence {
+            self.next.entry(previous).or_default().push(Some(word.clone()));
+            previous = Some(word.clone());
+        }
+        self.next.entry(previous).or_default().push(None);
+        self.seen.insert(sequence);
+    }
+
+    // A walk through the chain of at least two words that isn't one it was trained on, if it finds one.
+    pub fn generate(&self, rng: &mut impl Rng) -> Option<Vec<String>> {
+        (0..ATTEMPTS).find_map(|_| {
+            let words = self.walk(rng);
+            (words.len() >= 2 && !self.seen.contains(&words)).then_some(words)
+        })
+    }
+
+    fn walk(&self, rng: &mut impl Rng) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current = None;
+        while words.len() < MAX_WORDS {
+            current = match self.next.get(&current).and_then(|next| next.choose(rng)) {
+                Some(Some(word)) => Some(word.clone()),
+                _ => break,
+            };
+            words.extend(current.clone());
+        }
+        words
+    }
+}
+
+// Lowercased words, without the punctuation around them.
+pub fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != '-' && c != '\'').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+// The seed corpus, read once per container.
+pub async fn corpus() -> LambdaResult<&'static [Vec<String>]> {
+    static CORPUS: OnceCell<Vec<Vec<String>>> = OnceCell::const_new();
+    Ok(CORPUS.get_or_try_init(fetch_corpus).await?)
+}
+
+async fn fetch_corpus() -> LambdaResult<Vec<Vec<String>>> {
+    let table_name = match std::env::var("MARKOV_CORPUS_TABLE") {
+        Ok(table_name) if !table_name.is_empty() => table_name,
+        _ => return Ok(Vec::new()),
+    };
+    let client = dynamo_client();
+    let items = scan_all(&client, ScanInput { table_name, ..Default::default() }).await?;
+    Ok(items.iter()
+        .filter_map(|item| item.get("text")?.s.as_deref().map(words))
+        .filter(|words| !words.is_empty())
+        .collect())
+}
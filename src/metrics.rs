@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+
+use crate::now;
+
+// Metrics go out as CloudWatch Embedded Metric Format lines on stdout,
+// which CloudWatch Logs turns into metrics without us calling any AWS API.
+// https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+// They're collected over an invocation and written out together by `flush`, one line per dimension.
+const NAMESPACE: &str = "SlackBotRust";
+const SERVICE: &str = "slack-bot";
+
+// CloudWatch takes at most this many values of one metric in one document.
+const MAX_VALUES: usize = 100;
+
+// An extra dimension to split a metric by, eg ("EventType", "message").
+type Dimension = Option<(&'static str, String)>;
+
+struct Metric {
+    unit: &'static str,
+    values: Vec<u64>,
+}
+
+static PENDING: Lazy<Mutex<BTreeMap<(Dimension, String), Metric>>> = Lazy::new(Default::default);
+
+fn emf_document(dimension: &Dimension, metrics: &[(&str, &Metric)], timestamp_ms: u64) -> Value {
+    let mut dimensions = vec!["Service"];
+    let mut document = Map::new();
+    document.insert("Service".to_string(), Value::from(SERVICE));
+    if let Some((name, value)) = dimension {
+        dimensions.push(name);
+        document.insert(name.to_string(), Value::from(value.as_str()));
+    }
+    let definitions: Vec<_> = metrics.iter().map(|(name, metric)| json!({ "Name": name, "Unit": metric.unit })).collect();
+    for (name, metric) in metrics {
+        let value = match metric.values.as_slice() {
+            [value] => Value::from(*value),
+            values => Value::from(values),
+        };
+        document.insert(name.to_string(), value);
+    }
+    document.insert("_aws".to_string(), json!({
+        "Timestamp": timestamp_ms,
+        "CloudWatchMetrics": [{
+            "Namespace": NAMESPACE,
+            "Dimensions": [dimensions],
+            "Metrics": definitions,
+        }],
+    }));
+    Value::Object(document)
+}
+
+fn add(name: &str, dimension: Dimension, unit: &'static str, value: u64) {
+    let mut pending = PENDING.lock().unwrap();
+    let metric = pending.entry((dimension, name.to_string())).or_insert_with(|| Metric { unit, values: Vec::new() });
+    // Counts add up, since only the total is ever wanted.
+    if let ("Count", Some(total)) = (unit, metric.values.first_mut()) {
+        *total += value;
+    } else if metric.values.len() < MAX_VALUES {
+        metric.values.push(value);
+    }
+}
+
+pub fn count(name: &str, value: u64) {
+    add(name, None, "Count", value);
+}
+
+// Like `count`, split by `dimension`, eg how many of each type of event came in.
+pub fn count_by(name: &str, dimension: &'static str, dimension_value: &str, value: u64) {
+    add(name, Some((dimension, dimension_value.to_string())), "Count", value);
+}
+
+// How long something took, split by `dimension`.
+pub fn time_by(name: &str, dimension: &'static str, dimension_value: &str, elapsed: Duration) {
+    add(name, Some((dimension, dimension_value.to_string())), "Milliseconds", elapsed.as_millis() as u64);
+}
+
+// Writes out everything collected since the last flush. Called at the end of each invocation.
+pub fn flush() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    let timestamp_ms = now() * 1000;
+    let mut groups: BTreeMap<&Dimension, Vec<(&str, &Metric)>> = BTreeMap::new();
+    for ((dimension, name), metric) in &pending {
+        groups.entry(dimension).or_default().push((name, metric));
+    }
+    for (dimension, metrics) in groups {
+        println!("{}", emf_document(dimension, &metrics, timestamp_ms));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_counter_is_one_emf_document() {
+        let metric = Metric { unit: "Count", values: vec![3] };
+        let document = emf_document(&None, &[("InsultsGenerated", &metric)], 1_600_000_000_000);
+        assert_eq!(document, json!({
+            "Service": "slack-bot",
+            "InsultsGenerated": 3,
+            "_aws": {
+                "Timestamp": 1_600_000_000_000u64,
+                "CloudWatchMetrics": [{
+                    "Namespace": "SlackBotRust",
+                    "Dimensions": [["Service"]],
+                    "Metrics": [{ "Name": "InsultsGenerated", "Unit": "Count" }],
+                }],
+            },
+        }));
+    }
+
+    #[test]
+    fn dimensions_and_timings_are_listed() {
+        let metric = Metric { unit: "Milliseconds", values: vec![12, 40] };
+        let dimension = Some(("Command", "insult me".to_string()));
+        let document = emf_document(&dimension, &[("CommandLatency", &metric)], 0);
+        assert_eq!(document["Command"], "insult me");
+        assert_eq!(document["CommandLatency"], json!([12, 40]));
+        assert_eq!(document["_aws"]["CloudWatchMetrics"][0]["Dimensions"], json!([["Service", "Command"]]));
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemError, DeleteItemInput, DynamoDb, PutItemInput, UpdateItemError, UpdateItemInput,
+};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr, string_set_attr};
+use crate::insult::{self, PartOfSpeech};
+use crate::{bot_user_id, env_flag, env_parse, now, post_message, send_message, slack, LambdaResult, ReactionEvent, ReactionItem};
+
+// With REACTION_MODERATION, words are added right away but the "Added" message stays open to 👎s,
+// and enough of them take the word back out. Unlike MODERATION_VOTE, nobody has to wait.
+// Reacting takes the app's reactions:write scope, and taking 👎s back needs the reaction_removed event.
+
+const UPVOTE: &str = "+1";
+const DOWNVOTE: &str = "-1";
+
+pub fn enabled() -> bool {
+    env_flag("REACTION_MODERATION")
+}
+
+// 👎s, not counting ours, that remove a word.
+fn threshold() -> i64 {
+    env_parse("REMOVE_THRESHOLD", 3)
+}
+
+// How long after it's added a word can be voted out.
+fn window() -> u64 {
+    env_parse("REMOVE_WINDOW_SECS", 7 * 24 * 60 * 60)
+}
+
+// Timestamps are only unique within a channel, so the key is both, eg "C123:1612345678.000200".
+fn message_key(channel: &str, ts: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("ts".to_string(), string_attr(format!("{}:{}", channel, ts)));
+    key
+}
+
+// Announces the freshly added word, with our own 👍 and 👎 to show people how to vote,
+// and remembers which word the message is about.
+pub async fn announce_added(channel: &str, user: &str, pos: &PartOfSpeech, word: &str) -> LambdaResult<()> {
+    let message = format!(
+        "<@{}> added the {} \"{}\". {} :-1: and it's gone.",
+        user, pos.name(), word, threshold(),
+    );
+    let ts = match post_message(channel, &message).await? {
+        Some(ts) => ts,
+        None => return Ok(()),
+    };
+    insert_added_message(channel, &ts, insult::encode_word(pos, word.to_string())).await?;
+    let client = slack::client().await?;
+    client.add_reaction(channel, &ts, UPVOTE).await?;
+    client.add_reaction(channel, &ts, DOWNVOTE).await?;
+    Ok(())
+}
+
+async fn insert_added_message(channel: &str, ts: &str, word: String) -> LambdaResult<()> {
+    let table_name = std::env::var("ADDED_MESSAGE_TABLE")?;
+    let mut item = message_key(channel, ts);
+    item.insert("word".to_string(), string_attr(word));
+    item.insert("channel".to_string(), string_attr(channel.to_string()));
+    item.insert("expires".to_string(), number_attr(now() + window()));
+
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+// Counts `voter`'s 👎 on the message at `ts`, or with `add` false takes it back. Returns the word it's about
+// and how many people have 👎ed it, or None if it isn't one of our "added" messages.
+// Voters are kept as a set, so the same person reacting twice, eg with two skin tones, only counts once.
+// Dynamo may not have expired it yet, so we check too.
+async fn record_downvote(channel: &str, ts: &str, voter: &str, add: bool) -> LambdaResult<Option<(String, i64)>> {
+    let table_name = std::env::var("ADDED_MESSAGE_TABLE")?;
+    // Aliased in case either lands on dynamo's long list of reserved words.
+    let mut names = HashMap::new();
+    names.insert("#voters".to_string(), "down_voters".to_string());
+    names.insert("#expires".to_string(), "expires".to_string());
+    let mut values = HashMap::new();
+    values.insert(":voter".to_string(), string_set_attr(vec![voter.to_string()]));
+    values.insert(":now".to_string(), number_attr(now()));
+
+    let client = dynamo_client();
+    let input = UpdateItemInput {
+        key: message_key(channel, ts),
+        table_name,
+        update_expression: Some(format!("{} #voters :voter", if add { "ADD" } else { "DELETE" })),
+        condition_expression: Some("attribute_exists(ts) AND #expires > :now".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("ALL_NEW".to_string()),
+        ..Default::default()
+    };
+    match client.update_item(input).await {
+        Ok(output) => Ok(output.attributes.as_ref().and_then(tally)),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The word an "added" message is about, and how many people have 👎ed it.
+// Dynamo drops a set once its last member is taken out, so no set is no votes.
+fn tally(item: &HashMap<String, AttributeValue>) -> Option<(String, i64)> {
+    let word = item.get("word")?.s.clone()?;
+    let down = item.get("down_voters").and_then(|voters| voters.ss.as_ref()).map_or(0, Vec::len);
+    Some((word, down as i64))
+}
+
+// Closes the message to voting. Returns false if another reaction already did.
+async fn delete_added_message(channel: &str, ts: &str) -> LambdaResult<bool> {
+    let table_name = std::env::var("ADDED_MESSAGE_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput {
+        key: message_key(channel, ts),
+        table_name,
+        condition_expression: Some("attribute_exists(ts)".to_string()),
+        ..Default::default()
+    };
+    match client.delete_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The message and voter a 👎 is from, unless it's our own, which is just there to show how.
+async fn downvote(event: &ReactionEvent) -> LambdaResult<Option<(&str, &str)>> {
+    // Skin tones come through as eg "-1::skin-tone-2".
+    if event.reaction.split("::").next() != Some(DOWNVOTE) {
+        return Ok(None);
+    }
+    let (channel, ts) = match &event.item {
+        ReactionItem::Message { channel, ts } => (channel, ts),
+        ReactionItem::Other => return Ok(None),
+    };
+    if bot_user_id().await?.as_deref() == Some(event.user.as_str()) {
+        return Ok(None);
+    }
+    Ok(Some((channel, ts)))
+}
+
+pub async fn handle_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    let (channel, ts) = match downvote(event).await? {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let (word, down) = match record_downvote(channel, ts, &event.user, true).await? {
+        Some(tally) => tally,
+        None => return Ok(()),
+    };
+    if down < threshold() || !delete_added_message(channel, ts).await? {
+        return Ok(());
+    }
+    let (pos, word) = match insult::decode_word(word) {
+        Some(decoded) => decoded,
+        None => return Ok(()),
+    };
+    // Recorded as removed by whoever cast the deciding vote.
+    if !insult::forget_word(&pos, &word, &event.user).await? {
+        return Ok(());
+    }
+    log::info!("The {} {} was voted out", pos.name(), word);
+    send_message(channel, &format!("The people have spoken: \"{}\" is out.", word)).await
+}
+
+// Taking a 👎 back takes the vote back, while the message is still open.
+pub async fn handle_reaction_removed(event: &ReactionEvent) -> LambdaResult<()> {
+    if let Some((channel, ts)) = downvote(event).await? {
+        record_downvote(channel, ts, &event.user, false).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tally_counts_each_voter_once() {
+        let mut item = message_key("C1", "1.0");
+        item.insert("word".to_string(), string_attr("clownn".to_string()));
+        assert_eq!(tally(&item), Some(("clownn".to_string(), 0)));
+        item.insert("down_voters".to_string(), string_set_attr(vec!["U1".to_string(), "U2".to_string()]));
+        assert_eq!(tally(&item), Some(("clownn".to_string(), 2)));
+    }
+
+    #[test]
+    fn messages_are_keyed_by_channel_and_ts() {
+        assert_ne!(message_key("C1", "1.0"), message_key("C2", "1.0"));
+    }
+}
@@ -0,0 +1,147 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::error::{env_var, BotResult};
+use crate::{now, secrets, slack};
+use crate::teams::{self, Team};
+
+// The "Add to Slack" flow: `GET /slack/install` sends whoever's installing the app to Slack with a signed
+// `state`, and Slack sends them back to /slack/oauth with it and a `code`, which we trade for the workspace's
+// bot token. A callback without a state we signed in the last STATE_MAX_AGE_SECS didn't start here.
+// https://api.slack.com/authentication/oauth-v2
+
+// What the bot asks for when it's installed.
+const BOT_SCOPES: &str = "app_mentions:read,channels:history,channels:read,chat:write,commands,files:read,\
+                          files:write,groups:history,groups:read,im:history,im:write,pins:read,reactions:read,\
+                          reactions:write,users:read";
+
+// Long enough to read Slack's permissions page, short enough that an old link is no use to anyone.
+const STATE_MAX_AGE_SECS: u64 = 10 * 60;
+
+// Where to send someone to install the app.
+pub async fn install_url() -> BotResult<String> {
+    let client_secret = secrets::get("SLACK_CLIENT_SECRET").await?;
+    let params = [
+        ("client_id", env_var("SLACK_CLIENT_ID")?),
+        ("scope", BOT_SCOPES.to_string()),
+        ("state", sign_state(&client_secret, now())),
+    ];
+    let query = serde_urlencoded::to_string(params).expect("strings always encode");
+    Ok(format!("https://slack.com/oauth/v2/authorize?{}", query))
+}
+
+// "<issued>.<signature>", the signature being an HMAC of the issue time under the client secret.
+fn sign_state(client_secret: &str, issued: u64) -> String {
+    format!("{}.{}", issued, hex::encode(state_mac(client_secret, issued).finalize().into_bytes()))
+}
+
+fn state_mac(client_secret: &str, issued: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(client_secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("install:{}", issued).as_bytes());
+    mac
+}
+
+// Whether we signed `state`, no more than STATE_MAX_AGE_SECS before `at`.
+fn verify_state(client_secret: &str, state: &str, at: u64) -> bool {
+    let (issued, signature) = match state.split_once('.') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let (issued, signature) = match (issued.parse::<u64>(), hex::decode(signature)) {
+        (Ok(issued), Ok(signature)) => (issued, signature),
+        _ => return false,
+    };
+    issued <= at && at - issued <= STATE_MAX_AGE_SECS
+        && state_mac(client_secret, issued).verify(&signature).is_ok()
+}
+
+// Handles the redirect, returning the status and HTML page for the installer's browser.
+pub async fn handle_redirect(code: Option<&str>, error: Option<&str>, state: Option<&str>) -> (u16, String) {
+    let code = match (code, error) {
+        (Some(code), None) if !code.is_empty() => code,
+        (_, Some("access_denied")) => return (200, page("No hard feelings", "The app wasn't installed.")),
+        _ => return (400, page("Something went wrong", "Slack didn't send back an install code. Try again?")),
+    };
+    match secrets::get("SLACK_CLIENT_SECRET").await {
+        Ok(client_secret) if state.is_some_and(|state| verify_state(&client_secret, state, now())) => (),
+        Ok(_) => {
+            log::warn!("Rejecting an install with a missing or invalid state");
+            let message = "That install link is stale or didn't come from us. Start again from the install link?";
+            return (400, page("Something went wrong", message));
+        },
+        Err(e) => {
+            log::error!("Error reading the client secret: {}", e);
+            return (500, page("Something went wrong", "The app isn't set up for installing. Try again later?"));
+        },
+    }
+    match install(code).await {
+        Ok(name) => {
+            let message = format!("The insult bot is installed in {}. Invite it to a channel and say \"help\".", name);
+            (200, page("Installed!", &message))
+        },
+        Err(e) => {
+            log::error!("Error installing the app: {}", e);
+            (502, page("Something went wrong", "Slack wouldn't finish the install. Try again?"))
+        },
+    }
+}
+
+// Returns the name of the workspace the app was installed in.
+async fn install(code: &str) -> BotResult<String> {
+    let client_secret = secrets::get("SLACK_CLIENT_SECRET").await?;
+    let access = slack::oauth_v2_access(&env_var("SLACK_CLIENT_ID")?, &client_secret, code).await?;
+    let team = Team { token: access.access_token, bot_user_id: access.bot_user_id };
+    teams::save(&access.team.id, &team, access.team.name.as_deref()).await?;
+    log::info!("Installed in team {} ({:?})", access.team.id, access.team.name);
+    Ok(access.team.name.unwrap_or_else(|| "your workspace".to_string()))
+}
+
+fn page(title: &str, message: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head>\
+         <body><h1>{title}</h1><p>{message}</p></body></html>",
+        title = html_escape(title), message = html_escape(message),
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_we_signed_verify() {
+        let state = sign_state("secret", 1_000);
+        assert!(verify_state("secret", &state, 1_000));
+        assert!(verify_state("secret", &state, 1_000 + STATE_MAX_AGE_SECS));
+    }
+
+    #[test]
+    fn stale_states_are_rejected() {
+        let state = sign_state("secret", 1_000);
+        assert!(!verify_state("secret", &state, 1_001 + STATE_MAX_AGE_SECS));
+        assert!(!verify_state("secret", &state, 999));
+    }
+
+    #[test]
+    fn forged_states_are_rejected() {
+        let state = sign_state("secret", 1_000);
+        assert!(!verify_state("other secret", &state, 1_000));
+        let (_, signature) = state.split_once('.').unwrap();
+        assert!(!verify_state("secret", &format!("1001.{}", signature), 1_001));
+        assert!(!verify_state("secret", "1000", 1_000));
+        assert!(!verify_state("secret", "", 1_000));
+    }
+
+    #[tokio::test]
+    async fn callbacks_without_a_state_are_rejected() {
+        std::env::set_var("SLACK_CLIENT_SECRET", "secret");
+        let (status, _) = handle_redirect(Some("code"), None, None).await;
+        assert_eq!(status, 400);
+        let (status, _) = handle_redirect(Some("code"), None, Some("1000.abcd")).await;
+        assert_eq!(status, 400);
+    }
+}
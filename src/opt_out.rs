@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, GetItemInput, PutItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::{now, LambdaResult};
+
+// Users who said "leave me alone". Others can't insult them, but they can still insult themselves.
+
+fn user_key(user: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("user".to_string(), string_attr(user.to_string()));
+    key
+}
+
+pub async fn is_opted_out(user: &str) -> LambdaResult<bool> {
+    is_opted_out_in(&dynamo_client(), user).await
+}
+
+pub async fn is_opted_out_in(client: &impl DynamoDb, user: &str) -> LambdaResult<bool> {
+    let table_name = std::env::var("OPT_OUT_TABLE")?;
+    let input = GetItemInput { key: user_key(user), table_name, ..Default::default() };
+    Ok(client.get_item(input).await?.item.is_some())
+}
+
+pub async fn opt_out(user: &str) -> LambdaResult<()> {
+    let table_name = std::env::var("OPT_OUT_TABLE")?;
+    let mut item = user_key(user);
+    item.insert("since".to_string(), number_attr(now()));
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+pub async fn opt_in(user: &str) -> LambdaResult<()> {
+    let table_name = std::env::var("OPT_OUT_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput { key: user_key(user), table_name, ..Default::default() };
+    client.delete_item(input).await?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::future::Future;
+use rand::random;
+use rusoto_dynamodb::{AttributeValue, DeleteItemInput, DynamoDb, PutItemInput, ScanInput};
+
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr};
+use crate::error::BotResult;
+use crate::{env_flag, env_parse, now, resend_message, teams, LambdaResult, SendOptions};
+
+// With USE_OUTBOX, messages that fail to send are parked in OUTBOX_TABLE
+// and retried by a scheduled sweep until they go out or get too old to matter.
+pub fn enabled() -> bool {
+    env_flag("USE_OUTBOX")
+}
+
+fn max_age() -> u64 {
+    env_parse("OUTBOX_MAX_AGE_SECS", 60 * 60)
+}
+
+fn id_key(id: String) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("id".to_string(), string_attr(id));
+    key
+}
+
+pub async fn enqueue(channel: &str, message: &str, options: &SendOptions) -> LambdaResult<()> {
+    enqueue_in(&dynamo_client(), channel, message, options).await
+}
+
+async fn enqueue_in(client: &impl DynamoDb, channel: &str, message: &str, options: &SendOptions) -> LambdaResult<()> {
+    let table_name = std::env::var("OUTBOX_TABLE")?;
+    let created = now();
+    let mut item = id_key(format!("{}-{:08x}", created, random::<u32>()));
+    item.insert("channel".to_string(), string_attr(channel.to_string()));
+    item.insert("text".to_string(), string_attr(message.to_string()));
+    item.insert("created".to_string(), number_attr(created));
+    if let Some(thread_ts) = &options.thread_ts {
+        item.insert("thread_ts".to_string(), string_attr(thread_ts.clone()));
+    }
+    if let Some(blocks) = &options.blocks {
+        item.insert("blocks".to_string(), string_attr(blocks.to_string()));
+    }
+    if options.raw {
+        item.insert("raw".to_string(), AttributeValue { bool: Some(true), ..Default::default() });
+    }
+    // So the retry goes out with the same workspace's token.
+    if let Some(team_id) = teams::current_team_id() {
+        item.insert("team_id".to_string(), string_attr(team_id));
+    }
+
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+async fn delete(client: &impl DynamoDb, table_name: &str, id: String) -> LambdaResult<()> {
+    let input = DeleteItemInput { key: id_key(id), table_name: table_name.to_string(), ..Default::default() };
+    client.delete_item(input).await?;
+    Ok(())
+}
+
+// Retries everything in the outbox, clearing what gets sent and what's too old.
+pub async fn sweep() -> LambdaResult<()> {
+    let resend = |channel: String, text: String, options: SendOptions| async move {
+        resend_message(&channel, &text, &options).await
+    };
+    sweep_with(&dynamo_client(), resend).await
+}
+
+async fn sweep_with<F, R>(client: &impl DynamoDb, resend: F) -> LambdaResult<()>
+where
+    F: Fn(String, String, SendOptions) -> R,
+    R: Future<Output = BotResult<()>>,
+{
+    let table_name = std::env::var("OUTBOX_TABLE")?;
+    let input = ScanInput { table_name: table_name.clone(), ..Default::default() };
+    let items = scan_all(client, input).await?;
+
+    let (mut sent, mut dropped, mut failed) = (0, 0, 0);
+    for item in items {
+        let s = |name: &str| item.get(name).and_then(|attr| attr.s.clone());
+        let created = item.get("created")
+            .and_then(|attr| attr.n.as_ref()?.parse::<u64>().ok())
+            .unwrap_or(0);
+        let options = SendOptions {
+            raw: item.get("raw").and_then(|attr| attr.bool).unwrap_or(false),
+            thread_ts: s("thread_ts"),
+            blocks: s("blocks").and_then(|blocks| serde_json::from_str(&blocks).ok()),
+        };
+        let (id, channel, text) = match (s("id"), s("channel"), s("text")) {
+            (Some(id), Some(channel), Some(text)) => (id, channel, text),
+            (Some(id), _, _) => {
+                delete(client, &table_name, id).await?;
+                dropped += 1;
+                continue;
+            },
+            _ => continue,
+        };
+        if created + max_age() < now() {
+            log::warn!("Dropping outbox message to {} after {}s: {}", channel, max_age(), text);
+            delete(client, &table_name, id).await?;
+            dropped += 1;
+        } else {
+            match teams::scope(s("team_id"), resend(channel.clone(), text.clone(), options)).await {
+                Ok(()) => {
+                    delete(client, &table_name, id).await?;
+                    sent += 1;
+                },
+                Err(e) if !e.is_retryable() => {
+                    log::warn!("Dropping outbox message to {} that can't be sent ({}): {}", channel, e, text);
+                    delete(client, &table_name, id).await?;
+                    dropped += 1;
+                },
+                Err(_) => failed += 1,
+            }
+        }
+    }
+    log::info!("Swept outbox: {} sent, {} dropped, {} still failing", sent, dropped, failed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use serde_json::json;
+
+    use super::*;
+    use crate::dynamo::mock::client_answering;
+    use crate::error::BotError;
+
+    #[tokio::test]
+    async fn failed_sends_are_parked_with_their_thread_and_team() {
+        std::env::set_var("OUTBOX_TABLE", "outbox");
+        let (client, requests) = client_answering(vec![json!({})]);
+        let options = SendOptions { thread_ts: Some("1.2".to_string()), ..Default::default() };
+        teams::scope(Some("T1".to_string()), enqueue_in(&client, "C1", "hello", &options)).await.unwrap();
+        let item = &requests.lock().unwrap()[0]["Item"];
+        assert_eq!(item["channel"], json!({ "S": "C1" }));
+        assert_eq!(item["text"], json!({ "S": "hello" }));
+        assert_eq!(item["thread_ts"], json!({ "S": "1.2" }));
+        assert_eq!(item["team_id"], json!({ "S": "T1" }));
+    }
+
+    #[tokio::test]
+    async fn sweep_resends_and_clears() {
+        std::env::set_var("OUTBOX_TABLE", "outbox");
+        let created = now().to_string();
+        let (client, requests) = client_answering(vec![
+            json!({ "Items": [
+                { "id": { "S": "1" }, "channel": { "S": "C1" }, "text": { "S": "hello" }, "created": { "N": created },
+                  "thread_ts": { "S": "1.2" }, "team_id": { "S": "T1" } },
+                { "id": { "S": "2" }, "channel": { "S": "C2" }, "text": { "S": "stale" }, "created": { "N": "0" } },
+                { "id": { "S": "3" }, "channel": { "S": "C3" }, "text": { "S": "flaky" }, "created": { "N": created } },
+            ] }),
+            json!({}),
+            json!({}),
+        ]);
+        let resent = Arc::new(Mutex::new(Vec::new()));
+        let resend = |channel: String, text: String, options: SendOptions| {
+            let resent = resent.clone();
+            async move {
+                resent.lock().unwrap().push((channel.clone(), text, options.thread_ts, teams::current_team_id()));
+                match channel.as_str() {
+                    "C3" => Err(BotError::Http("timed out".to_string())),
+                    _ => Ok(()),
+                }
+            }
+        };
+        sweep_with(&client, resend).await.unwrap();
+
+        let resent = resent.lock().unwrap();
+        assert_eq!(*resent, [
+            ("C1".to_string(), "hello".to_string(), Some("1.2".to_string()), Some("T1".to_string())),
+            ("C3".to_string(), "flaky".to_string(), None, None),
+        ]);
+        // The sent one and the stale one are cleared, and the one still failing is kept for next time.
+        let deleted: Vec<_> = requests.lock().unwrap()[1..].iter().map(|request| request["Key"]["id"]["S"].clone()).collect();
+        assert_eq!(deleted, [json!("1"), json!("2")]);
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, PutItemInput, ScanInput, UpdateItemError, UpdateItemInput};
+
+use crate::blocks::{self, Block, Button};
+use crate::dynamo::{dynamo_client, number_attr, scan_all, string_attr};
+use crate::router::{Args, Command};
+use crate::{interactive, now, post_message_with, slack, LambdaResult, MessageEvent, MessageSink, SendOptions};
+
+// `poll "question" "option" "option"`, voted on with buttons. Kept in POLL_TABLE keyed by the poll message's ts,
+// with each person's pick in a map so clicking another option moves their vote.
+
+const MAX_OPTIONS: usize = 10;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::verbatim(
+            "poll \"question\" \"option\" \"option\"…", "Ask the channel something, with buttons to vote.",
+            r"(?i)^\s*poll\s+(.+)$",
+            |event, args, services| Box::pin(handle_poll_command(event, services.sink, args)),
+        ),
+        Command::new(
+            "close poll", "Stop the voting on the last poll you started here.",
+            r"(?i)^\s*close\s+poll$",
+            |event, _, services| Box::pin(handle_close_poll(event, services.sink)),
+        ),
+    ]
+}
+
+// Slack likes to curl people's quotes.
+static QUOTED_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"["“”]([^"“”]+)["“”]"#).unwrap());
+
+struct Poll {
+    ts: String,
+    channel: String,
+    creator: String,
+    question: String,
+    options: Vec<String>,
+    closed: bool,
+    // Who picked which option, by index.
+    votes: HashMap<String, usize>,
+}
+
+impl Poll {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name)?.s.clone();
+        let votes = item.get("votes").and_then(|attr| attr.m.as_ref())
+            .map(|votes| votes.iter()
+                .filter_map(|(user, choice)| Some((user.clone(), choice.n.as_ref()?.parse().ok()?)))
+                .collect())
+            .unwrap_or_default();
+        Some(Poll {
+            ts: s("ts")?,
+            channel: s("channel")?,
+            creator: s("creator")?,
+            question: s("question")?,
+            options: serde_json::from_str(&s("options")?).ok()?,
+            closed: item.get("closed").and_then(|attr| attr.bool).unwrap_or(false),
+            votes,
+        })
+    }
+
+    fn blocks(&self) -> Vec<Block> {
+        let mut blocks = vec![Block::section(&format!("*{}*", self.question))];
+        for (i, option) in self.options.iter().enumerate() {
+            let count = self.votes.values().filter(|choice| **choice == i).count();
+            let plural = if count == 1 { "" } else { "s" };
+            blocks.push(Block::section(&format!("{} — {} vote{}", option, count, plural)));
+        }
+        if self.closed {
+            blocks.push(Block::context(&format!("Poll by <@{}>. Closed.", self.creator)));
+        } else {
+            let buttons = self.options.iter().enumerate()
+                .map(|(i, option)| Button::new(option, interactive::POLL_VOTE, &i.to_string()))
+                .collect();
+            blocks.push(Block::actions(buttons));
+            blocks.push(Block::context(&format!("Poll by <@{}>. Pick another option to change your vote.", self.creator)));
+        }
+        blocks
+    }
+
+    // Redraws the poll message with the current counts.
+    async fn refresh(&self) -> LambdaResult<()> {
+        let text = format!("Poll: {}", self.question);
+        let blocks = blocks::to_value(&self.blocks());
+        Ok(slack::client().await?.update_message_blocks(&self.channel, &self.ts, &text, blocks).await?)
+    }
+}
+
+fn ts_key(ts: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("ts".to_string(), string_attr(ts.to_string()));
+    key
+}
+
+async fn handle_poll_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    if !interactive::enabled() {
+        let message = "Polls need INTERACTIVE_BUTTONS switched on, so I can hear the clicks.";
+        return event.reply_privately(sink, message).await;
+    }
+    let mut quoted: Vec<String> = QUOTED_RE.captures_iter(args.get(1))
+        .map(|caps| caps[1].trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect();
+    if quoted.len() < 3 || quoted.len() > MAX_OPTIONS + 1 {
+        let message = format!("Try `poll \"Lunch?\" \"Tacos\" \"Pizza\"`, with 2 to {} options.", MAX_OPTIONS);
+        return event.reply_privately(sink, &message).await;
+    }
+    let question = quoted.remove(0);
+    let mut poll = Poll {
+        ts: String::new(),
+        channel: event.channel.clone(),
+        creator: event.user.clone(),
+        question,
+        options: quoted,
+        closed: false,
+        votes: HashMap::new(),
+    };
+    let options = SendOptions { blocks: Some(blocks::to_value(&poll.blocks())), ..event.reply_options() };
+    poll.ts = match post_message_with(&event.channel, &format!("Poll: {}", poll.question), &options).await? {
+        Some(ts) => ts,
+        None => return Ok(()),
+    };
+
+    let table_name = std::env::var("POLL_TABLE")?;
+    let mut item = ts_key(&poll.ts);
+    item.insert("channel".to_string(), string_attr(poll.channel.clone()));
+    item.insert("creator".to_string(), string_attr(poll.creator.clone()));
+    item.insert("question".to_string(), string_attr(poll.question.clone()));
+    item.insert("options".to_string(), string_attr(serde_json::to_string(&poll.options)?));
+    item.insert("votes".to_string(), AttributeValue { m: Some(HashMap::new()), ..Default::default() });
+    item.insert("created".to_string(), number_attr(now()));
+    let client = dynamo_client();
+    client.put_item(PutItemInput { item, table_name, ..Default::default() }).await?;
+    Ok(())
+}
+
+// A click on one of the poll's buttons. `value` is the option's index.
+pub async fn handle_vote(user: &str, ts: &str, value: &str) -> LambdaResult<()> {
+    let choice: usize = match value.parse() {
+        Ok(choice) => choice,
+        Err(_) => return Ok(()),
+    };
+    let table_name = std::env::var("POLL_TABLE")?;
+    let mut names = HashMap::new();
+    names.insert("#votes".to_string(), "votes".to_string());
+    names.insert("#user".to_string(), user.to_string());
+    names.insert("#closed".to_string(), "closed".to_string());
+    let mut values = HashMap::new();
+    values.insert(":choice".to_string(), number_attr(choice as u64));
+    let client = dynamo_client();
+    let input = UpdateItemInput {
+        key: ts_key(ts),
+        table_name,
+        update_expression: Some("SET #votes.#user = :choice".to_string()),
+        condition_expression: Some("attribute_exists(#votes) AND attribute_not_exists(#closed)".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("ALL_NEW".to_string()),
+        ..Default::default()
+    };
+    let item = match client.update_item(input).await {
+        Ok(output) => output.attributes.unwrap_or_default(),
+        // Closed, or not one of ours. The buttons shouldn't be there, but old clients can be slow to catch up.
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    match Poll::from_item(&item) {
+        Some(poll) => poll.refresh().await,
+        None => Ok(()),
+    }
+}
+
+async fn handle_close_poll(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let table_name = std::env::var("POLL_TABLE")?;
+    let client = dynamo_client();
+    let input = ScanInput { table_name: table_name.clone(), ..Default::default() };
+    let latest = scan_all(&client, input).await?.iter()
+        .filter_map(Poll::from_item)
+        .filter(|poll| poll.channel == event.channel && poll.creator == event.user && !poll.closed)
+        .max_by(|a, b| a.ts.cmp(&b.ts));
+    let mut poll = match latest {
+        Some(poll) => poll,
+        None => return event.reply_privately(sink, "You don't have an open poll here.").await,
+    };
+
+    let mut names = HashMap::new();
+    names.insert("#closed".to_string(), "closed".to_string());
+    let mut values = HashMap::new();
+    values.insert(":closed".to_string(), AttributeValue { bool: Some(true), ..Default::default() });
+    let input = UpdateItemInput {
+        key: ts_key(&poll.ts),
+        table_name,
+        update_expression: Some("SET #closed = :closed".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    client.update_item(input).await?;
+    poll.closed = true;
+    poll.refresh().await
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use rusoto_dynamodb::AttributeValue;
+
+use crate::insult;
+use crate::router::{Args, Command};
+use crate::{
+    bot_user_id, env_parse, fetch_message_text, now, rate_limit, LambdaResult, MessageEvent, MessageSink, ReactionEvent,
+    ReactionItem,
+};
+
+// Words people like come up more often. Each word has a score, kept in INSULT_TABLE as `score` and `scored`,
+// when it was last bumped. A 👍 on an insult bumps the words in it, and "boost <word>" bumps one directly.
+// Scores halve every SCORE_HALF_LIFE_DAYS, so old favorites give way to new ones.
+
+fn half_life_secs() -> f64 {
+    env_parse("SCORE_HALF_LIFE_DAYS", 30.0) * 24.0 * 60.0 * 60.0
+}
+
+// The reaction that bumps the words in an insult, without the colons.
+fn boost_emoji() -> String {
+    std::env::var("BOOST_EMOJI").unwrap_or_else(|_| "+1".to_string())
+}
+
+// How long before someone can boost the same word again.
+const BOOST_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, Default)]
+pub struct Score {
+    points: f64,
+    scored: u64,
+}
+
+impl Score {
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let n = |name: &str| item.get(name)?.n.clone();
+        Some(Score { points: n("score")?.parse().ok()?, scored: n("scored")?.parse().ok()? })
+    }
+
+    pub fn to_attrs(self) -> Vec<(&'static str, AttributeValue)> {
+        let number = |n: String| AttributeValue { n: Some(n), ..Default::default() };
+        vec![("score", number(self.points.to_string())), ("scored", number(self.scored.to_string()))]
+    }
+
+    // What's left of the points as of `at`.
+    fn decayed(&self, at: u64) -> f64 {
+        let age = at.saturating_sub(self.scored) as f64;
+        self.points * 0.5f64.powf(age / half_life_secs().max(1.0))
+    }
+
+    pub fn bumped(self, points: f64, at: u64) -> Self {
+        Score { points: self.decayed(at) + points, scored: at }
+    }
+}
+
+// How likely a word is to be picked, next to an unscored word's 1.
+pub fn weight(score: Option<&Score>) -> f64 {
+    1.0 + score.map_or(0.0, |score| score.decayed(now()))
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "boost <word>", "Like a word? Boost it and it'll come up more often.",
+            r"(?i)^\s*boost\s+(.+?)$",
+            |event, args, services| Box::pin(handle_boost(event, services.sink, args)),
+        ),
+    ]
+}
+
+async fn handle_boost(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let word = args.get(1).trim().to_lowercase();
+    let pos = match insult::known_pos(&word).await? {
+        Some(pos) => pos,
+        None => return event.reply_privately(sink, &format!("I don't know \"{}\".", word)).await,
+    };
+    if rate_limit::hit(&format!("boost:{}:{}", event.user, word), BOOST_WINDOW_SECS).await? > 1 {
+        return event.reply_privately(sink, "You've boosted that one already today.").await;
+    }
+    insult::boost_word(&pos, &word, 1.0).await?;
+    log::info!("{} boosted the {} {}", event.user, pos.name(), word);
+    event.acknowledge(sink, "Boosted.").await
+}
+
+// A BOOST_EMOJI on one of our insults bumps every word in it, once per person.
+pub async fn handle_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    if event.reaction.split("::").next() != Some(boost_emoji().as_str()) {
+        return Ok(());
+    }
+    let (channel, ts) = match &event.item {
+        ReactionItem::Message { channel, ts } => (channel, ts),
+        ReactionItem::Other => return Ok(()),
+    };
+    if event.item_user.is_none() || bot_user_id().await? != event.item_user {
+        return Ok(());
+    }
+    let text = match fetch_message_text(channel, ts).await? {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    let words = insult::words_in(&text).await?;
+    // Taking the reaction off and putting it back doesn't count twice.
+    if words.is_empty() || rate_limit::hit(&format!("boost:{}:{}", event.user, ts), BOOST_WINDOW_SECS).await? > 1 {
+        return Ok(());
+    }
+    for (pos, word) in &words {
+        insult::boost_word(pos, word, 1.0).await?;
+    }
+    log::info!("{} liked an insult, boosting {} words", event.user, words.len());
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use rusoto_core::signature::SignedRequest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::dynamo::aws_region;
+use crate::error::{env_var, BotError};
+use crate::{env_flag, LambdaResult};
+
+// With USE_EVENT_QUEUE, event callbacks are acknowledged right away and handed to EVENT_QUEUE_URL,
+// which invokes us again to do the slow part. Slack only waits 3 seconds for an answer.
+pub fn enabled() -> bool {
+    env_flag("USE_EVENT_QUEUE")
+}
+
+// There's no rusoto SQS crate on hand, but SendMessage is a single signed POST.
+// https://docs.aws.amazon.com/AWSSimpleQueueService/latest/APIReference/API_SendMessage.html
+pub async fn enqueue(body: &Value) -> LambdaResult<()> {
+    let queue_url = env_var("EVENT_QUEUE_URL")?;
+    let payload = json!({ "QueueUrl": queue_url, "MessageBody": body.to_string() });
+    let mut request = SignedRequest::new("POST", "sqs", &aws_region(), "/");
+    request.set_content_type("application/x-amz-json-1.0".to_string());
+    request.add_header("x-amz-target", "AmazonSQS.SendMessage");
+    request.set_payload(Some(payload.to_string()));
+    let mut response = rusoto_core::Client::shared().sign_and_dispatch(request).await
+        .map_err(|e| BotError::Http(format!("{:?}", e)))?;
+    let response = response.buffer().await.map_err(|e| BotError::Http(e.to_string()))?;
+    if !response.status.is_success() {
+        let message = format!("SendMessage returned {}: {}", response.status, String::from_utf8_lossy(&response.body));
+        return Err(BotError::Http(message).into());
+    }
+    Ok(())
+}
+
+// What the queue invokes us with.
+// https://docs.aws.amazon.com/lambda/latest/dg/with-sqs.html
+#[derive(Deserialize)]
+pub struct SqsEvent {
+    #[serde(rename = "Records")]
+    pub records: Vec<SqsRecord>,
+}
+
+#[derive(Deserialize)]
+pub struct SqsRecord {
+    pub body: String,
+}
+
+pub fn is_sqs_event(event: &Value) -> bool {
+    event.get("Records")
+        .and_then(|records| records.get(0))
+        .and_then(|record| record.get("eventSource"))
+        .is_some_and(|source| source == "aws:sqs")
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{DeleteItemInput, DynamoDb, PutItemError, PutItemInput, UpdateItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::{now, LambdaResult};
+
+// Fixed window counters: each `window` seconds gets its own item, which dynamo expires afterwards.
+// Counts `name` as happening once more, returning how many times it's happened this window.
+pub async fn hit(name: &str, window: u64) -> LambdaResult<u64> {
+    hit_many(name, window, 1).await
+}
+
+// Counts `name` as happening `count` more times at once, eg for each word in a list.
+pub async fn hit_many(name: &str, window: u64, count: u64) -> LambdaResult<u64> {
+    hit_many_in(&dynamo_client(), std::env::var("RATE_LIMIT_TABLE")?, name, window, count).await
+}
+
+// When the window `at` falls in started.
+fn window_start(at: u64, window: u64) -> u64 {
+    at - at % window
+}
+
+pub async fn hit_many_in(
+    client: &impl DynamoDb, table_name: String, name: &str, window: u64, count: u64,
+) -> LambdaResult<u64> {
+    let window_start = window_start(now(), window);
+
+    let mut key = HashMap::new();
+    key.insert("key".to_string(), string_attr(format!("{}:{}", name, window_start)));
+    // Aliased in case either lands on dynamo's long list of reserved words.
+    let mut names = HashMap::new();
+    names.insert("#hits".to_string(), "hits".to_string());
+    names.insert("#expires".to_string(), "expires".to_string());
+    let mut values = HashMap::new();
+    values.insert(":count".to_string(), number_attr(count));
+    values.insert(":expires".to_string(), number_attr(window_start + window));
+
+    let input = UpdateItemInput {
+        key,
+        table_name,
+        update_expression: Some("ADD #hits :count SET #expires = :expires".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("UPDATED_NEW".to_string()),
+        ..Default::default()
+    };
+    let output = client.update_item(input).await?;
+    let hits = output.attributes
+        .and_then(|attributes| attributes.get("hits")?.n.as_ref()?.parse().ok())
+        .unwrap_or(count);
+    Ok(hits)
+}
+
+// Cooldowns: `name` can happen once every `secs` seconds, counted from when it last did. Unlike a fixed window,
+// this can't be doubled up on by going at the end of one window and again at the start of the next.
+// Records `name` as happening now, or returns false without recording it if it already did in the last `secs`.
+pub async fn cool_down(name: &str, secs: u64) -> LambdaResult<bool> {
+    cool_down_in(&dynamo_client(), std::env::var("RATE_LIMIT_TABLE")?, name, secs).await
+}
+
+pub async fn cool_down_in(client: &impl DynamoDb, table_name: String, name: &str, secs: u64) -> LambdaResult<bool> {
+    let now = now();
+    let mut item = HashMap::new();
+    item.insert("key".to_string(), string_attr(name.to_string()));
+    item.insert("used".to_string(), number_attr(now));
+    // Dynamo can take days to expire an item, so it's `used` that decides.
+    item.insert("expires".to_string(), number_attr(now + secs));
+    let mut names = HashMap::new();
+    names.insert("#key".to_string(), "key".to_string());
+    names.insert("#used".to_string(), "used".to_string());
+    let mut values = HashMap::new();
+    values.insert(":cutoff".to_string(), number_attr(now.saturating_sub(secs)));
+
+    let input = PutItemInput {
+        item,
+        table_name,
+        condition_expression: Some("attribute_not_exists(#key) OR #used <= :cutoff".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        ..Default::default()
+    };
+    match client.put_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Records `name` as seen for the next `ttl` seconds. Returns false if it already was.
+pub async fn first_seen(name: &str, ttl: u64) -> LambdaResult<bool> {
+    first_seen_in(&dynamo_client(), std::env::var("RATE_LIMIT_TABLE")?, name, ttl).await
+}
+
+pub async fn first_seen_in(client: &impl DynamoDb, table_name: String, name: &str, ttl: u64) -> LambdaResult<bool> {
+    let mut item = HashMap::new();
+    item.insert("key".to_string(), string_attr(name.to_string()));
+    item.insert("expires".to_string(), number_attr(now() + ttl));
+    let mut names = HashMap::new();
+    names.insert("#key".to_string(), "key".to_string());
+
+    let input = PutItemInput {
+        item,
+        table_name,
+        condition_expression: Some("attribute_not_exists(#key)".to_string()),
+        expression_attribute_names: Some(names),
+        ..Default::default()
+    };
+    match client.put_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Undoes `first_seen`, so `name` can be seen for the first time again.
+pub async fn forget_in(client: &impl DynamoDb, table_name: String, name: &str) -> LambdaResult<()> {
+    let mut key = HashMap::new();
+    key.insert("key".to_string(), string_attr(name.to_string()));
+    let input = DeleteItemInput { key, table_name, ..Default::default() };
+    client.delete_item(input).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamo::mock::recording_client;
+
+    #[test]
+    fn hits_in_the_same_window_share_a_start() {
+        assert_eq!(window_start(3_600, 3_600), 3_600);
+        assert_eq!(window_start(7_199, 3_600), 3_600);
+        assert_eq!(window_start(7_200, 3_600), 7_200);
+        assert_eq!(window_start(59, 60), 0);
+    }
+
+    #[tokio::test]
+    async fn cooldowns_run_from_the_last_use() {
+        let (client, requests) = recording_client(200, "{}");
+        assert!(cool_down_in(&client, "limits".to_string(), "cooldown:C1", 30).await.unwrap());
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests[0]["ConditionExpression"], "attribute_not_exists(#key) OR #used <= :cutoff");
+            let number = |value: &serde_json::Value| value["N"].as_str().unwrap().parse::<u64>().unwrap();
+            let used = number(&requests[0]["Item"]["used"]);
+            assert_eq!(used - number(&requests[0]["ExpressionAttributeValues"][":cutoff"]), 30);
+        }
+
+        let still_cooling = r#"{"__type":"com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException"}"#;
+        let (client, _) = recording_client(400, still_cooling);
+        assert!(!cool_down_in(&client, "limits".to_string(), "cooldown:C1", 30).await.unwrap());
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{TimeZone, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::router::{Args, Command};
+use crate::slack::{self, ScheduledMessage};
+use crate::{now, LambdaResult, MessageEvent, MessageSink};
+
+// "remind me in 20m to stretch", as a message Slack holds on to and posts for us.
+// https://api.slack.com/messaging/scheduling
+// We don't store reminders anywhere: they're the scheduled messages that start with someone's mention.
+
+// As far ahead as Slack will schedule.
+const MAX_DELAY_SECS: u64 = 120 * 24 * 60 * 60;
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::verbatim(
+            "remind me in <20m, 2 hours, 1d…> to <thing>", "I'll mention you about it then.",
+            r"(?i)^\s*remind\s+me\s+in\s+(.+?)\s+to\s+(.+)$",
+            |event, args, services| Box::pin(handle_remind_command(event, services.sink, args)),
+        ),
+        Command::new(
+            "list reminders", "Your reminders in this channel that haven't gone off yet.",
+            r"(?i)^\s*list\s+reminders$",
+            |event, _, services| Box::pin(handle_list_reminders(event, services.sink)),
+        ),
+        Command::new(
+            "cancel reminder <number>", "Cancel one of your reminders, numbered as in \"list reminders\".",
+            r"(?i)^\s*cancel\s+reminder\s+#?(\d+)$",
+            |event, args, services| Box::pin(handle_cancel_reminder(event, services.sink, args)),
+        ),
+    ]
+}
+
+static DURATION_PART_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)(?:(\d+)\s*|(an?)\s+)([a-z]+)").unwrap());
+
+fn unit_secs(unit: &str) -> Option<u64> {
+    Some(match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        _ => return None,
+    })
+}
+
+fn is_separator(text: &str) -> bool {
+    matches!(text.trim().to_lowercase().as_str(), "" | "," | "and" | ", and")
+}
+
+// Seconds in eg "20m", "1h30m", "an hour and 10 minutes". None unless all of it makes sense.
+fn parse_duration(text: &str) -> Option<u64> {
+    let mut secs: u64 = 0;
+    let mut end = 0;
+    for caps in DURATION_PART_RE.captures_iter(text) {
+        let part = caps.get(0).unwrap();
+        if !is_separator(&text[end..part.start()]) {
+            return None;
+        }
+        let count: u64 = match caps.get(1) {
+            Some(count) => count.as_str().parse().ok()?,
+            None => 1,
+        };
+        secs = secs.checked_add(count.checked_mul(unit_secs(&caps[3].to_lowercase())?)?)?;
+        end = part.end();
+    }
+    if end == 0 || !is_separator(&text[end..]) {
+        return None;
+    }
+    Some(secs)
+}
+
+fn reminder_prefix(user: &str) -> String {
+    format!("<@{}> Reminder: ", user)
+}
+
+// Shown in each reader's own timezone, with UTC for clients that can't.
+// https://api.slack.com/reference/surfaces/formatting#date-formatting
+pub fn format_time(time: u64) -> String {
+    let fallback = Utc.timestamp(time as i64, 0).format("%Y-%m-%d %H:%M UTC");
+    format!("<!date^{}^{{date_short_pretty}} at {{time}}|{}>", time, fallback)
+}
+
+async fn handle_remind_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let delay = match parse_duration(args.get(1)) {
+        Some(delay) if delay > 0 && delay <= MAX_DELAY_SECS => delay,
+        Some(delay) if delay > 0 => {
+            return event.reply_privately(sink, "I can't remember things for more than 120 days.").await
+        }
+        _ => {
+            let message = format!("I don't know how long \"{}\" is. Try eg `remind me in 20m to stretch`.", args.get(1));
+            return event.reply_privately(sink, &message).await;
+        },
+    };
+    let what = args.get(2).trim_end_matches(['.', '!']);
+    let post_at = now() + delay;
+    let text = format!("{}{}", reminder_prefix(&event.user), what);
+    slack::client().await?.schedule_message(&event.channel, &text, post_at).await?;
+    event.reply_privately(sink, &format!("Okay, I'll remind you {}.", format_time(post_at))).await
+}
+
+// The sender's reminders in this channel, soonest first.
+async fn own_reminders(event: &MessageEvent) -> LambdaResult<Vec<ScheduledMessage>> {
+    let prefix = reminder_prefix(&event.user);
+    let mut reminders: Vec<_> = slack::client().await?.scheduled_messages(&event.channel).await?
+        .into_iter()
+        .filter(|message| message.text.starts_with(&prefix))
+        .collect();
+    reminders.sort_by_key(|message| message.post_at);
+    Ok(reminders)
+}
+
+async fn handle_list_reminders(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let reminders = own_reminders(event).await?;
+    if reminders.is_empty() {
+        return event.reply_privately(sink, "You don't have any reminders here.").await;
+    }
+    let prefix = reminder_prefix(&event.user);
+    let lines: Vec<_> = reminders.iter()
+        .enumerate()
+        .map(|(i, message)| format!("{}. {} — {}", i + 1, format_time(message.post_at), &message.text[prefix.len()..]))
+        .collect();
+    event.reply_privately(sink, &lines.join("\n")).await
+}
+
+async fn handle_cancel_reminder(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let reminders = own_reminders(event).await?;
+    let number: usize = args.get(1).parse().unwrap_or(0);
+    let reminder = match number.checked_sub(1).and_then(|i| reminders.get(i)) {
+        Some(reminder) => reminder,
+        None => {
+            let message = "You don't have a reminder with that number. Try `list reminders`.";
+            return event.reply_privately(sink, message).await;
+        }
+    };
+    slack::client().await?.delete_scheduled_message(&event.channel, &reminder.id).await?;
+    event.reply_privately(sink, "Cancelled.").await
+}
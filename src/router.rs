@@ -0,0 +1,338 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use crate::{admin, audit};
+use crate::blocks::{self, Block};
+use crate::config::{self, Feature};
+use crate::insult::{DynamoWords, WordStore};
+use crate::{insult_stats, metrics};
+use crate::{bot_user_id, env_parse, rate_limit, LambdaResult, MessageEvent, MessageSink, SendOptions, SlackSink};
+
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = LambdaResult<()>> + Send + 'a>>;
+pub type Handler = for<'a> fn(&'a MessageEvent, Args, Services<'a>) -> CommandFuture<'a>;
+
+// Where handlers send their messages and keep their words: Slack and dynamo, or fakes in tests.
+#[derive(Clone, Copy)]
+pub struct Services<'a> {
+    pub sink: &'a dyn MessageSink,
+    pub words: &'a dyn WordStore,
+}
+
+impl Services<'static> {
+    pub fn production() -> Self {
+        Services { sink: &SlackSink, words: &DynamoWords }
+    }
+}
+
+// Something people can say to the bot: what it looks like, what "help" says about it, and what it does.
+pub struct Command {
+    syntax: &'static str,
+    description: &'static str,
+    pattern: Regex,
+    // Matched against the message as sent, rather than the normalized text.
+    verbatim: bool,
+    // Only admins may run it.
+    admin: bool,
+    // Channels can switch this off with "config set".
+    feature: Option<Feature>,
+    handler: Handler,
+}
+
+impl Command {
+    // Patterns are compiled here, once, when the registry is first built, and never per message.
+    // There's no benchmark of that: benches link against a library, and the bot is only a binary.
+    pub fn new(syntax: &'static str, description: &'static str, pattern: &str, handler: Handler) -> Self {
+        let pattern = Regex::new(pattern).unwrap();
+        Command { syntax, description, pattern, verbatim: false, admin: false, feature: None, handler }
+    }
+
+    // For commands whose arguments are taken exactly as typed, trailing punctuation included.
+    pub fn verbatim(syntax: &'static str, description: &'static str, pattern: &str, handler: Handler) -> Self {
+        Command { verbatim: true, ..Command::new(syntax, description, pattern, handler) }
+    }
+
+    pub fn admin_only(self) -> Self {
+        Command { admin: true, ..self }
+    }
+
+    pub fn requires(self, feature: Feature) -> Self {
+        Command { feature: Some(feature), ..self }
+    }
+}
+
+// A command pattern's capture groups, numbered as in the pattern.
+pub struct Args(Vec<Option<String>>);
+
+impl Args {
+    fn new(caps: &Captures) -> Self {
+        Args(caps.iter().map(|group| group.map(|m| m.as_str().to_string())).collect())
+    }
+
+    // The text of `group`, or "" if it didn't participate in the match.
+    pub fn get(&self, group: usize) -> &str {
+        self.0.get(group).and_then(Option::as_deref).unwrap_or("")
+    }
+
+    pub fn has(&self, group: usize) -> bool {
+        self.0.get(group).is_some_and(Option::is_some)
+    }
+}
+
+// The commands, in the order they're tried, plus help-only notes for things that aren't messages.
+pub struct Registry {
+    commands: Vec<Command>,
+    notes: Vec<(&'static str, &'static str)>,
+}
+
+impl Registry {
+    pub fn new(commands: Vec<Command>, notes: Vec<(&'static str, &'static str)>) -> Self {
+        Registry { commands, notes }
+    }
+
+    fn help_text(&self) -> String {
+        let lines: Vec<_> = self.commands.iter()
+            .map(|command| (command.syntax, command.description))
+            .chain(self.notes.iter().copied())
+            .chain(std::iter::once(("help", "This.")))
+            .map(|(syntax, description)| format!("`{}` — {}", syntax, description))
+            .collect();
+        lines.join("\n")
+    }
+
+    // The command `text` looks like a typo of, if any is close enough to guess.
+    // Only the words before a command's first argument are compared, and the longest close one wins,
+    // so "insult stat" is taken for "insult stats" rather than "insult @someone".
+    pub async fn suggest(&self, text: &str) -> LambdaResult<Option<&'static str>> {
+        let text = normalize_command(strip_bot_mention(text).await?).to_lowercase();
+        let words: Vec<_> = text.split(' ').collect();
+        let mut best: Option<(&'static str, usize, usize)> = None;
+        let syntaxes = self.commands.iter().map(|command| command.syntax).chain(std::iter::once("help"));
+        for syntax in syntaxes {
+            let literal: Vec<_> = syntax.split(' ').take_while(|word| !word.contains(ARGUMENT_CHARS)).collect();
+            if literal.is_empty() || literal.len() > words.len() {
+                continue;
+            }
+            let (literal, said) = (literal.join(" "), words[..literal.len()].join(" "));
+            let distance = levenshtein(&literal, &said);
+            if distance > (literal.len() / 3).max(1) {
+                continue;
+            }
+            let better = match best {
+                Some((_, length, best_distance)) => (literal.len(), best_distance) > (length, distance),
+                None => true,
+            };
+            if better {
+                best = Some((syntax, literal.len(), distance));
+            }
+        }
+        Ok(best.map(|(syntax, _, _)| syntax))
+    }
+
+    // Whether `text` would run a command, without running it.
+    pub async fn matches(&self, text: &str) -> LambdaResult<bool> {
+        let text = strip_bot_mention(text).await?;
+        Ok(HELP_RE.is_match(&normalize_command(text)) || self.find(text).is_some())
+    }
+
+    // The syntax of the command `text` would run, and its arguments. `text` is without any mention of us.
+    #[cfg(test)]
+    pub fn parse(&self, text: &str) -> Option<(&'static str, Args)> {
+        self.find(text).map(|(command, args)| (command.syntax, args))
+    }
+
+    // Runs the command the event's text matches with `services`, skipping the admin and channel checks.
+    #[cfg(test)]
+    pub async fn run(&self, event: &MessageEvent, services: Services<'_>) -> LambdaResult<bool> {
+        match self.find(&event.text) {
+            Some((command, args)) => (command.handler)(event, args, services).await.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn find(&self, verbatim: &str) -> Option<(&Command, Args)> {
+        let normalized = normalize_command(verbatim);
+        self.commands.iter().find_map(|command| {
+            let text = if command.verbatim { verbatim } else { &normalized };
+            command.pattern.captures(text).map(|caps| (command, Args::new(&caps)))
+        })
+    }
+
+    // Runs the first command matching the message. Returns false if none did.
+    pub async fn dispatch(&self, event: &MessageEvent) -> LambdaResult<bool> {
+        let services = Services::production();
+        let verbatim = strip_bot_mention(&event.text).await?;
+        if HELP_RE.is_match(&normalize_command(verbatim)) {
+            send_help(event, services.sink, &self.help_text()).await?;
+            return Ok(true);
+        }
+        let (command, args) = match self.find(verbatim) {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+        if allowed(command, event, services.sink).await? {
+            let started = Instant::now();
+            let handled = audit::in_channel(Some(&event.channel), (command.handler)(event, args, services));
+            let result = insult_stats::as_insulter(&event.user, handled).await;
+            metrics::time_by("CommandLatency", "Command", command.syntax, started.elapsed());
+            result?;
+        }
+        Ok(true)
+    }
+}
+
+// "<@UBOT> insult me" is just "insult me" addressed to us, so commands don't each need to allow for the mention.
+async fn strip_bot_mention(text: &str) -> LambdaResult<&str> {
+    let bot = match bot_user_id().await? {
+        Some(bot) => bot,
+        None => return Ok(text),
+    };
+    Ok(strip_mention(text, &bot))
+}
+
+fn strip_mention<'a>(text: &'a str, bot: &str) -> &'a str {
+    match text.trim_start().strip_prefix(&format!("<@{}>", bot)) {
+        Some(rest) => rest.trim_start_matches([':', ',']).trim_start(),
+        None => text,
+    }
+}
+
+// Whether the sender and the channel's config let `command` run, telling the sender why not if they don't.
+async fn allowed(command: &Command, event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<bool> {
+    if command.admin && !admin::is_admin(&event.user).await? {
+        event.reply_privately(sink, "Only admins can do that.").await?;
+        return Ok(false);
+    }
+    let feature = match command.feature {
+        Some(feature) => feature,
+        None => return Ok(true),
+    };
+    match refusal(&event.channel, &event.user, feature, command.syntax).await? {
+        Some(reason) => {
+            event.reply_privately(sink, reason).await?;
+            Ok(false)
+        },
+        None => Ok(true),
+    }
+}
+
+// Why `user` can't use `feature` in `channel` right now, if they can't: it's switched off there,
+// or for insults, the channel or `user` is cooling down. `name` is what `user`'s cooldown is kept by, eg the command.
+// Things that don't come in as messages, like slash commands and reactions, check this themselves.
+pub async fn refusal(channel: &str, user: &str, feature: Feature, name: &str) -> LambdaResult<Option<&'static str>> {
+    let config = config::get(channel).await?;
+    if !config.allows(feature) {
+        return Ok(Some("That's switched off in this channel."));
+    }
+    if let Feature::Insults = feature {
+        let cooldown = config.cooldown_secs;
+        if cooldown > 0 && !rate_limit::cool_down(&format!("cooldown:{}", channel), cooldown).await? {
+            return Ok(Some("Give it a minute, this channel has a cooldown."));
+        }
+        // Per person and per command, so asking for a different kind of insult isn't held up.
+        let cooldown = user_cooldown();
+        if cooldown > 0 && !rate_limit::cool_down(&format!("cooldown:{}:{}", user, name), cooldown).await? {
+            return Ok(Some("Easy there. Even I need a breather between insults."));
+        }
+    }
+    Ok(None)
+}
+
+// Seconds before someone can use the same insult command again, or 0 to let them go wild.
+fn user_cooldown() -> u64 {
+    env_parse("USER_COOLDOWN_SECS", 10)
+}
+
+static HELP_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^\s*help$").unwrap());
+
+async fn send_help(event: &MessageEvent, sink: &dyn MessageSink, commands: &str) -> LambdaResult<()> {
+    let blocks = [Block::section("*Here's what I can do:*"), Block::section(commands)];
+    let text = format!("Here's what I can do:\n{}", commands);
+    let options = SendOptions { blocks: Some(blocks::to_value(&blocks)), ..event.reply_options() };
+    sink.post(&event.channel, &text, &options).await.map(|_| ())
+}
+
+// Whether `text` starts by mentioning us.
+pub async fn is_addressed(text: &str) -> LambdaResult<bool> {
+    Ok(strip_bot_mention(text).await? != text)
+}
+
+// What marks a word of a command's syntax as an argument, eg "<word>", "@someone", or "me|@someone".
+const ARGUMENT_CHARS: [char; 7] = ['<', '@', '#', '|', '[', ':', '*'];
+
+// How many single character insertions, deletions, and substitutions it takes to turn `a` into `b`.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut previous: Vec<_> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Trims, collapses runs of whitespace, and drops trailing sentence punctuation,
+// so "Insult   me!" reads the same as "insult me".
+fn normalize_command(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed.trim_end_matches(['.', '!', '?']).trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_every_syntax() {
+        let help = crate::COMMANDS.help_text();
+        let syntaxes = crate::COMMANDS.commands.iter().map(|command| command.syntax)
+            .chain(crate::COMMANDS.notes.iter().map(|(syntax, _)| *syntax));
+        for syntax in syntaxes {
+            assert!(help.contains(&format!("`{}` — ", syntax)), "help is missing {}", syntax);
+        }
+        assert!(help.contains("`insult me` — ") && help.contains("`add noun|adjective <word> [tagged <tag>]` — "));
+        assert!(HELP_RE.is_match(&normalize_command("  HELP!")));
+    }
+
+    #[test]
+    fn commands_shrug_off_casing_and_punctuation() {
+        let syntax = |text: &str| crate::COMMANDS.parse(text).map(|(syntax, _)| syntax);
+        assert_eq!(syntax("INSULT ME"), Some("insult me"));
+        assert_eq!(syntax("insult me!"), Some("insult me"));
+        assert_eq!(syntax("  Insult   me ?! "), Some("insult me"));
+        let (parsed, args) = crate::COMMANDS.parse("insult   @U123  ").unwrap();
+        assert_eq!((parsed, args.get(1)), ("insult <name>", "U123"));
+        // The mention's user id keeps its case.
+        let (parsed, args) = crate::COMMANDS.parse("INSULT <@U123abc>!").unwrap();
+        assert_eq!((parsed, args.get(1)), ("insult @someone [@someone else…]", " <@U123abc>"));
+        assert_eq!(syntax("insulting"), None);
+        assert_eq!(syntax("stop insulting me"), None);
+    }
+
+    #[test]
+    fn our_mention_is_stripped_before_matching() {
+        assert_eq!(strip_mention("<@UBOT> insult me", "UBOT"), "insult me");
+        assert_eq!(strip_mention("  <@UBOT>: insult me", "UBOT"), "insult me");
+        assert_eq!(strip_mention("<@UBOT>, help", "UBOT"), "help");
+        // Anyone else's mention is part of the command.
+        assert_eq!(strip_mention("<@U1> insult me", "UBOT"), "<@U1> insult me");
+        assert_eq!(strip_mention("insult <@UBOT>", "UBOT"), "insult <@UBOT>");
+        let (syntax, _) = crate::COMMANDS.parse(strip_mention("<@UBOT> insult me!", "UBOT")).unwrap();
+        assert_eq!(syntax, "insult me");
+    }
+    #[tokio::test]
+    async fn help_answers_in_the_thread_it_was_asked_in() {
+        let sink = crate::tests::RecordingSink::default();
+        let event: MessageEvent = serde_json::from_value(serde_json::json!({
+            "channel": "C1", "user": "U1", "text": "help", "ts": "1.3", "thread_ts": "1.1",
+        })).unwrap();
+        send_help(&event, &sink, "`help` — this").await.unwrap();
+        assert_eq!(*sink.threads.lock().unwrap(), vec![Some("1.1".to_string())]);
+    }
+}
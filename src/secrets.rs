@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use rusoto_secretsmanager::{GetSecretValueRequest, SecretsManager, SecretsManagerClient};
+use rusoto_ssm::{GetParametersByPathRequest, Ssm, SsmClient};
+use tokio::sync::OnceCell;
+
+use crate::dynamo::aws_region;
+use crate::error::{env_var, BotError, BotResult};
+
+// The Slack token and secrets, from wherever they're kept:
+// - SECRETS_ID names a Secrets Manager secret whose value is a JSON object, eg `{"SLACK_TOKEN": "xoxb-..."}`.
+// - SECRETS_PARAMETER_PATH is an SSM Parameter Store path, eg "/slack-bot", holding eg "/slack-bot/SLACK_TOKEN".
+// - Otherwise, and for anything they don't have, the env var of the same name. Fine for local dev.
+// They're read once per container, on first use.
+
+static SECRETS: OnceCell<HashMap<String, String>> = OnceCell::const_new();
+
+pub async fn get(name: &str) -> BotResult<String> {
+    let secrets = SECRETS.get_or_try_init(fetch_secrets).await?;
+    match secrets.get(name) {
+        Some(value) => Ok(value.clone()),
+        None => env_var(name),
+    }
+}
+
+fn configured(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+async fn fetch_secrets() -> BotResult<HashMap<String, String>> {
+    if let Some(secret_id) = configured("SECRETS_ID") {
+        return fetch_secret(&SecretsManagerClient::new(aws_region()), &secret_id).await;
+    }
+    if let Some(path) = configured("SECRETS_PARAMETER_PATH") {
+        return fetch_parameters(&SsmClient::new(aws_region()), &path).await;
+    }
+    Ok(HashMap::new())
+}
+
+// https://docs.aws.amazon.com/secretsmanager/latest/apireference/API_GetSecretValue.html
+async fn fetch_secret(client: &impl SecretsManager, secret_id: &str) -> BotResult<HashMap<String, String>> {
+    let request = GetSecretValueRequest { secret_id: secret_id.to_string(), ..Default::default() };
+    let secret = client.get_secret_value(request).await?.secret_string
+        .ok_or_else(|| BotError::Http(format!("secret {} has no SecretString", secret_id)))?;
+    Ok(serde_json::from_str(&secret)?)
+}
+
+// https://docs.aws.amazon.com/systems-manager/latest/APIReference/API_GetParametersByPath.html
+async fn fetch_parameters(client: &impl Ssm, path: &str) -> BotResult<HashMap<String, String>> {
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    let mut secrets = HashMap::new();
+    let mut next_token = None;
+    loop {
+        let request = GetParametersByPathRequest {
+            path: path.to_string(),
+            with_decryption: Some(true),
+            next_token,
+            ..Default::default()
+        };
+        let output = client.get_parameters_by_path(request).await?;
+        for parameter in output.parameters.unwrap_or_default() {
+            let name = parameter.name.as_deref().and_then(|name| name.strip_prefix(&prefix));
+            if let (Some(name), Some(value)) = (name, parameter.value) {
+                secrets.insert(name.to_string(), value);
+            }
+        }
+        next_token = output.next_token;
+        if next_token.is_none() {
+            return Ok(secrets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusoto_core::Region;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher, MultipleMockRequestDispatcher};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn secret_string_is_a_json_object() {
+        let body = r#"{ "Name": "slack-bot", "SecretString": "{\"SLACK_TOKEN\": \"xoxb-1\"}" }"#;
+        let dispatcher = MockRequestDispatcher::with_status(200).with_body(body);
+        let client = SecretsManagerClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1);
+        let secrets = fetch_secret(&client, "slack-bot").await.unwrap();
+        assert_eq!(secrets["SLACK_TOKEN"], "xoxb-1");
+    }
+
+    #[tokio::test]
+    async fn parameters_are_read_across_pages_without_the_path() {
+        let dispatcher = MultipleMockRequestDispatcher::new(vec![
+            MockRequestDispatcher::with_status(200).with_body(r#"{
+                "Parameters": [{ "Name": "/slack-bot/SLACK_TOKEN", "Value": "xoxb-1" }],
+                "NextToken": "page2"
+            }"#),
+            MockRequestDispatcher::with_status(200).with_body(r#"{
+                "Parameters": [{ "Name": "/slack-bot/SLACK_SIGNING_SECRET", "Value": "shh" }]
+            }"#),
+        ]);
+        let client = SsmClient::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1);
+        let secrets = fetch_parameters(&client, "/slack-bot/").await.unwrap();
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets["SLACK_TOKEN"], "xoxb-1");
+        assert_eq!(secrets["SLACK_SIGNING_SECRET"], "shh");
+    }
+}
@@ -0,0 +1,68 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+
+use crate::{check_body_size, metrics, respond, ApiGatewayEvent, ApiGatewayResponse, BodyError, LambdaResult};
+
+// `--serve` answers Slack from localhost, for pointing ngrok at instead of deploying.
+// Requests go through the same routing as the Lambda, shaped like API Gateway would hand them to us.
+
+pub async fn serve(port: u16) -> LambdaResult<()> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    log::info!("Listening on http://{}", addr);
+    Server::bind(&addr).serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match to_event(request).await {
+        Ok(event) => match respond(event).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("Error handling request: {}", e);
+                ApiGatewayResponse::error(500, &e.to_string())
+            },
+        },
+        Err(response) => response,
+    };
+    metrics::flush();
+    Ok(to_response(response))
+}
+
+async fn to_event(request: Request<Body>) -> Result<ApiGatewayEvent, ApiGatewayResponse> {
+    let (parts, body) = request.into_parts();
+    let headers = parts.headers.iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let query = match parts.uri.query() {
+        Some(query) => {
+            let query = serde_urlencoded::from_str(query).map_err(|e| ApiGatewayResponse::bad_request(&e.to_string()))?;
+            Some(query)
+        },
+        None => None,
+    };
+    // The signature is over these exact bytes, so no trimming or re-encoding.
+    let body = hyper::body::to_bytes(body).await.map_err(|e| ApiGatewayResponse::bad_request(&e.to_string()))?;
+    let body = String::from_utf8(body.to_vec()).map_err(|_| ApiGatewayResponse::body_error(&BodyError::NotUtf8))?;
+    check_body_size(&body).map_err(|e| ApiGatewayResponse::body_error(&e))?;
+    Ok(ApiGatewayEvent {
+        body,
+        is_base64_encoded: false,
+        headers: Some(headers),
+        http_method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query,
+        request_context: None,
+    })
+}
+
+fn to_response(response: ApiGatewayResponse) -> Response<Body> {
+    let mut builder = Response::builder().status(response.status_code);
+    for (name, value) in &response.headers {
+        builder = builder.header(name, value);
+    }
+    builder.body(Body::from(response.body)).unwrap_or_else(|_| Response::new(Body::empty()))
+}
+
@@ -0,0 +1,68 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::now;
+
+// Slack stops retrying well within this, so anything older is a replay.
+const MAX_AGE_SECS: u64 = 5 * 60;
+
+// Checks the `X-Slack-Signature` header against the request body, as described at
+// https://api.slack.com/authentication/verifying-requests-from-slack
+pub fn verify_signature(raw_body: &str, timestamp: &str, signature: &str, signing_secret: &str) -> bool {
+    let expected = match signature.strip_prefix("v0=").and_then(|hash| hex::decode(hash).ok()) {
+        Some(expected) => expected,
+        None => return false,
+    };
+    let mut mac = match Hmac::<Sha256>::new_varkey(signing_secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(format!("v0:{}:{}", timestamp, raw_body).as_bytes());
+    mac.verify(&expected).is_ok()
+}
+
+// Whether `timestamp` is recent enough to trust.
+pub fn fresh_timestamp(timestamp: &str) -> bool {
+    match timestamp.parse::<u64>() {
+        Ok(timestamp) => now().abs_diff(timestamp) <= MAX_AGE_SECS,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example from Slack's docs.
+    const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const TIMESTAMP: &str = "1531420618";
+    const BODY: &str = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&channel_id=G8PSS9T3V\
+                        &channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner&command=%2Fwebhook-collect&text=\
+                        &response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J%2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN\
+                        &trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+    const SIGNATURE: &str = "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+
+    #[test]
+    fn slacks_example_verifies() {
+        assert!(verify_signature(BODY, TIMESTAMP, SIGNATURE, SECRET));
+    }
+
+    #[test]
+    fn tampering_fails() {
+        assert!(!verify_signature(&BODY.replace("foobar", "foobaz"), TIMESTAMP, SIGNATURE, SECRET));
+        assert!(!verify_signature(BODY, "1531420619", SIGNATURE, SECRET));
+        assert!(!verify_signature(BODY, TIMESTAMP, SIGNATURE, "another secret"));
+        assert!(!verify_signature(BODY, TIMESTAMP, &SIGNATURE.replace("v0=", "v1="), SECRET));
+        assert!(!verify_signature(BODY, TIMESTAMP, "v0=not hex", SECRET));
+    }
+
+    #[test]
+    fn stale_timestamps_fail() {
+        assert!(fresh_timestamp(&now().to_string()));
+        assert!(fresh_timestamp(&(now() - MAX_AGE_SECS + 5).to_string()));
+        assert!(!fresh_timestamp(&(now() - MAX_AGE_SECS - 5).to_string()));
+        assert!(!fresh_timestamp(&(now() + MAX_AGE_SECS + 5).to_string()));
+        assert!(!fresh_timestamp(TIMESTAMP));
+        assert!(!fresh_timestamp("yesterday"));
+    }
+}
@@ -0,0 +1,558 @@
+use std::time::Duration;
+use hyper::client::HttpConnector;
+use hyper::{body, Body, Method, Request, Response, StatusCode};
+use hyper_openssl::HttpsConnector;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::Instrument;
+
+use crate::error::{BotError, BotResult};
+use crate::{health, metrics, secrets, teams};
+
+// The Slack Web API methods we use, over a shared connection pool.
+// https://api.slack.com/web
+
+// The `error` code from a response with `"ok": false`.
+// https://api.slack.com/web#evaluating_responses
+#[derive(Debug, PartialEq)]
+pub enum SlackError {
+    ChannelNotFound,
+    NotInChannel,
+    IsArchived,
+    MessageNotFound,
+    CantUpdateMessage,
+    AlreadyReacted,
+    UserNotFound,
+    InvalidAuth,
+    RateLimited,
+    Other(String),
+}
+
+impl SlackError {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "channel_not_found" => SlackError::ChannelNotFound,
+            "not_in_channel" => SlackError::NotInChannel,
+            "is_archived" => SlackError::IsArchived,
+            "message_not_found" => SlackError::MessageNotFound,
+            "cant_update_message" => SlackError::CantUpdateMessage,
+            "already_reacted" => SlackError::AlreadyReacted,
+            "user_not_found" => SlackError::UserNotFound,
+            "invalid_auth" | "not_authed" | "token_revoked" => SlackError::InvalidAuth,
+            "ratelimited" => SlackError::RateLimited,
+            other => SlackError::Other(other.to_string()),
+        }
+    }
+}
+
+impl SlackError {
+    // Trying again later won't help: the token or channel itself is the problem.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, SlackError::ChannelNotFound | SlackError::NotInChannel | SlackError::IsArchived | SlackError::InvalidAuth)
+    }
+}
+
+impl std::fmt::Display for SlackError {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let code = match self {
+            SlackError::ChannelNotFound => "channel_not_found",
+            SlackError::NotInChannel => "not_in_channel",
+            SlackError::IsArchived => "is_archived",
+            SlackError::MessageNotFound => "message_not_found",
+            SlackError::CantUpdateMessage => "cant_update_message",
+            SlackError::AlreadyReacted => "already_reacted",
+            SlackError::UserNotFound => "user_not_found",
+            SlackError::InvalidAuth => "invalid_auth",
+            SlackError::RateLimited => "ratelimited",
+            SlackError::Other(code) => code,
+        };
+        fmtr.write_str(code)
+    }
+}
+
+// https://api.slack.com/types/user
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub real_name: Option<String>,
+    #[serde(default)]
+    pub is_bot: bool,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub profile: UserProfile,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct UserProfile {
+    // Empty unless they've set one, in which case it's what Slack shows.
+    #[serde(default)]
+    pub display_name: String,
+}
+
+impl UserInfo {
+    // What Slack calls them: their display name, their full name, or failing those their username.
+    pub fn shown_name(&self) -> &str {
+        if !self.profile.display_name.is_empty() {
+            return &self.profile.display_name;
+        }
+        match &self.real_name {
+            Some(real_name) if !real_name.is_empty() => real_name,
+            _ => &self.name,
+        }
+    }
+}
+
+// https://api.slack.com/types/file
+#[derive(Deserialize, Debug)]
+pub struct FileInfo {
+    #[serde(default)]
+    pub name: String,
+    // eg "csv" or "text".
+    #[serde(default)]
+    pub filetype: String,
+    #[serde(default)]
+    pub size: u64,
+    // Only downloadable with a token that can see the file.
+    pub url_private: Option<String>,
+}
+
+// https://api.slack.com/methods/chat.scheduledMessages.list
+#[derive(Deserialize, Debug)]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub post_at: u64,
+    #[serde(default)]
+    pub text: String,
+}
+
+type HttpsClient = hyper::Client<HttpsConnector<HttpConnector>, Body>;
+
+// Shared across invocations in a warm container so we can reuse pooled connections.
+// A failed setup leaves the cell empty, so the next call tries again.
+static HTTPS_CLIENT: once_cell::sync::OnceCell<HttpsClient> = once_cell::sync::OnceCell::new();
+
+pub fn https_client() -> BotResult<&'static HttpsClient> {
+    HTTPS_CLIENT.get_or_try_init(|| {
+        let https = HttpsConnector::new()?;
+        // Idle connections are dropped before Slack's end would time them out.
+        Ok(hyper::Client::builder()
+            .pool_idle_timeout(Duration::from_secs(58))
+            .build(https))
+    })
+}
+
+// Tries a Slack call this many times while it keeps getting rate limited.
+const SLACK_ATTEMPTS: usize = 3;
+// Longer than this and we'd rather give up than sit out the Lambda timeout.
+const MAX_RETRY_AFTER_SECS: u64 = 10;
+
+// Sends the request `send` makes, waiting out Slack's Retry-After whenever it answers with a 429.
+// The last response is returned as is, rate limited or not.
+async fn retry_rate_limited<F, Fut>(attempts: usize, mut send: F) -> BotResult<Response<Body>>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = BotResult<Response<Body>>>,
+{
+    let mut attempt = 1;
+    loop {
+        let response = send().await?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || attempt >= attempts {
+            return Ok(response);
+        }
+        let retry_after = response.headers().get("retry-after")
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .unwrap_or(1)
+            .min(MAX_RETRY_AFTER_SECS);
+        log::warn!("Rate limited by Slack, retrying in {}s", retry_after);
+        tokio::time::sleep(Duration::from_secs(retry_after)).await;
+        attempt += 1;
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    token: String,
+}
+
+// SLACK_TOKEN doesn't change for the life of a container, so neither does its client.
+// Like the connection pool, a missing token leaves the cell empty to try again next time.
+static CLIENT: once_cell::sync::OnceCell<Client> = once_cell::sync::OnceCell::new();
+
+// A client for the workspace we're acting in: its own token if it installed the app, otherwise SLACK_TOKEN.
+// Clients are just a token, so they're cheap to hand out; the connection pool is shared.
+pub async fn client() -> BotResult<Client> {
+    if let Some(team) = teams::current().await? {
+        return Ok(Client::new(team.token));
+    }
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = Client::from_secrets().await?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+impl Client {
+    pub fn new(token: String) -> Self {
+        Client { token }
+    }
+
+    // A client for the bot token in `SLACK_TOKEN`, wherever the secrets are kept.
+    pub async fn from_secrets() -> BotResult<Self> {
+        Ok(Client::new(secrets::get("SLACK_TOKEN").await?))
+    }
+
+    // https://api.slack.com/methods/chat.postMessage
+    // Returns the `ts` Slack assigned the message. `payload` needs at least a channel and text.
+    pub async fn post_message(&self, payload: Value) -> BotResult<Option<String>> {
+        let body = self.call("chat.postMessage", payload).await?;
+        Ok(body.get("ts").and_then(Value::as_str).map(str::to_string))
+    }
+
+    // https://api.slack.com/methods/chat.postEphemeral
+    // Like post_message's, `payload` also needs the `user` to show it to.
+    pub async fn post_ephemeral(&self, payload: Value) -> BotResult<()> {
+        self.call("chat.postEphemeral", payload).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/chat.update
+    pub async fn update_message(&self, channel: &str, ts: &str, text: &str) -> BotResult<()> {
+        self.call("chat.update", json!({ "channel": channel, "ts": ts, "text": text })).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/chat.delete
+    pub async fn delete_message(&self, channel: &str, ts: &str) -> BotResult<()> {
+        self.call("chat.delete", json!({ "channel": channel, "ts": ts })).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/chat.scheduleMessage
+    // Posts `text` to `channel` at `post_at`, a unix time, returning the id to cancel it with.
+    pub async fn schedule_message(&self, channel: &str, text: &str, post_at: u64) -> BotResult<String> {
+        let body = self.call("chat.scheduleMessage", json!({ "channel": channel, "text": text, "post_at": post_at })).await?;
+        match body.get("scheduled_message_id") {
+            Some(Value::String(id)) => Ok(id.clone()),
+            _ => Err(BotError::Http("chat.scheduleMessage gave no scheduled_message_id".to_string())),
+        }
+    }
+
+    // https://api.slack.com/methods/chat.scheduledMessages.list
+    // Everything we've scheduled in `channel` that hasn't gone out yet.
+    pub async fn scheduled_messages(&self, channel: &str) -> BotResult<Vec<ScheduledMessage>> {
+        let mut messages = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let body = self.call("chat.scheduledMessages.list", json!({ "channel": channel, "cursor": cursor })).await?;
+            let page: Vec<ScheduledMessage> = serde_json::from_value(body["scheduled_messages"].clone())?;
+            messages.extend(page);
+            cursor = body["response_metadata"]["next_cursor"].as_str().unwrap_or("").to_string();
+            if cursor.is_empty() {
+                return Ok(messages);
+            }
+        }
+    }
+
+    // https://api.slack.com/methods/chat.deleteScheduledMessage
+    pub async fn delete_scheduled_message(&self, channel: &str, id: &str) -> BotResult<()> {
+        self.call("chat.deleteScheduledMessage", json!({ "channel": channel, "scheduled_message_id": id })).await?;
+        Ok(())
+    }
+
+    // Like update_message, replacing the blocks too. `text` stays as the notification and fallback.
+    pub async fn update_message_blocks(&self, channel: &str, ts: &str, text: &str, blocks: Value) -> BotResult<()> {
+        self.call("chat.update", json!({ "channel": channel, "ts": ts, "text": text, "blocks": blocks })).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/views.open
+    // `trigger_id` comes from the click or shortcut that asked for the modal, and only lasts a few seconds.
+    pub async fn open_view(&self, trigger_id: &str, view: Value) -> BotResult<()> {
+        self.call("views.open", json!({ "trigger_id": trigger_id, "view": view })).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/views.publish
+    // Replaces what `user` sees on the app's Home tab.
+    pub async fn publish_home(&self, user: &str, blocks: Value) -> BotResult<()> {
+        let view = json!({ "type": "home", "blocks": blocks });
+        self.call("views.publish", json!({ "user_id": user, "view": view })).await?;
+        Ok(())
+    }
+
+    // https://api.slack.com/methods/reactions.add
+    // `name` is the emoji name without colons, eg "white_check_mark".
+    pub async fn add_reaction(&self, channel: &str, ts: &str, name: &str) -> BotResult<()> {
+        match self.call("reactions.add", json!({ "channel": channel, "timestamp": ts, "name": name })).await {
+            Err(BotError::Slack(SlackError::AlreadyReacted)) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    // https://api.slack.com/methods/users.info
+    // Needs the users:read scope, like users_list.
+    pub async fn users_info(&self, user: &str) -> BotResult<UserInfo> {
+        let body = self.call_form("users.info", &[("user", user)]).await?;
+        Ok(serde_json::from_value(body["user"].clone())?)
+    }
+
+    // https://api.slack.com/methods/users.list
+    // Everyone in the workspace, a page at a time.
+    pub async fn users_list(&self) -> BotResult<Vec<UserInfo>> {
+        let mut users = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let body = self.call_form("users.list", &[("limit", "200"), ("cursor", &cursor)]).await?;
+            let members: Vec<UserInfo> = serde_json::from_value(body["members"].clone())?;
+            users.extend(members);
+            cursor = body["response_metadata"]["next_cursor"].as_str().unwrap_or("").to_string();
+            if cursor.is_empty() {
+                return Ok(users);
+            }
+        }
+    }
+
+    // https://api.slack.com/methods/auth.test
+    // The bot's own user id.
+    pub async fn auth_test(&self) -> BotResult<Option<String>> {
+        let body = self.call("auth.test", json!({})).await?;
+        Ok(body.get("user_id").and_then(Value::as_str).map(str::to_string))
+    }
+
+    // https://api.slack.com/methods/conversations.history
+    // The text of the top level message at `ts`.
+    pub async fn message_text(&self, channel: &str, ts: &str) -> BotResult<Option<String>> {
+        let body = self.call_form("conversations.history", &[
+            ("channel", channel),
+            ("latest", ts),
+            ("inclusive", "true"),
+            ("limit", "1"),
+        ]).await?;
+        Ok(history_text(&body, ts))
+    }
+
+    // https://api.slack.com/methods/files.info
+    pub async fn files_info(&self, file_id: &str) -> BotResult<FileInfo> {
+        let body = self.call_form("files.info", &[("file", file_id)]).await?;
+        Ok(serde_json::from_value(body["file"].clone())?)
+    }
+
+    // The contents of a file's `url_private`, which takes our token.
+    // https://api.slack.com/types/file#auth
+    pub async fn download(&self, url: &str) -> BotResult<Vec<u8>> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header("Authorization", format!("Bearer {}", self.token))
+            .body(Body::empty())?;
+        let response = https_client()?.request(request).await?;
+        if !response.status().is_success() {
+            return Err(BotError::Http(format!("file download failed with status {}", response.status())));
+        }
+        Ok(body::to_bytes(response.into_body()).await?.to_vec())
+    }
+
+    // Uploads a file to the channel, with `comment` posted alongside it.
+    // https://api.slack.com/messaging/files#uploading_files
+    pub async fn upload_file(
+        &self, channel: &str, thread_ts: Option<&str>, filename: &str, bytes: Vec<u8>, comment: &str,
+    ) -> BotResult<()> {
+        let length = bytes.len().to_string();
+        let body = self.call_form("files.getUploadURLExternal", &[("filename", filename), ("length", &length)]).await?;
+        let (upload_url, file_id) = match (body.get("upload_url"), body.get("file_id")) {
+            (Some(Value::String(url)), Some(Value::String(id))) => (url, id),
+            _ => return Err(BotError::Http("files.getUploadURLExternal gave no upload url".to_string())),
+        };
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(upload_url.as_str())
+            .body(Body::from(bytes))?;
+        let response = https_client()?.request(request).await?;
+        if !response.status().is_success() {
+            return Err(BotError::Http(format!("file upload failed with status {}", response.status())));
+        }
+
+        let mut payload = json!({
+            "files": [{ "id": file_id, "title": filename }],
+            "channel_id": channel,
+            "initial_comment": comment,
+        });
+        if let Some(thread_ts) = thread_ts {
+            payload["thread_ts"] = json!(thread_ts);
+        }
+        self.call("files.completeUploadExternal", payload).await?;
+        Ok(())
+    }
+
+    // Calls the Web API `method` with a json payload, returning the response body.
+    async fn call(&self, method: &str, payload: Value) -> BotResult<Value> {
+        self.call_raw(method, "application/json; charset=utf-8", payload.to_string()).await
+    }
+
+    // Some Web API methods only accept form encoded arguments.
+    async fn call_form(&self, method: &str, params: &[(&str, &str)]) -> BotResult<Value> {
+        call_api(method, Some(&self.token), FORM_CONTENT_TYPE, form_body(params)).await
+    }
+
+    async fn call_raw(&self, method: &str, content_type: &str, payload: String) -> BotResult<Value> {
+        call_api(method, Some(&self.token), content_type, payload).await
+    }
+}
+
+// The text of the message at `ts` in a conversations.history response.
+fn history_text(body: &Value, ts: &str) -> Option<String> {
+    let message = body.get("messages")?.get(0)?;
+    // Thread replies aren't in the channel history, so we'd get whatever preceded them instead.
+    if message.get("ts").and_then(Value::as_str) != Some(ts) {
+        return None;
+    }
+    message.get("text").and_then(Value::as_str).map(str::to_string)
+}
+
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+fn form_body(params: &[(&str, &str)]) -> String {
+    let body: Vec<_> = params.iter()
+        .map(|(key, value)| format!("{}={}", key, utf8_percent_encode(value, NON_ALPHANUMERIC)))
+        .collect();
+    body.join("&")
+}
+
+// Calls the Web API `method`, returning the response body if Slack says it went ok.
+// A few methods, like oauth.v2.access, are called before we have a token.
+async fn call_api(method: &str, token: Option<&str>, content_type: &str, payload: String) -> BotResult<Value> {
+    let result = call_api_uninstrumented(method, token, content_type, payload)
+        .instrument(tracing::info_span!("slack", method))
+        .await;
+    if let Err(e) = &result {
+        health::record_error("slack", &format!("{}: {}", method, e));
+    }
+    result
+}
+
+async fn call_api_uninstrumented(method: &str, token: Option<&str>, content_type: &str, payload: String) -> BotResult<Value> {
+    let response = retry_rate_limited(SLACK_ATTEMPTS, || async {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("https://slack.com/api/{}", method))
+            .header("content-type", content_type)
+            .header("accept", "*/*");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        Ok(https_client()?.request(request.body(Body::from(payload.clone()))?).await?)
+    }).await?;
+    let bytes = body::to_bytes(response.into_body()).await?;
+    let body: Value = serde_json::from_slice(&bytes)?;
+    match body.get("ok") {
+        Some(Value::Bool(true)) => Ok(body),
+        Some(Value::Bool(false)) => {
+            metrics::count("SlackErrors", 1);
+            let code = body.get("error").and_then(Value::as_str).unwrap_or("unknown");
+            Err(BotError::Slack(SlackError::from_code(code)))
+        },
+        _ => {
+            metrics::count("SlackErrors", 1);
+            Err(BotError::Http(format!("malformed Slack response from {}: {}", method, body)))
+        },
+    }
+}
+
+// https://api.slack.com/methods/oauth.v2.access
+#[derive(Deserialize, Debug)]
+pub struct OAuthAccess {
+    pub access_token: String,
+    #[serde(default)]
+    pub bot_user_id: Option<String>,
+    pub team: OAuthTeam,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OAuthTeam {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+// Trades the `code` from an install redirect for the workspace's bot token.
+pub async fn oauth_v2_access(client_id: &str, client_secret: &str, code: &str) -> BotResult<OAuthAccess> {
+    let params = [("client_id", client_id), ("client_secret", client_secret), ("code", code)];
+    let body = call_api("oauth.v2.access", None, FORM_CONTENT_TYPE, form_body(&params)).await?;
+    Ok(serde_json::from_value(body)?)
+}
+
+// https://api.slack.com/methods/apps.connections.open
+// A websocket url for Socket Mode. Takes the app level token (xapp-...), not the bot's.
+pub async fn connections_open(app_token: &str) -> BotResult<String> {
+    let body = call_api("apps.connections.open", Some(app_token), FORM_CONTENT_TYPE, String::new()).await?;
+    match body.get("url") {
+        Some(Value::String(url)) => Ok(url.clone()),
+        _ => Err(BotError::Http("apps.connections.open gave no url".to_string())),
+    }
+}
+
+// Replies to an interaction through the response_url Slack gave us for it. No token needed.
+// https://api.slack.com/interactivity/handling#message_responses
+pub async fn post_response(response_url: &str, payload: Value) -> BotResult<()> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(response_url)
+        .header("content-type", "application/json; charset=utf-8")
+        .body(Body::from(payload.to_string()))?;
+    let response = https_client()?.request(request).await?;
+    if !response.status().is_success() {
+        return Err(BotError::Http(format!("response_url returned {}", response.status())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn history_text_is_the_message_at_ts() {
+        let body = json!({
+            "ok": true,
+            "messages": [{ "type": "message", "user": "U1", "text": "buffoon", "ts": "1512085950.000216" }],
+            "has_more": true,
+        });
+        assert_eq!(history_text(&body, "1512085950.000216").as_deref(), Some("buffoon"));
+        // What came before a thread reply isn't the reply.
+        assert_eq!(history_text(&body, "1512085999.000100"), None);
+        assert_eq!(history_text(&json!({ "ok": true, "messages": [] }), "1512085950.000216"), None);
+    }
+
+    fn response(status: StatusCode) -> BotResult<Response<Body>> {
+        Ok(Response::builder().status(status).header("retry-after", "0").body(Body::empty()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rate_limited_calls_are_retried() {
+        let mut statuses = vec![StatusCode::OK, StatusCode::TOO_MANY_REQUESTS];
+        let mut calls = 0;
+        let result = retry_rate_limited(SLACK_ATTEMPTS, || {
+            calls += 1;
+            let status = statuses.pop().unwrap();
+            async move { response(status) }
+        }).await.unwrap();
+        assert_eq!((result.status(), calls), (StatusCode::OK, 2));
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_after_the_last_attempt() {
+        let mut calls = 0;
+        let result = retry_rate_limited(SLACK_ATTEMPTS, || {
+            calls += 1;
+            async { response(StatusCode::TOO_MANY_REQUESTS) }
+        }).await.unwrap();
+        assert_eq!((result.status(), calls), (StatusCode::TOO_MANY_REQUESTS, SLACK_ATTEMPTS));
+    }
+}
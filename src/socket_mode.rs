@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    handle_event_callback, handle_slash_command, interactive, metrics, secrets, slack, websocket, LambdaResult,
+    SlashCommand,
+};
+
+// `--socket-mode` gets events over a websocket Slack opens for us, for workspaces
+// that can't reach a public endpoint. Needs an app level token with connections:write in SLACK_APP_TOKEN.
+// https://api.slack.com/apis/connections/socket
+
+#[derive(Deserialize, Debug)]
+struct Envelope {
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(default)]
+    envelope_id: Option<String>,
+    #[serde(default)]
+    payload: Value,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+// How long to wait before reconnecting after the connection fails, doubling each time it fails again.
+const MIN_BACKOFF_SECS: u64 = 1;
+const MAX_BACKOFF_SECS: u64 = 60;
+
+pub async fn run() -> LambdaResult<()> {
+    let app_token = secrets::get("SLACK_APP_TOKEN").await?;
+    let mut backoff = MIN_BACKOFF_SECS;
+    // Slack closes connections every few hours, and asks us to reconnect before it does.
+    // Anything going wrong with one, or opening it, is only reason to open another.
+    loop {
+        if let Err(e) = serve(&app_token, &mut backoff).await {
+            log::error!("Socket Mode connection failed, reconnecting in {}s: {}", backoff, e);
+            tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+        }
+    }
+}
+
+// Handles what comes over one connection, until Slack closes it or asks us to reconnect.
+// `backoff` starts over once we're connected.
+async fn serve(app_token: &str, backoff: &mut u64) -> LambdaResult<()> {
+    let url = slack::connections_open(app_token).await?;
+    let mut socket = websocket::connect(&url).await?;
+    log::info!("Connected to Socket Mode");
+    *backoff = MIN_BACKOFF_SECS;
+    while let Some(text) = socket.read_text().await? {
+        let envelope: Envelope = match serde_json::from_str(&text) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                log::warn!("Ignoring unreadable Socket Mode message: {}", e);
+                continue;
+            },
+        };
+        if envelope.type_ == "disconnect" {
+            log::info!("Socket Mode asked us to reconnect ({})", envelope.reason.as_deref().unwrap_or("no reason"));
+            break;
+        }
+        let envelope_id = match &envelope.envelope_id {
+            Some(envelope_id) => envelope_id.clone(),
+            None => continue,
+        };
+        // Anything unacknowledged gets redelivered, so a failure is logged rather than ending the connection.
+        let result = handle_envelope(&mut socket, &envelope_id, envelope).await;
+        metrics::flush();
+        if let Err(e) = result {
+            log::error!("Error handling Socket Mode envelope {}: {}", envelope_id, e);
+        }
+    }
+    Ok(())
+}
+
+// Acknowledges the envelope, and does what it says.
+async fn handle_envelope(socket: &mut websocket::WebSocket, envelope_id: &str, envelope: Envelope) -> LambdaResult<()> {
+    match envelope.type_.as_str() {
+        "events_api" => {
+            socket.send_text(&json!({ "envelope_id": envelope_id }).to_string()).await?;
+            handle_event_callback(envelope.payload).await
+        },
+        // Modal submissions are answered in the acknowledgement, everything else afterwards.
+        "interactive" if envelope.payload["type"] == "view_submission" => {
+            let response = interactive::handle_payload(envelope.payload).await?;
+            socket.send_text(&json!({ "envelope_id": envelope_id, "payload": response }).to_string()).await?;
+            Ok(())
+        },
+        "interactive" => {
+            socket.send_text(&json!({ "envelope_id": envelope_id }).to_string()).await?;
+            interactive::handle_payload(envelope.payload).await?;
+            Ok(())
+        },
+        // The answer rides along with the acknowledgement, like the body of an HTTP response would.
+        "slash_commands" => {
+            let command: SlashCommand = serde_json::from_value(envelope.payload)?;
+            let response = handle_slash_command(&command).await?;
+            socket.send_text(&json!({ "envelope_id": envelope_id, "payload": response }).to_string()).await?;
+            Ok(())
+        },
+        _ => {
+            socket.send_text(&json!({ "envelope_id": envelope_id }).to_string()).await?;
+            Ok(())
+        },
+    }
+}
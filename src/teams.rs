@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use rusoto_dynamodb::{AttributeValue, DynamoDb, GetItemInput, PutItemInput};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::error::{env_var, BotResult};
+use crate::now;
+
+// Bot tokens for each workspace the app is installed in, so one deployment can serve several.
+// Without TEAM_TABLE there's just the one workspace, SLACK_TOKEN's, and everything below is skipped.
+// Workspaces missing from the table fall back to SLACK_TOKEN too.
+
+#[derive(Clone)]
+pub struct Team {
+    pub token: String,
+    pub bot_user_id: Option<String>,
+}
+
+tokio::task_local! {
+    // The workspace whose event we're handling.
+    static CURRENT_TEAM: Option<String>;
+}
+
+// Runs `future` on behalf of `team_id`, so Slack calls it makes use that workspace's token.
+pub async fn scope<F: Future>(team_id: Option<String>, future: F) -> F::Output {
+    CURRENT_TEAM.scope(team_id, future).await
+}
+
+// The workspace we're handling an event from, if Slack said.
+pub fn current_team_id() -> Option<String> {
+    CURRENT_TEAM.try_with(Clone::clone).ok().flatten()
+}
+
+// Lookups are cached for a while per container, so a reinstall can take this long to be picked up.
+const CACHE_SECS: u64 = 5 * 60;
+
+// When each workspace was looked up, and what we found.
+type TeamCache = HashMap<String, (u64, Option<Team>)>;
+
+static CACHE: Lazy<Mutex<TeamCache>> = Lazy::new(Default::default);
+
+// The stored install for the workspace we're handling an event from, if there is one.
+pub async fn current() -> BotResult<Option<Team>> {
+    let team_id = match current_team_id() {
+        Some(team_id) => team_id,
+        None => return Ok(None),
+    };
+    let table_name = match std::env::var("TEAM_TABLE") {
+        Ok(table_name) if !table_name.is_empty() => table_name,
+        _ => return Ok(None),
+    };
+    if let Some((read, team)) = CACHE.lock().unwrap_or_else(|e| e.into_inner()).get(&team_id) {
+        if read + CACHE_SECS > now() {
+            return Ok(team.clone());
+        }
+    }
+    let team = fetch(&table_name, &team_id).await?;
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(team_id, (now(), team.clone()));
+    Ok(team)
+}
+
+fn team_key(team_id: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("team_id".to_string(), string_attr(team_id.to_string()));
+    key
+}
+
+async fn fetch(table_name: &str, team_id: &str) -> BotResult<Option<Team>> {
+    let client = dynamo_client();
+    let input = GetItemInput { key: team_key(team_id), table_name: table_name.to_string(), ..Default::default() };
+    let item = match client.get_item(input).await?.item {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+    let s = |name: &str| item.get(name)?.s.clone();
+    Ok(s("token").map(|token| Team { token, bot_user_id: s("bot_user_id") }))
+}
+
+// Saves a workspace's install, replacing any earlier one.
+pub async fn save(team_id: &str, team: &Team, name: Option<&str>) -> BotResult<()> {
+    let mut item = team_key(team_id);
+    item.insert("token".to_string(), string_attr(team.token.clone()));
+    if let Some(bot_user_id) = &team.bot_user_id {
+        item.insert("bot_user_id".to_string(), string_attr(bot_user_id.clone()));
+    }
+    if let Some(name) = name {
+        item.insert("name".to_string(), string_attr(name.to_string()));
+    }
+    item.insert("installed".to_string(), number_attr(now()));
+
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name: env_var("TEAM_TABLE")?, ..Default::default() };
+    client.put_item(input).await?;
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(team_id.to_string(), (now(), Some(team.clone())));
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+
+use crate::slack::{self, UserInfo};
+use crate::{now, teams, LambdaResult};
+
+// Who people are, by id and by name, from users.info and users.list. Both need the users:read scope.
+// Mentions render as names in Slack, but plain text we post doesn't, so this is for the plain text.
+
+// People don't rename themselves often, so each container holds on to what it looked up for a while.
+const CACHE_SECS: u64 = 60 * 60;
+
+static USERS: Lazy<Mutex<HashMap<String, (u64, UserInfo)>>> = Lazy::new(Default::default);
+
+// Everyone in each workspace, as of when we listed them, for finding people by name.
+type DirectoryCache = HashMap<String, (u64, Vec<UserInfo>)>;
+
+static DIRECTORIES: Lazy<Mutex<DirectoryCache>> = Lazy::new(Default::default);
+
+fn cached_user(user: &str) -> Option<UserInfo> {
+    match USERS.lock().unwrap_or_else(|e| e.into_inner()).get(user) {
+        Some((read, info)) if read + CACHE_SECS > now() => Some(info.clone()),
+        _ => None,
+    }
+}
+
+fn cache_user(info: &UserInfo) {
+    USERS.lock().unwrap_or_else(|e| e.into_inner()).insert(info.id.clone(), (now(), info.clone()));
+}
+
+pub async fn info(user: &str) -> LambdaResult<UserInfo> {
+    if let Some(info) = cached_user(user) {
+        return Ok(info);
+    }
+    let info = slack::client().await?.users_info(user).await?;
+    cache_user(&info);
+    Ok(info)
+}
+
+// What Slack shows as `user`'s name. Falls back to the bare id if Slack won't tell us.
+pub async fn display_name(user: &str) -> String {
+    match info(user).await {
+        Ok(info) => info.shown_name().to_string(),
+        Err(e) => {
+            log::warn!("Couldn't look up {}: {}", user, e);
+            user.to_string()
+        },
+    }
+}
+
+async fn directory() -> LambdaResult<Vec<UserInfo>> {
+    let team = teams::current_team_id().unwrap_or_default();
+    if let Some((read, users)) = DIRECTORIES.lock().unwrap_or_else(|e| e.into_inner()).get(&team) {
+        if read + CACHE_SECS > now() {
+            return Ok(users.clone());
+        }
+    }
+    let users = slack::client().await?.users_list().await?;
+    users.iter().for_each(cache_user);
+    DIRECTORIES.lock().unwrap_or_else(|e| e.into_inner()).insert(team, (now(), users.clone()));
+    Ok(users)
+}
+
+// The id of whoever goes by `name`: their display name, full name, or username, ignoring case and a leading @.
+// None if nobody does, or if it's ambiguous. Bots don't count, they've got thicker skin.
+pub async fn find_by_name(name: &str) -> LambdaResult<Option<String>> {
+    let name = name.trim_start_matches('@').to_lowercase();
+    let users = directory().await?;
+    let matches: Vec<_> = users.iter()
+        .filter(|user| !user.deleted && !user.is_bot)
+        .filter(|user| {
+            user.name.to_lowercase() == name
+                || user.profile.display_name.to_lowercase() == name
+                || user.real_name.as_deref().is_some_and(|real_name| real_name.to_lowercase() == name)
+        })
+        .collect();
+    match matches.as_slice() {
+        [user] => Ok(Some(user.id.clone())),
+        _ => Ok(None),
+    }
+}
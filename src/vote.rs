@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemError, DeleteItemInput, DynamoDb, PutItemInput,
+    UpdateItemError, UpdateItemInput,
+};
+
+use crate::dynamo::{dynamo_client, number_attr, string_attr, string_set_attr};
+use crate::insult::{self, PartOfSpeech};
+use crate::{env_flag, env_parse, now, post_message, send_message, LambdaResult, ReactionEvent, ReactionItem};
+
+// Slack's names for 👍 and 👎.
+const UPVOTE: &str = "+1";
+const DOWNVOTE: &str = "-1";
+
+// When enabled, "add noun X" posts X as a candidate for the channel to vote on
+// instead of adding it immediately.
+pub fn enabled() -> bool {
+    env_flag("MODERATION_VOTE")
+}
+
+// Net 👍 a candidate needs to be added.
+fn threshold() -> i64 {
+    env_parse("VOTE_THRESHOLD", 3)
+}
+
+// How long a candidate stays open for voting, in seconds.
+// Votes are tallied on the first reaction after the window closes.
+fn window() -> u64 {
+    env_parse("VOTE_WINDOW_SECS", 600)
+}
+
+// Timestamps are only unique within a channel, so the key is both, eg "C123:1612345678.000200".
+fn message_key(channel: &str, ts: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("ts".to_string(), string_attr(format!("{}:{}", channel, ts)));
+    key
+}
+
+struct Candidate {
+    word: String,
+    channel: String,
+    proposer: String,
+    created: u64,
+    up: i64,
+    down: i64,
+}
+
+impl Candidate {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name)?.s.clone();
+        // Dynamo drops a set once its last member is taken out, so no set is no votes.
+        let voters = |name: &str| item.get(name).and_then(|voters| voters.ss.as_ref()).map_or(0, Vec::len) as i64;
+        Some(Candidate {
+            word: s("word")?,
+            channel: s("channel")?,
+            proposer: s("proposer")?,
+            created: item.get("created")?.n.as_ref()?.parse().ok()?,
+            up: voters("up_voters"),
+            down: voters("down_voters"),
+        })
+    }
+}
+
+fn vote_passes(up: i64, down: i64, threshold: i64) -> bool {
+    up - down >= threshold
+}
+
+pub async fn propose_word(channel: &str, user: &str, pos: PartOfSpeech, word: String) -> LambdaResult<()> {
+    let message = format!(
+        "<@{}> wants to add the {} \"{}\". React with :+1: or :-1: to vote.",
+        user, pos.name(), word,
+    );
+    let ts = match post_message(channel, &message).await? {
+        Some(ts) => ts,
+        None => return Ok(()),
+    };
+    insert_candidate(channel, &ts, user, insult::encode_word(&pos, word)).await
+}
+
+async fn insert_candidate(channel: &str, ts: &str, proposer: &str, word: String) -> LambdaResult<()> {
+    let table_name = std::env::var("CANDIDATE_TABLE")?;
+    let created = now();
+    let mut item = message_key(channel, ts);
+    item.insert("word".to_string(), string_attr(word));
+    item.insert("channel".to_string(), string_attr(channel.to_string()));
+    item.insert("proposer".to_string(), string_attr(proposer.to_string()));
+    item.insert("created".to_string(), number_attr(created));
+    // Let dynamo clean up candidates nobody ever came back to vote on.
+    item.insert("expires".to_string(), number_attr(created + window() + 24 * 60 * 60));
+
+    let client = dynamo_client();
+    let input = PutItemInput { item, table_name, ..Default::default() };
+    client.put_item(input).await?;
+    Ok(())
+}
+
+// Counts `voter` in `column`'s set of voters, or with `add` false takes them back out, returning the updated
+// candidate, or None if the reacted message isn't an open candidate or the voter proposed it.
+// As a set, the same person reacting twice, eg with two skin tones, only counts once.
+async fn record_vote(channel: &str, ts: &str, column: &str, voter: &str, add: bool) -> LambdaResult<Option<Candidate>> {
+    let table_name = std::env::var("CANDIDATE_TABLE")?;
+    let mut names = HashMap::new();
+    names.insert("#voters".to_string(), column.to_string());
+    let mut values = HashMap::new();
+    values.insert(":voters".to_string(), string_set_attr(vec![voter.to_string()]));
+    values.insert(":voter".to_string(), string_attr(voter.to_string()));
+
+    let client = dynamo_client();
+    let input = UpdateItemInput {
+        key: message_key(channel, ts),
+        table_name,
+        update_expression: Some(format!("{} #voters :voters", if add { "ADD" } else { "DELETE" })),
+        condition_expression: Some("attribute_exists(ts) AND proposer <> :voter".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("ALL_NEW".to_string()),
+        ..Default::default()
+    };
+    match client.update_item(input).await {
+        Ok(output) => Ok(output.attributes.as_ref().and_then(Candidate::from_item)),
+        Err(RusotoError::Service(UpdateItemError::ConditionalCheckFailed(_))) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Closes the candidate. Returns false if someone else already closed it.
+async fn delete_candidate(channel: &str, ts: &str) -> LambdaResult<bool> {
+    let table_name = std::env::var("CANDIDATE_TABLE")?;
+    let client = dynamo_client();
+    let input = DeleteItemInput {
+        key: message_key(channel, ts),
+        table_name,
+        condition_expression: Some("attribute_exists(ts)".to_string()),
+        ..Default::default()
+    };
+    match client.delete_item(input).await {
+        Ok(_) => Ok(true),
+        Err(RusotoError::Service(DeleteItemError::ConditionalCheckFailed(_))) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The message a 👍 or 👎 is on, and which set of voters it goes in.
+fn vote(event: &ReactionEvent) -> Option<(&str, &str, &'static str)> {
+    // Skin tones come through as eg "+1::skin-tone-2".
+    let column = match event.reaction.split("::").next() {
+        Some(UPVOTE) => "up_voters",
+        Some(DOWNVOTE) => "down_voters",
+        _ => return None,
+    };
+    match &event.item {
+        ReactionItem::Message { channel, ts } => Some((channel, ts, column)),
+        ReactionItem::Other => None,
+    }
+}
+
+pub async fn handle_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    let (channel, ts, column) = match vote(event) {
+        Some(vote) => vote,
+        None => return Ok(()),
+    };
+    let candidate = match record_vote(channel, ts, column, &event.user, true).await? {
+        Some(candidate) => candidate,
+        None => return Ok(()),
+    };
+    if now() < candidate.created + window() {
+        return Ok(());
+    }
+    if !delete_candidate(channel, ts).await? {
+        return Ok(());
+    }
+    resolve(candidate).await
+}
+
+// Taking a 👍 or 👎 back takes the vote back, while the candidate is still open.
+pub async fn handle_reaction_removed(event: &ReactionEvent) -> LambdaResult<()> {
+    if let Some((channel, ts, column)) = vote(event) {
+        record_vote(channel, ts, column, &event.user, false).await?;
+    }
+    Ok(())
+}
+
+async fn resolve(candidate: Candidate) -> LambdaResult<()> {
+    let (pos, word) = match insult::decode_word(candidate.word) {
+        Some(decoded) => decoded,
+        None => return Ok(()),
+    };
+    if !vote_passes(candidate.up, candidate.down, threshold()) {
+        let message = format!("The people have spoken: no \"{}\".", word);
+        return send_message(&candidate.channel, &message).await;
+    }
+    let message = if insult::add_word(&pos, word.clone(), &candidate.proposer, None).await? {
+        format!("The people have spoken: added the {} \"{}\".", pos.name(), word)
+    } else {
+        format!("The people have spoken, but I already have \"{}\".", word)
+    };
+    send_message(&candidate.channel, &message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate_item(up: &[&str], down: &[&str]) -> HashMap<String, AttributeValue> {
+        let mut item = message_key("C1", "1.0");
+        item.insert("word".to_string(), string_attr("clownn".to_string()));
+        item.insert("channel".to_string(), string_attr("C1".to_string()));
+        item.insert("proposer".to_string(), string_attr("U0".to_string()));
+        item.insert("created".to_string(), number_attr(0));
+        let voters = |voters: &[&str]| string_set_attr(voters.iter().map(|voter| voter.to_string()).collect());
+        if !up.is_empty() {
+            item.insert("up_voters".to_string(), voters(up));
+        }
+        if !down.is_empty() {
+            item.insert("down_voters".to_string(), voters(down));
+        }
+        item
+    }
+
+    #[test]
+    fn candidate_counts_voters() {
+        let candidate = Candidate::from_item(&candidate_item(&["U1", "U2", "U3"], &["U4"])).unwrap();
+        assert_eq!((candidate.up, candidate.down), (3, 1));
+        let candidate = Candidate::from_item(&candidate_item(&[], &[])).unwrap();
+        assert_eq!((candidate.up, candidate.down), (0, 0));
+    }
+
+    #[test]
+    fn enough_upvotes_pass() {
+        let passes = |up: &[&str], down: &[&str]| {
+            let candidate = Candidate::from_item(&candidate_item(up, down)).unwrap();
+            vote_passes(candidate.up, candidate.down, 3)
+        };
+        assert!(passes(&["U1", "U2", "U3"], &[]));
+        assert!(!passes(&["U1", "U2"], &[]));
+        // 👎s count against.
+        assert!(!passes(&["U1", "U2", "U3"], &["U4"]));
+        assert!(passes(&["U1", "U2", "U3", "U4", "U5"], &["U6", "U7"]));
+    }
+}
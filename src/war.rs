@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    AttributeValue, DeleteItemInput, DynamoDb, GetItemInput, PutItemError, PutItemInput, UpdateItemInput,
+};
+
+use crate::config::Feature;
+use crate::dynamo::{dynamo_client, number_attr, string_attr};
+use crate::router::{Args, Command};
+use crate::{env_parse, now, LambdaResult, MessageEvent, MessageSink, SendOptions};
+
+// "insult war @a @b" pits two people against each other in a channel. Every insult one of them sends
+// the other scores a point an adjective, until WAR_ROUNDS insults have gone back and forth,
+// WAR_WINDOW_SECS have passed, or someone says "end war".
+// There's one war a channel at a time, kept in STATS_TABLE as "war:<channel>".
+
+fn rounds() -> u64 {
+    env_parse("WAR_ROUNDS", 10)
+}
+
+fn window() -> u64 {
+    env_parse("WAR_WINDOW_SECS", 60 * 60)
+}
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "insult war @someone @someone", "Keep score while two people insult each other.",
+            r"(?i)^\s*insult\s+war\s+(?-i:<@(U\w+)>)\s+(?-i:<@(U\w+)>)$",
+            |event, args, services| Box::pin(handle_start(event, services.sink, args)),
+        ).requires(Feature::Insults),
+        Command::new(
+            "end war", "Call the insult war in this channel, whoever's ahead.",
+            r"(?i)^\s*end\s+(?:the\s+)?war$",
+            |event, _, services| Box::pin(handle_end(event, services.sink)),
+        ),
+    ]
+}
+
+struct War {
+    a: String,
+    b: String,
+    a_score: u64,
+    b_score: u64,
+    rounds: u64,
+    started: u64,
+}
+
+impl War {
+    fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let s = |name: &str| item.get(name)?.s.clone();
+        let n = |name: &str| item.get(name).and_then(|attr| attr.n.as_ref()?.parse().ok()).unwrap_or(0);
+        Some(War {
+            a: s("a")?,
+            b: s("b")?,
+            a_score: n("a_score"),
+            b_score: n("b_score"),
+            rounds: n("rounds"),
+            started: n("started"),
+        })
+    }
+
+    fn is_over(&self) -> bool {
+        self.rounds >= rounds() || self.started + window() <= now()
+    }
+
+    fn score(&self) -> String {
+        format!("<@{}> {} — <@{}> {}", self.a, self.a_score, self.b, self.b_score)
+    }
+
+    fn result(&self) -> String {
+        let verdict = match self.a_score.cmp(&self.b_score) {
+            std::cmp::Ordering::Greater => format!("<@{}> wins!", self.a),
+            std::cmp::Ordering::Less => format!("<@{}> wins!", self.b),
+            std::cmp::Ordering::Equal => "It's a draw. You're both losers.".to_string(),
+        };
+        format!("The war is over. {} {}", self.score(), verdict)
+    }
+}
+
+fn war_key(channel: &str) -> HashMap<String, AttributeValue> {
+    let mut key = HashMap::new();
+    key.insert("stat".to_string(), string_attr(format!("war:{}", channel)));
+    key
+}
+
+async fn fetch(channel: &str) -> LambdaResult<Option<War>> {
+    let table_name = std::env::var("STATS_TABLE")?;
+    let input = GetItemInput { key: war_key(channel), table_name, ..Default::default() };
+    Ok(dynamo_client().get_item(input).await?.item.as_ref().and_then(War::from_item))
+}
+
+// Ends the war in `channel`, returning it if there was one to end.
+async fn take(channel: &str) -> LambdaResult<Option<War>> {
+    let table_name = std::env::var("STATS_TABLE")?;
+    let input = DeleteItemInput {
+        key: war_key(channel),
+        table_name,
+        return_values: Some("ALL_OLD".to_string()),
+        ..Default::default()
+    };
+    Ok(dynamo_client().delete_item(input).await?.attributes.as_ref().and_then(War::from_item))
+}
+
+async fn handle_start(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let (a, b) = (args.get(1), args.get(2));
+    if a == b {
+        return event.reply_privately(sink, "It takes two to have a war.").await;
+    }
+    // A war that's run its course gives way, but it still gets its result.
+    if let Some(war) = fetch(&event.channel).await? {
+        if !war.is_over() {
+            return event.reply_privately(sink, &format!("There's already a war on here: {}", war.score())).await;
+        }
+        take(&event.channel).await?;
+        event.reply(sink, &war.result()).await?;
+    }
+    let table_name = std::env::var("STATS_TABLE")?;
+    let mut item = war_key(&event.channel);
+    item.insert("a".to_string(), string_attr(a.to_string()));
+    item.insert("b".to_string(), string_attr(b.to_string()));
+    item.insert("started".to_string(), number_attr(now()));
+    let input = PutItemInput {
+        item,
+        table_name,
+        condition_expression: Some("attribute_not_exists(stat)".to_string()),
+        ..Default::default()
+    };
+    match dynamo_client().put_item(input).await {
+        Ok(_) => (),
+        Err(RusotoError::Service(PutItemError::ConditionalCheckFailed(_))) =>
+            return event.reply_privately(sink, "Someone just started a war here.").await,
+        Err(e) => return Err(e.into()),
+    }
+    let message = format!(
+        "War! <@{}> vs <@{}>, {} insults. Every adjective you land on the other is a point. Go.",
+        a, b, rounds(),
+    );
+    event.reply(sink, &message).await
+}
+
+async fn handle_end(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    match take(&event.channel).await? {
+        Some(war) => event.reply(sink, &war.result()).await,
+        None => event.reply_privately(sink, "There's no war on here.").await,
+    }
+}
+
+// Scores an insult `insulter` sent `target_tag` in `channel`, if they're at war there, and posts the score
+// alongside it, per `options`. Scoring is best effort: the insult already went out.
+pub async fn score(
+    sink: &dyn MessageSink, channel: &str, options: &SendOptions, insulter: &str, target_tag: &str, adjectives: usize,
+) {
+    if let Err(e) = try_score(sink, channel, options, insulter, target_tag, adjectives as u64).await {
+        log::error!("Error scoring the war in {}: {}", channel, e);
+    }
+}
+
+async fn try_score(
+    sink: &dyn MessageSink, channel: &str, options: &SendOptions, insulter: &str, target_tag: &str, points: u64,
+) -> LambdaResult<()> {
+    let war = match fetch(channel).await? {
+        Some(war) => war,
+        None => return Ok(()),
+    };
+    let side = if insulter == war.a && target_tag == format!("<@{}>", war.b) {
+        "a_score"
+    } else if insulter == war.b && target_tag == format!("<@{}>", war.a) {
+        "b_score"
+    } else {
+        return Ok(());
+    };
+    if war.is_over() {
+        if let Some(war) = take(channel).await? {
+            sink.post(channel, &war.result(), options).await?;
+        }
+        return Ok(());
+    }
+    let table_name = std::env::var("STATS_TABLE")?;
+    let mut names = HashMap::new();
+    names.insert("#side".to_string(), side.to_string());
+    names.insert("#rounds".to_string(), "rounds".to_string());
+    let mut values = HashMap::new();
+    values.insert(":points".to_string(), number_attr(points));
+    values.insert(":one".to_string(), number_attr(1));
+    let input = UpdateItemInput {
+        key: war_key(channel),
+        table_name,
+        update_expression: Some("ADD #side :points, #rounds :one".to_string()),
+        condition_expression: Some("attribute_exists(stat)".to_string()),
+        expression_attribute_names: Some(names),
+        expression_attribute_values: Some(values),
+        return_values: Some("ALL_NEW".to_string()),
+        ..Default::default()
+    };
+    let war = match dynamo_client().update_item(input).await?.attributes.as_ref().and_then(War::from_item) {
+        Some(war) => war,
+        None => return Ok(()),
+    };
+    if war.rounds < rounds() {
+        let message = format!("Round {} of {}: {}", war.rounds, rounds(), war.score());
+        return sink.post(channel, &message, options).await.map(|_| ());
+    }
+    if let Some(war) = take(channel).await? {
+        sink.post(channel, &war.result(), options).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use hyper::client::HttpConnector;
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, StatusCode};
+use hyper_openssl::HttpsConnector;
+use openssl::ssl::{SslConnector, SslMethod};
+use rand::random;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::{BotError, BotResult};
+use crate::MAX_BODY_BYTES;
+
+// Just enough of a websocket client for Socket Mode: text messages, pings, and closes.
+// https://datatracker.ietf.org/doc/html/rfc6455
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+// Socket Mode delivers what would otherwise come as an HTTP request, so it gets the same limit,
+// for a frame and for a message put together from them. The peer says how long a frame is before sending it,
+// so without a limit it could have us allocate anything.
+const MAX_MESSAGE_BYTES: usize = MAX_BODY_BYTES;
+
+pub struct WebSocket<S = Upgraded> {
+    io: S,
+}
+
+fn too_large(len: usize) -> BotError {
+    BotError::Http(format!("websocket message of {} bytes is over the limit of {}", len, MAX_MESSAGE_BYTES))
+}
+
+// Opens a websocket to a wss:// `url`.
+pub async fn connect(url: &str) -> BotResult<WebSocket> {
+    // Upgrades are HTTP/1.1 only, so unlike the shared client this one doesn't offer h2.
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let ssl = SslConnector::builder(SslMethod::tls())?;
+    let https = HttpsConnector::with_connector(http, ssl)?;
+    let client = hyper::Client::builder().build::<_, Body>(https);
+
+    let key = base64::encode(random::<[u8; 16]>());
+    let request = Request::builder()
+        .uri(url.replacen("wss://", "https://", 1))
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header("Sec-WebSocket-Key", &key)
+        .body(Body::empty())?;
+    let response = client.request(request).await?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(BotError::Http(format!("websocket handshake returned {}", response.status())));
+    }
+    let expected = base64::encode(openssl::sha::sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()));
+    let accept = response.headers().get("Sec-WebSocket-Accept").and_then(|value| value.to_str().ok());
+    if accept != Some(expected.as_str()) {
+        return Err(BotError::Http("websocket handshake had the wrong Sec-WebSocket-Accept".to_string()));
+    }
+    let io = hyper::upgrade::on(response).await?;
+    Ok(WebSocket { io })
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WebSocket<S> {
+    // The next text message, or None once the server closes the connection.
+    // Pings are answered along the way.
+    pub async fn read_text(&mut self) -> BotResult<Option<String>> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = self.read_frame().await?;
+            match opcode {
+                TEXT | BINARY | CONTINUATION => {
+                    if message.len() + payload.len() > MAX_MESSAGE_BYTES {
+                        return Err(too_large(message.len() + payload.len()));
+                    }
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return Ok(Some(String::from_utf8_lossy(&message).into_owned()));
+                    }
+                },
+                PING => self.write_frame(PONG, &payload).await?,
+                CLOSE => {
+                    // Echoing the close is polite, but the server is hanging up either way.
+                    let _ = self.write_frame(CLOSE, &payload).await;
+                    return Ok(None);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    pub async fn send_text(&mut self, text: &str) -> BotResult<()> {
+        self.write_frame(TEXT, text.as_bytes()).await
+    }
+
+    async fn read_frame(&mut self) -> BotResult<(bool, u8, Vec<u8>)> {
+        let mut head = [0; 2];
+        self.io.read_exact(&mut head).await?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        let masked = head[1] & 0x80 != 0;
+        let len = match head[1] & 0x7F {
+            126 => self.io.read_u16().await? as u64,
+            127 => self.io.read_u64().await?,
+            len => len as u64,
+        };
+        if len > MAX_MESSAGE_BYTES as u64 {
+            return Err(too_large(len as usize));
+        }
+        // Servers aren't supposed to mask, but it costs nothing to cope.
+        let mut mask = [0; 4];
+        if masked {
+            self.io.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0; len as usize];
+        self.io.read_exact(&mut payload).await?;
+        if masked {
+            payload.iter_mut().zip(mask.iter().cycle()).for_each(|(byte, mask)| *byte ^= mask);
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    // Clients have to mask everything they send.
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> BotResult<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            },
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            },
+        }
+        let mask = random::<[u8; 4]>();
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(byte, mask)| byte ^ mask));
+        self.io.write_all(&frame).await?;
+        Ok(self.io.flush().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    // A websocket, and the server's end of its connection.
+    fn pair() -> (WebSocket<DuplexStream>, DuplexStream) {
+        let (client, server) = tokio::io::duplex(4 * MAX_MESSAGE_BYTES);
+        (WebSocket { io: client }, server)
+    }
+
+    // A frame as a server sends it, unmasked unless there's a `mask`.
+    fn frame(fin: bool, opcode: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        match payload.len() {
+            len if len < 126 => frame.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            },
+            len => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            },
+        }
+        match mask {
+            Some(mask) => {
+                frame.extend_from_slice(&mask);
+                frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(byte, mask)| byte ^ mask));
+            },
+            None => frame.extend_from_slice(payload),
+        }
+        frame
+    }
+
+    // Reads one frame the client sent, unmasking it, and checks that it was masked.
+    async fn read_client_frame(server: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        server.read_exact(&mut head).await.unwrap();
+        assert_ne!(head[1] & 0x80, 0, "client frames must be masked");
+        let len = match head[1] & 0x7F {
+            126 => server.read_u16().await.unwrap() as usize,
+            127 => server.read_u64().await.unwrap() as usize,
+            len => len as usize,
+        };
+        let mut mask = [0; 4];
+        server.read_exact(&mut mask).await.unwrap();
+        let mut payload = vec![0; len];
+        server.read_exact(&mut payload).await.unwrap();
+        payload.iter_mut().zip(mask.iter().cycle()).for_each(|(byte, mask)| *byte ^= mask);
+        (head[0] & 0x0F, payload)
+    }
+
+    #[tokio::test]
+    async fn reads_text_of_every_length_encoding() {
+        let (mut socket, mut server) = pair();
+        for len in [5, 300, 70_000] {
+            let text = "a".repeat(len);
+            server.write_all(&frame(true, TEXT, text.as_bytes(), None)).await.unwrap();
+            assert_eq!(socket.read_text().await.unwrap(), Some(text));
+        }
+    }
+
+    #[tokio::test]
+    async fn unmasks_and_joins_fragments() {
+        let (mut socket, mut server) = pair();
+        server.write_all(&frame(false, TEXT, b"hello, ", Some([1, 2, 3, 4]))).await.unwrap();
+        server.write_all(&frame(true, CONTINUATION, b"world", None)).await.unwrap();
+        assert_eq!(socket.read_text().await.unwrap().as_deref(), Some("hello, world"));
+    }
+
+    #[tokio::test]
+    async fn answers_pings_and_closes() {
+        let (mut socket, mut server) = pair();
+        server.write_all(&frame(true, PING, b"are you there", None)).await.unwrap();
+        server.write_all(&frame(true, CLOSE, b"", None)).await.unwrap();
+        assert_eq!(socket.read_text().await.unwrap(), None);
+        assert_eq!(read_client_frame(&mut server).await, (PONG, b"are you there".to_vec()));
+        assert_eq!(read_client_frame(&mut server).await, (CLOSE, Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn sends_masked_text() {
+        let (mut socket, mut server) = pair();
+        let text = "b".repeat(200);
+        socket.send_text(&text).await.unwrap();
+        assert_eq!(read_client_frame(&mut server).await, (TEXT, text.into_bytes()));
+    }
+
+    #[tokio::test]
+    async fn rejects_frames_over_the_limit() {
+        let (mut socket, mut server) = pair();
+        // Just the header: it's refused before the payload is read, or allocated.
+        let mut head = vec![0x80 | TEXT, 127];
+        head.extend_from_slice(&(u64::MAX).to_be_bytes());
+        server.write_all(&head).await.unwrap();
+        assert!(socket.read_text().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_messages_over_the_limit() {
+        let (mut socket, mut server) = pair();
+        let half = vec![b'a'; MAX_MESSAGE_BYTES / 2 + 1];
+        server.write_all(&frame(false, TEXT, &half, None)).await.unwrap();
+        server.write_all(&frame(true, CONTINUATION, &half, None)).await.unwrap();
+        assert!(socket.read_text().await.is_err());
+    }
+}
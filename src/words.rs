@@ -0,0 +1,665 @@
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusoto_dynamodb::DynamoDb;
+use serde_json::{json, Value};
+
+use crate::blocks::{self, Block, Button};
+use crate::config::Feature;
+use crate::dynamo::dynamo_client;
+use crate::error::poisoned;
+use crate::insult::{
+    add_word, decode_word, encode_word, forget_word, insert_words, insult_factory, insult_table, remember_added,
+    remove_matching, DynamoWords, PartOfSpeech,
+};
+use crate::router::{self, Args, Command};
+use crate::{
+    acknowledge, audit, digest, env_parse, fetch_message_text, filter, insult_log, interactive, last_added, metrics,
+    moderation, rate_limit, send_private, vote, LambdaResult, MessageEvent, MessageSink, ReactionEvent, ReactionItem,
+    SendOptions, SlackSink, SlashCommand,
+};
+
+// Teaching me words and taking them back, by message, slash command, modal, or reaction, and looking over what I know.
+
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new(
+            "add noun|adjective <word> [tagged <tag>]", "Teach me a new word, optionally tagged eg \"sfw\".",
+            r"(?i)^\s*add\s+(adjective|noun)\s+([\w ,-]+?)(?:\s+tagged\s+(\w+))?$",
+            |event, args, services| Box::pin(handle_add_word_command(event, services.sink, args)),
+        ).requires(Feature::AddWord),
+        Command::new(
+            "add emoji :emoji:", "Teach me a new emoji, for emoji channels.",
+            r"(?i)^\s*add\s+emoji\s+:([\w+-]+):$",
+            |event, args, services| {
+                Box::pin(handle_add_word(event, services.sink, PartOfSpeech::Emoji, args.get(1).to_string(), None))
+            },
+        ).requires(Feature::AddWord),
+        Command::new(
+            "remove noun|adjective <word>", "Admins only: make me forget a word.",
+            r"(?i)^\s*remove\s+(adjective|noun)\s+([\w ,-]+)$",
+            |event, args, services| Box::pin(handle_remove_word_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "undo", "Forget the last word you added.",
+            r"(?i)^\s*undo$",
+            |event, _, services| Box::pin(handle_undo(event, services.sink)),
+        ),
+        Command::verbatim(
+            "remove matching <text or /regex/>", "Admins only: forget every word that matches.",
+            r"(?i)^\s*remove\s+matching\s+(.+?)(\s+confirm)?$",
+            |event, args, services| Box::pin(handle_remove_matching_command(event, services.sink, args)),
+        ).admin_only(),
+        Command::new(
+            "pairs with <word>", "See which words have gone well with a word.",
+            r"(?i)^\s*pairs\s+with\s+([\w -]+)$",
+            |event, args, services| Box::pin(handle_pairs_with_command(event, services.sink, args)),
+        ),
+        Command::new(
+            "list nouns|adjectives [prefix]", "Every word I know of that kind, alphabetically, or just those starting with prefix.",
+            r"(?i)^\s*list\s+(nouns|adjectives)(?:\s+([\w -]+))?$",
+            |event, args, services| Box::pin(handle_list_command(event, services.sink, args)),
+        ),
+        Command::new(
+            "stats", "How many words I know.",
+            r"(?i)^\s*stats$",
+            |event, _, services| Box::pin(handle_stats(event, services.sink)),
+        ),
+    ]
+}
+
+fn parse_pos(name: &str) -> PartOfSpeech {
+    match name.to_lowercase().trim_end_matches('s') {
+        "adjective" => PartOfSpeech::Adjective,
+        "noun" => PartOfSpeech::Noun,
+        _ => unreachable!(),
+    }
+}
+
+async fn handle_add_word_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    let tag = args.has(3).then(|| args.get(3).to_lowercase());
+    handle_add_word(event, sink, parse_pos(args.get(1)), args.get(2).trim().to_string(), tag.as_deref()).await
+}
+
+async fn handle_remove_word_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_remove_word(event, sink, parse_pos(args.get(1)), args.get(2).trim()).await
+}
+
+async fn handle_remove_matching_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_remove_matching(event, sink, args.get(1), args.has(2)).await
+}
+
+async fn handle_pairs_with_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_pairs_with(event, sink, args.get(1).trim()).await
+}
+
+async fn handle_list_command(event: &MessageEvent, sink: &dyn MessageSink, args: Args) -> LambdaResult<()> {
+    handle_list(event, sink, parse_pos(args.get(1)), args.get(2)).await
+}
+
+// Slack cuts messages off well past this, but long walls of words are hard to read anyway.
+const LIST_MESSAGE_CHARS: usize = 3000;
+
+// Joins `words` with commas, starting a new message whenever one would run past `max_chars`.
+fn chunk_words(words: &[String], max_chars: usize) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    for word in words {
+        match messages.last_mut() {
+            Some(message) if message.chars().count() + 2 + word.chars().count() <= max_chars => {
+                message.push_str(", ");
+                message.push_str(word);
+            },
+            _ => messages.push(word.to_string()),
+        }
+    }
+    messages
+}
+
+// The words starting with `prefix`, whatever its case, alphabetically.
+fn listed_words(words: &[String], prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut words: Vec<_> = words.iter().filter(|word| word.to_lowercase().starts_with(&prefix)).cloned().collect();
+    words.sort_by_key(|word| word.to_lowercase());
+    words
+}
+
+// Lists come from this container's cache, like stats. An empty `prefix` lists everything.
+async fn handle_list(
+    event: &MessageEvent, sink: &dyn MessageSink, pos: PartOfSpeech, prefix: &str,
+) -> LambdaResult<()> {
+    let cache = insult_factory().await?;
+    let (words, total) = {
+        let insults = cache.read()
+            .map_err(poisoned("insult cache"))?;
+        let all = insults.words(&pos);
+        (listed_words(all, prefix), all.len())
+    };
+    if words.is_empty() {
+        let message = match prefix {
+            "" => format!("I don't know any {}s.", pos.name()),
+            _ => format!("I don't know any {}s starting with \"{}\".", pos.name(), prefix),
+        };
+        return event.reply_privately(sink, &message).await;
+    }
+    let summary = match prefix {
+        "" => format!("{} {}s:", total, pos.name()),
+        _ => format!("{} of {} {}s start with \"{}\":", words.len(), total, pos.name(), prefix),
+    };
+    event.reply_privately(sink, &summary).await?;
+    for message in chunk_words(&words, LIST_MESSAGE_CHARS) {
+        event.reply_privately(sink, &message).await?;
+    }
+    Ok(())
+}
+
+// Counts come from this container's cache: the last scan, plus whatever was added or removed through it since.
+async fn handle_stats(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let cache = insult_factory().await?;
+    let (nouns, adjectives) = {
+        let insults = cache.read()
+            .map_err(poisoned("insult cache"))?;
+        (insults.noun_count(), insults.adjective_count())
+    };
+    let message = format!(
+        "I know {} nouns and {} adjectives, for {} possible insults.",
+        nouns, adjectives, nouns * adjectives,
+    );
+    event.reply(sink, &message).await
+}
+
+static SLASH_ADD_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^(adjective|noun)\s+([\w ,-]+?)(?:\s+tagged\s+(\w+))?$").unwrap());
+
+// `/addword noun clown` works like "add noun clown", but answers only the person who ran it.
+pub async fn handle_add_word_slash_command(command: &SlashCommand) -> LambdaResult<Value> {
+    let ephemeral = |message: String| Ok(json!({ "response_type": "ephemeral", "text": message }));
+    if let Some(reason) = router::refusal(&command.channel_id, &command.user_id, Feature::AddWord, &command.command).await? {
+        return ephemeral(reason.to_string());
+    }
+    let caps = match SLASH_ADD_RE.captures(command.text.trim()) {
+        Some(caps) => caps,
+        None => return ephemeral(format!("Try `{} noun|adjective <word>`.", command.command)),
+    };
+    let pos = parse_pos(caps.get(1).unwrap().as_str());
+    let tag = caps.get(3).map(|tag| tag.as_str().to_lowercase());
+    let words = split_words(caps.get(2).unwrap().as_str());
+    // Each candidate needs its own message to vote on, but otherwise a list goes in as one batch.
+    if words.len() > 1 && !vote::enabled() {
+        let (message, _) = add_words(&command.user_id, pos, words, tag.as_deref()).await?;
+        return ephemeral(message);
+    }
+    if over_add_limit(&command.user_id, words.len()).await? {
+        return ephemeral("You've added a lot lately — take a break.".to_string());
+    }
+
+    let mut replies = Vec::new();
+    for word in words {
+        if let Err(reason) = validate_word(&word) {
+            replies.push(format!("\"{}\": {}", word, reason));
+            continue;
+        }
+        if let Some(reason) = filter::screen(&pos, &word, &command.user_id, tag.as_deref()).await? {
+            replies.push(format!("\"{}\": {}", word, reason));
+            continue;
+        }
+        if vote::enabled() {
+            vote::propose_word(&command.channel_id, &command.user_id, pos.clone(), word.clone()).await?;
+            replies.push(format!("\"{}\": put to a vote.", word));
+            continue;
+        }
+        let reply = match add_word(&pos, word.clone(), &command.user_id, tag.as_deref()).await {
+            Ok(true) => "added.",
+            Ok(false) => "I already have that word!",
+            Err(e) => {
+                log::error!("Error adding word: {}", e);
+                "I couldn't save that word, try again later."
+            },
+        };
+        replies.push(format!("\"{}\": {}", word, reply));
+    }
+    ephemeral(replies.join("\n"))
+}
+
+// The "Add word" modal, opened from the shortcut or the button on the Home tab.
+// Opened from a button in a channel, it remembers the channel, so the channel's config applies.
+// https://api.slack.com/surfaces/modals
+pub fn add_word_modal(channel: Option<&str>) -> Value {
+    let blocks = [
+        Block::input("pos", "Part of speech", blocks::Input::select("pos", &[("Adjective", "adjective"), ("Noun", "noun")])),
+        Block::input("word", "Word", blocks::Input::text("word", max_word_length())),
+    ];
+    json!({
+        "type": "modal",
+        "callback_id": interactive::ADD_WORD,
+        "private_metadata": channel.unwrap_or_default(),
+        "title": { "type": "plain_text", "text": "Add a word" },
+        "submit": { "type": "plain_text", "text": "Add" },
+        "close": { "type": "plain_text", "text": "Cancel" },
+        "blocks": blocks::to_value(&blocks),
+    })
+}
+
+// The modal's submission, with what was entered keyed by block id, from `channel` if it was opened in one.
+// Returns the response action: errors to show next to the inputs, or closing the modal.
+pub async fn handle_add_word_submission(
+    user: &str, channel: Option<&str>, values: &HashMap<String, String>,
+) -> LambdaResult<Value> {
+    let errors = |block_id: &str, message: &str| Ok(json!({ "response_action": "errors", "errors": { block_id: message } }));
+    if let Some(channel) = channel {
+        if let Some(reason) = router::refusal(channel, user, Feature::AddWord, interactive::ADD_WORD).await? {
+            return errors("word", reason);
+        }
+    }
+    let pos = match values.get("pos").map(String::as_str) {
+        Some(name @ ("adjective" | "noun")) => parse_pos(name),
+        _ => return errors("pos", "Pick one."),
+    };
+    let word = normalize_word(values.get("word").map(String::as_str).unwrap_or_default());
+    if let Err(reason) = validate_word(&word) {
+        return errors("word", reason);
+    }
+    if over_add_limit(user, 1).await? {
+        return errors("word", "You've added a lot lately — take a break.");
+    }
+    if vote::enabled() {
+        return errors("word", "New words go to a vote here, so add this one with a message in the channel.");
+    }
+    if let Some(reason) = filter::screen(&pos, &word, user, None).await? {
+        return errors("word", reason);
+    }
+    match add_word(&pos, word, user, None).await {
+        Ok(true) => Ok(json!({ "response_action": "clear" })),
+        Ok(false) => errors("word", "I already have that word!"),
+        Err(e) => {
+            log::error!("Error adding word: {}", e);
+            errors("word", "I couldn't save that word, try again later.")
+        },
+    }
+}
+
+// The "Undo" button on "Added.", whose value is the adder and the encoded word.
+// Only whoever added the word can take it back.
+pub async fn handle_undo_add(user: &str, value: &str) -> LambdaResult<Value> {
+    let decoded = match value.split_once(' ') {
+        Some((adder, data)) if adder == user => decode_word(data.to_string()),
+        _ => None,
+    };
+    let (pos, word) = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            let message = "That's not yours to undo.";
+            return Ok(json!({ "response_type": "ephemeral", "replace_original": false, "text": message }));
+        },
+    };
+    if !forget_word(&pos, &word, user).await? {
+        return Ok(json!({ "replace_original": true, "text": "It's already gone." }));
+    }
+    log::info!("{} undid adding the {} {}", user, pos.name(), word);
+    let message = format!("Never mind, I forgot \"{}\".", word);
+    Ok(json!({ "replace_original": true, "text": message }))
+}
+
+// How many words one user may add per ADD_LIMIT_WINDOW_SECS.
+fn add_limit() -> u64 {
+    env_parse("ADD_LIMIT", 20)
+}
+
+fn add_limit_window() -> u64 {
+    env_parse("ADD_LIMIT_WINDOW_SECS", 60 * 60)
+}
+
+// Counts `count` more words added by `user`, returning whether that takes them over ADD_LIMIT.
+async fn over_add_limit(user: &str, count: usize) -> LambdaResult<bool> {
+    over_add_limit_in(&dynamo_client(), std::env::var("RATE_LIMIT_TABLE")?, user, count).await
+}
+
+async fn over_add_limit_in(client: &impl DynamoDb, table_name: String, user: &str, count: usize) -> LambdaResult<bool> {
+    let name = format!("add:{}", user);
+    let hits = rate_limit::hit_many_in(client, table_name, &name, add_limit_window(), count as u64).await?;
+    Ok(hits > add_limit())
+}
+
+fn max_word_length() -> usize {
+    env_parse("MAX_WORD_LENGTH", 40)
+}
+
+// Words are stored trimmed and lowercased, and since the word is its own key,
+// dynamo's conditional put then turns away "Clown " as a duplicate of "clown".
+pub fn normalize_word(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+// "clown, Buffoon ,jester" is three words.
+fn split_words(list: &str) -> Vec<String> {
+    list.split(',').map(normalize_word).collect()
+}
+
+// Why `word` can't be added, if it can't.
+pub fn validate_word(word: &str) -> Result<(), &'static str> {
+    if word.is_empty() {
+        return Err("Nice try wise guy.");
+    }
+    if word.chars().count() > max_word_length() {
+        return Err("That's too long to be a word.");
+    }
+    if !word.chars().any(char::is_alphanumeric) {
+        return Err("That's not a word, that's punctuation.");
+    }
+    Ok(())
+}
+
+// "add noun clown, buffoon, jester" adds all three.
+async fn handle_add_word(
+    event: &MessageEvent, sink: &dyn MessageSink, pos: PartOfSpeech, insult: String, tag: Option<&str>,
+) -> LambdaResult<()> {
+    let mut words = split_words(&insult);
+    if words.len() == 1 {
+        let (options, word) = (event.reply_options(), words.remove(0));
+        return add_word_for(sink, &event.channel, &event.user, &event.ts, &options, pos, word, tag).await;
+    }
+    let options = event.reply_options();
+    // Each candidate needs its own message to vote on.
+    if vote::enabled() {
+        for word in words {
+            add_word_for(sink, &event.channel, &event.user, &event.ts, &options, pos.clone(), word, tag).await?;
+        }
+        return Ok(());
+    }
+    add_words_for(sink, &event.channel, &event.user, &event.ts, &options, pos, words, tag).await
+}
+
+// Adds several words in one batch on `user`'s behalf, replying in `channel`, per `options`, with what was
+// skipped and why. If they all went in, that's just an acknowledgement of the message at `ts`.
+#[allow(clippy::too_many_arguments)]
+async fn add_words_for(
+    sink: &dyn MessageSink, channel: &str, user: &str, ts: &str, options: &SendOptions, pos: PartOfSpeech,
+    words: Vec<String>, tag: Option<&str>,
+) -> LambdaResult<()> {
+    match add_words(user, pos, words, tag).await? {
+        (message, true) => acknowledge(sink, channel, user, ts, &message, options).await,
+        (message, false) => sink.post_private(channel, user, &message, options).await,
+    }
+}
+
+// Adds several words on `user`'s behalf. Returns what to tell them, including what was skipped and why,
+// and whether every word went in.
+async fn add_words(
+    user: &str, pos: PartOfSpeech, words: Vec<String>, tag: Option<&str>,
+) -> LambdaResult<(String, bool)> {
+    // Every word counts against the limit, so a list can't sneak past it.
+    if over_add_limit(user, words.len()).await? {
+        return Ok(("You've added a lot lately — take a break.".to_string(), false));
+    }
+    let mut candidates = Vec::new();
+    let mut skipped = Vec::new();
+    for word in words {
+        if let Err(reason) = validate_word(&word) {
+            skipped.push(format!("\"{}\" ({})", word, reason));
+        } else if let Some(reason) = filter::screen(&pos, &word, user, tag).await? {
+            skipped.push(format!("\"{}\" ({})", word, reason));
+        } else {
+            candidates.push(word);
+        }
+    }
+
+    let cache = insult_factory().await?;
+    let outcome = insert_words(&DynamoWords, cache, &pos, candidates, user, tag).await?;
+    skipped.extend(outcome.duplicates.iter().map(|word| format!("\"{}\" (already have it)", word)));
+    metrics::count("WordsAdded", outcome.added.len() as u64);
+    let encoded: Vec<_> = outcome.added.iter().map(|word| encode_word(&pos, word.clone())).collect();
+    audit::record("added", &encoded, user).await;
+    if let Some(word) = outcome.added.last() {
+        remember_added(user, encode_word(&pos, word.clone())).await;
+    }
+    let mut message = format!("Added {}.", outcome.added.len());
+    if !skipped.is_empty() {
+        message.push_str(&format!(" Skipped {}.", skipped.join(", ")));
+    }
+    if !outcome.failed.is_empty() {
+        message.push_str(&format!(" I couldn't save {}, try again later.", outcome.failed.join(", ")));
+    }
+    Ok((message, skipped.is_empty() && outcome.failed.is_empty()))
+}
+
+// Adds a word on `user`'s behalf, replying in `channel`, per `options`, or reacting to the message at `ts`
+// that asked for it. Candidates put to a vote go in untagged.
+#[allow(clippy::too_many_arguments)]
+async fn add_word_for(
+    sink: &dyn MessageSink, channel: &str, user: &str, ts: &str, options: &SendOptions, pos: PartOfSpeech,
+    insult: String, tag: Option<&str>,
+) -> LambdaResult<()> {
+    if let Err(reason) = validate_word(&insult) {
+        return sink.post_private(channel, user, reason, options).await;
+    }
+    if over_add_limit(user, 1).await? {
+        let message = "You've added a lot lately — take a break.";
+        return sink.post_private(channel, user, message, options).await;
+    }
+    if let Some(reason) = filter::screen(&pos, &insult, user, tag).await? {
+        return sink.post_private(channel, user, reason, options).await;
+    }
+    if vote::enabled() {
+        let cache = insult_factory().await?;
+        let known = cache.read()
+            .map_err(poisoned("insult cache"))?
+            .has_word(&pos, &insult);
+        if known {
+            return sink.post_private(channel, user, "I already have that word!", options).await;
+        }
+        return vote::propose_word(channel, user, pos, insult).await;
+    }
+    match add_word(&pos, insult.clone(), user, tag).await {
+        Ok(true) if moderation::enabled() => moderation::announce_added(channel, user, &pos, &insult).await,
+        Ok(true) if !interactive::enabled() => acknowledge(sink, channel, user, ts, "Added.", options).await,
+        // The undo button needs a reply to go on.
+        Ok(true) => {
+            let value = format!("{} {}", user, encode_word(&pos, insult));
+            let blocks = [Block::section("Added."), Block::actions(vec![Button::new("Undo", interactive::UNDO_ADD, &value)])];
+            let options = SendOptions { blocks: Some(blocks::to_value(&blocks)), ..options.clone() };
+            sink.post_private(channel, user, "Added.", &options).await
+        },
+        Ok(false) => sink.post_private(channel, user, "I already have that word!", options).await,
+        Err(e) => {
+            log::error!("Error adding word: {}", e);
+            let message = "I couldn't save that word, try again later.";
+            sink.post_private(channel, user, message, options).await
+        },
+    }
+}
+
+async fn handle_remove_word(
+    event: &MessageEvent, sink: &dyn MessageSink, pos: PartOfSpeech, word: &str,
+) -> LambdaResult<()> {
+    match forget_word(&pos, word, &event.user).await {
+        Ok(true) => (),
+        Ok(false) => return event.reply_privately(sink, "Never heard of it.").await,
+        Err(e) => {
+            log::error!("Error removing word: {}", e);
+            return event.reply_privately(sink, "I couldn't remove that word, try again later.").await;
+        },
+    }
+    log::info!("{} removed the {} {}", event.user, pos.name(), word);
+    event.acknowledge(sink, "Removed.").await
+}
+
+static SINGLE_WORD_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\w-]+$").unwrap());
+
+// With ADD_VIA_REACTION, reacting to a one word message with ADD_REACTION_EMOJI adds it as a noun,
+// or with ADD_ADJECTIVE_REACTION_EMOJI as an adjective.
+pub async fn handle_add_reaction(event: &ReactionEvent) -> LambdaResult<()> {
+    let reaction = event.reaction.split("::").next().unwrap_or_default();
+    let pos = match reaction_pos(reaction) {
+        Some(pos) => pos,
+        None => return Ok(()),
+    };
+    let (channel, ts) = match &event.item {
+        ReactionItem::Message { channel, ts } => (channel, ts),
+        ReactionItem::Other => return Ok(()),
+    };
+    if let Some(reason) = router::refusal(channel, &event.user, Feature::AddWord, reaction).await? {
+        return send_private(channel, &event.user, reason, &SendOptions::default()).await;
+    }
+    let text = match fetch_message_text(channel, ts).await? {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    match word_in_message(&text) {
+        Some(word) => {
+            let options = SendOptions::default();
+            add_word_for(&SlackSink, channel, &event.user, ts, &options, pos, word, None).await
+        },
+        None => {
+            let message = "I can only add messages that are a single word.";
+            send_private(channel, &event.user, message, &SendOptions::default()).await
+        },
+    }
+}
+
+// The part of speech a reaction adds messages as: ADD_REACTION_EMOJI for nouns and
+// ADD_ADJECTIVE_REACTION_EMOJI for adjectives.
+fn reaction_pos(reaction: &str) -> Option<PartOfSpeech> {
+    let noun_emoji = std::env::var("ADD_REACTION_EMOJI").unwrap_or_else(|_| "arrow_left".to_string());
+    let adjective_emoji = std::env::var("ADD_ADJECTIVE_REACTION_EMOJI").ok();
+    if reaction == noun_emoji {
+        Some(PartOfSpeech::Noun)
+    } else if adjective_emoji.as_deref() == Some(reaction) {
+        Some(PartOfSpeech::Adjective)
+    } else {
+        None
+    }
+}
+
+// The word a message is, if it's just the one.
+fn word_in_message(text: &str) -> Option<String> {
+    let word = text.trim();
+    SINGLE_WORD_RE.is_match(word).then(|| normalize_word(word))
+}
+
+// How many partner words "pairs with" lists.
+const PAIRS_SHOWN: usize = 5;
+
+async fn handle_pairs_with(event: &MessageEvent, sink: &dyn MessageSink, word: &str) -> LambdaResult<()> {
+    if !insult_log::enabled() {
+        return event.reply(sink, "I'm not keeping track of insults, so I couldn't tell you.").await;
+    }
+    let entries = insult_log::fetch_since(0).await?;
+    let pairs = digest::top(insult_log::pairings(&entries, word), PAIRS_SHOWN);
+    if pairs.is_empty() {
+        return event.reply(sink, &format!("No data yet on \"{}\".", word)).await;
+    }
+    let pairs: Vec<_> = pairs.iter().map(|(other, count)| format!("_{}_ ({})", other, count)).collect();
+    let message = format!("\"{}\" goes well with {}.", word, pairs.join(", "));
+    event.reply(sink, &message).await
+}
+
+// Takes back the caller's most recent addition, whether it was added by message, slash command, or vote.
+// Each addition can only be undone once.
+async fn handle_undo(event: &MessageEvent, sink: &dyn MessageSink) -> LambdaResult<()> {
+    let (pos, word) = match last_added::take(&event.user).await?.and_then(decode_word) {
+        Some(decoded) => decoded,
+        None => return event.reply_privately(sink, "You haven't added anything I can undo.").await,
+    };
+    if !forget_word(&pos, &word, &event.user).await? {
+        return event.reply_privately(sink, &format!("\"{}\" is already gone.", word)).await;
+    }
+    log::info!("{} undid adding the {} {}", event.user, pos.name(), word);
+    event.reply_privately(sink, &format!("Never mind, I forgot the {} \"{}\".", pos.name(), word)).await
+}
+
+async fn handle_remove_matching(
+    event: &MessageEvent, sink: &dyn MessageSink, pattern: &str, confirmed: bool,
+) -> LambdaResult<()> {
+    let cache = insult_factory().await?;
+    let removed = match remove_matching(&dynamo_client(), insult_table()?, cache, pattern, confirmed).await? {
+        Ok(removed) => removed,
+        Err(reason) => return event.reply_privately(sink, &reason).await,
+    };
+    let encoded: Vec<_> = removed.iter().map(|(pos, word)| encode_word(pos, word.clone())).collect();
+    audit::record("removed", &encoded, &event.user).await;
+    log::info!("{} removed {} words matching {}", event.user, removed.len(), pattern);
+    event.reply_privately(sink, &format!("Removed {} words.", removed.len())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamo::mock::{client_answering, recording_client};
+
+    #[test]
+    fn split_words_trims_and_lowercases() {
+        assert_eq!(split_words(" Clown, BUFFOON ,jester"), ["clown", "buffoon", "jester"]);
+        assert_eq!(split_words("clown,,"), ["clown", "", ""]);
+    }
+
+    #[tokio::test]
+    async fn adds_past_the_limit_are_refused() {
+        let counted = |hits: u64| json!({ "Attributes": { "hits": { "N": hits.to_string() } } });
+        let (client, _) = client_answering((add_limit() - 1..=add_limit() + 1).map(counted).collect());
+        let over = || over_add_limit_in(&client, "rate_limits".to_string(), "U1", 1);
+        assert!(!over().await.unwrap());
+        assert!(!over().await.unwrap());
+        assert!(over().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn each_word_in_a_list_counts_against_the_limit() {
+        let (client, requests) = recording_client(200, r#"{"Attributes": {"hits": {"N": "3"}}}"#);
+        assert!(!over_add_limit_in(&client, "rate_limits".to_string(), "U1", 3).await.unwrap());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["ExpressionAttributeValues"][":count"], json!({ "N": "3" }));
+        assert!(requests[0]["Key"]["key"]["S"].as_str().unwrap().starts_with("add:U1:"));
+    }
+
+    #[test]
+    fn reacting_to_a_one_word_message_adds_it() {
+        let event: ReactionEvent = serde_json::from_value(json!({
+            "type": "reaction_added",
+            "user": "U1",
+            "reaction": "arrow_left",
+            "item_user": "U2",
+            "item": { "type": "message", "channel": "C1", "ts": "1360782400.498405" },
+            "event_ts": "1360782804.083113",
+        })).unwrap();
+        assert_eq!(reaction_pos(&event.reaction), Some(PartOfSpeech::Noun));
+        assert!(matches!(&event.item, ReactionItem::Message { channel, ts } if channel == "C1" && ts == "1360782400.498405"));
+        assert_eq!(reaction_pos("tada"), None);
+
+        assert_eq!(word_in_message(" Buffoon\n").as_deref(), Some("buffoon"));
+        assert_eq!(word_in_message("two-faced").as_deref(), Some("two-faced"));
+        assert_eq!(word_in_message("what a buffoon"), None);
+        assert_eq!(word_in_message(""), None);
+    }
+
+    #[test]
+    fn remove_parses_like_add() {
+        let (syntax, args) = crate::COMMANDS.parse("remove noun clown").unwrap();
+        assert_eq!(syntax, "remove noun|adjective <word>");
+        assert_eq!((args.get(1), args.get(2)), ("noun", "clown"));
+        let (_, args) = crate::COMMANDS.parse("Remove Adjective  smelly").unwrap();
+        assert_eq!((args.get(1), args.get(2)), ("Adjective", "smelly"));
+        assert!(crate::COMMANDS.parse("remove verb run").is_none());
+    }
+
+    #[test]
+    fn words_are_validated() {
+        assert_eq!(validate_word(""), Err("Nice try wise guy."));
+        assert_eq!(validate_word(&"a".repeat(max_word_length() + 1)), Err("That's too long to be a word."));
+        assert_eq!(validate_word(" , - "), Err("That's not a word, that's punctuation."));
+        assert_eq!(validate_word("nincompoop"), Ok(()));
+        assert_eq!(validate_word(&"é".repeat(max_word_length())), Ok(()));
+    }
+
+    #[test]
+    fn long_lists_are_sorted_and_split() {
+        let words: Vec<_> = (0..500).rev().map(|i| format!("word{:03}", i)).chain(["Zany".to_string()]).collect();
+        let listed = listed_words(&words, "");
+        assert_eq!((listed[0].as_str(), listed[499].as_str(), listed[500].as_str()), ("word000", "word499", "Zany"));
+        assert_eq!(listed_words(&words, "WORD00").len(), 10);
+
+        let messages = chunk_words(&listed, 100);
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|message| message.chars().count() <= 100));
+        assert_eq!(messages.join(", "), listed.join(", "));
+        assert_eq!(chunk_words(&listed[..3], LIST_MESSAGE_CHARS), ["word000, word001, word002"]);
+    }
+}